@@ -1,11 +1,32 @@
 // client/src/main.rs
 use base64::{Engine as _, engine::general_purpose};
-use clap::{Parser, Subcommand};
-use merkle::{MerkleTree, ProofNode, sha256};
+use clap::{Parser, Subcommand, ValueEnum};
+use flate2::read::GzDecoder;
+use futures_util::{TryStreamExt as _, stream};
+use merkle::{
+    HashAlgorithm, MerkleTree, ProofBundle, RESERVED_NAMES, SortStrategy, TreeConfig, natural_cmp,
+};
 use reqwest::Client;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::io::ReaderStream;
+
+/// Buffer size for streaming file contents into an upload
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Header carrying the hex SHA-256 of all file contents, concatenated in name
+/// order, so the server can detect a corrupted upload before building the tree
+const UPLOAD_SHA256_HEADER: &str = "x-upload-sha256";
+
+/// Wait before the first retry of a failed request; doubled for each retry after
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Quiet period after the last change in a watched directory before it is
+/// uploaded again, so a burst of writes causes one upload
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 #[derive(Parser)]
 #[command(name = "merkle-client")]
@@ -15,15 +36,43 @@ struct Cli {
 
     #[arg(long, default_value = "http://localhost:3000")]
     server: String,
+
+    /// Times to retry a request after a connection error or 5xx response
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Upload {
-        #[arg(long)]
-        dir: PathBuf,
+        #[arg(long, required_unless_present = "tar")]
+        dir: Option<PathBuf>,
+        /// Upload the entries of this tar archive instead of a directory; the
+        /// archive is read in memory and left in place
+        #[arg(long, conflicts_with = "dir")]
+        tar: Option<PathBuf>,
         #[arg(long, default_value = "./merkle_root.hex")]
         root_file: PathBuf,
+        /// Hash algorithm for the local tree; must match the server's. A
+        /// non-default value is checked against the server before uploading
+        #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+        /// Order of the files in the local tree; must match the server's
+        #[arg(long, value_enum, default_value_t = Sort::Lexicographic)]
+        sort: Sort,
+        /// Save each file's proof here as `<name>.proof.json` for offline checks
+        #[arg(long)]
+        proofs_dir: Option<PathBuf>,
+        /// Print the root and leaf hashes without uploading or deleting anything
+        #[arg(long, conflicts_with = "proofs_dir")]
+        dry_run: bool,
+        /// Keep the local files after a successful upload instead of deleting them
+        #[arg(long)]
+        keep_local: bool,
+        /// Keep running and upload `dir` again whenever its files change;
+        /// implies `--keep-local`
+        #[arg(long, conflicts_with_all = ["tar", "dry_run"])]
+        watch: bool,
     },
     Request {
         #[arg(long)]
@@ -32,7 +81,218 @@ enum Commands {
         root_file: PathBuf,
         #[arg(long)]
         out: Option<PathBuf>,
+        /// Hash algorithm; detected from the root file if omitted, else sha256
+        #[arg(long, value_enum)]
+        algorithm: Option<Algorithm>,
+    },
+    /// Compare a local directory's root with the server's without uploading
+    Status {
+        #[arg(long)]
+        dir: PathBuf,
+        /// Hash algorithm for the local tree; must match the server's
+        #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+        /// Order of the files in the local tree; must match the server's
+        #[arg(long, value_enum, default_value_t = Sort::Lexicographic)]
+        sort: Sort,
+    },
+    /// List the files stored on the server
+    List {
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long)]
+        limit: Option<usize>,
     },
+    /// Download and verify every file the server lists into a directory
+    Download {
+        #[arg(long)]
+        dir: PathBuf,
+        #[arg(long, default_value = "./merkle_root.hex")]
+        root_file: PathBuf,
+        /// Hash algorithm; detected from the root file if omitted, else sha256
+        #[arg(long, value_enum)]
+        algorithm: Option<Algorithm>,
+    },
+    /// Verify a local directory against a saved root without contacting the server
+    Verify {
+        #[arg(long)]
+        dir: PathBuf,
+        #[arg(long, default_value = "./merkle_root.hex")]
+        root_file: PathBuf,
+        /// Hash algorithm; detected from the root file if omitted, else sha256
+        #[arg(long, value_enum)]
+        algorithm: Option<Algorithm>,
+        /// Order of the files in the tree; must match the one used on upload
+        #[arg(long, value_enum, default_value_t = Sort::Lexicographic)]
+        sort: Sort,
+    },
+    /// Write a standalone proof file for one file of a local directory
+    Prove {
+        #[arg(long)]
+        dir: PathBuf,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        out: PathBuf,
+        /// Hash algorithm for the local tree
+        #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+        algorithm: Algorithm,
+        /// Order of the files in the local tree
+        #[arg(long, value_enum, default_value_t = Sort::Lexicographic)]
+        sort: Sort,
+    },
+    /// Check a proof file written by `prove`, without the server or the directory
+    VerifyProof {
+        #[arg(long)]
+        proof: PathBuf,
+        /// Also check that this file's name and contents match the proven leaf
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Root the proof must resolve to, as hex or `<algorithm>:<hex>`
+        #[arg(long)]
+        expected_root: Option<String>,
+    },
+}
+
+/// CLI names for `HashAlgorithm`, also used as the root file prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Algorithm {
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl From<Algorithm> for HashAlgorithm {
+    fn from(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => HashAlgorithm::Sha256,
+            Algorithm::Sha512 => HashAlgorithm::Sha512,
+            Algorithm::Blake3 => HashAlgorithm::Blake3,
+        }
+    }
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// CLI names for `SortStrategy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Sort {
+    Lexicographic,
+    Numeric,
+    ModifiedTime,
+}
+
+impl From<Sort> for SortStrategy {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Lexicographic => SortStrategy::Lexicographic,
+            Sort::Numeric => SortStrategy::Numeric,
+            Sort::ModifiedTime => SortStrategy::ModifiedTime,
+        }
+    }
+}
+
+/// Files to upload, in leaf order.
+enum LocalFiles {
+    /// Regular files in a directory, streamed from disk and deleted once the
+    /// roots match
+    Dir(Vec<(String, PathBuf)>),
+    /// Entries of a tar archive, held in memory; the archive is kept
+    Tar(Vec<(String, Vec<u8>)>),
+}
+
+impl LocalFiles {
+    fn names(&self) -> Vec<String> {
+        match self {
+            LocalFiles::Dir(files) => files.iter().map(|(name, _)| name.clone()).collect(),
+            LocalFiles::Tar(entries) => entries.iter().map(|(name, _)| name.clone()).collect(),
+        }
+    }
+
+    /// The local tree over the files, binding each name to its contents.
+    fn tree(&self, config: TreeConfig) -> anyhow::Result<MerkleTree> {
+        Ok(match self {
+            LocalFiles::Dir(files) => {
+                MerkleTree::from_named_file_paths_streaming_with_config(files, config)?
+            }
+            LocalFiles::Tar(entries) => MerkleTree::from_named_files_with_config(entries, config)?,
+        })
+    }
+
+    /// SHA-256 over the contents of all files concatenated in name order,
+    /// independent of the sort strategy.
+    ///
+    /// Directory files are read in `UPLOAD_CHUNK_SIZE` chunks, never whole.
+    fn body_digest(&self) -> anyhow::Result<Vec<u8>> {
+        let mut hasher = Sha256::new();
+        match self {
+            LocalFiles::Dir(files) => {
+                let mut sorted: Vec<_> = files.iter().collect();
+                sorted.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+                for (_, path) in sorted {
+                    let mut file = fs::File::open(path)?;
+                    loop {
+                        let n = file.read(&mut buf)?;
+                        if n == 0 {
+                            break;
+                        }
+                        hasher.update(&buf[..n]);
+                    }
+                }
+            }
+            LocalFiles::Tar(entries) => {
+                let mut sorted: Vec<_> = entries.iter().collect();
+                sorted.sort_by(|a, b| a.0.cmp(&b.0));
+                for (_, contents) in sorted {
+                    hasher.update(contents);
+                }
+            }
+        }
+        Ok(hasher.finalize().to_vec())
+    }
+
+    /// Multipart form with one part per file.
+    fn form(&self) -> anyhow::Result<reqwest::multipart::Form> {
+        let mut form = reqwest::multipart::Form::new();
+        match self {
+            LocalFiles::Dir(files) => {
+                for (name, path) in files {
+                    form = form.part(name.clone(), file_part(name, path.clone())?);
+                }
+            }
+            LocalFiles::Tar(entries) => {
+                for (name, contents) in entries {
+                    let part =
+                        reqwest::multipart::Part::bytes(contents.clone()).file_name(name.clone());
+                    form = form.part(name.clone(), part);
+                }
+            }
+        }
+        Ok(form)
+    }
+}
+
+/// Settings of one `upload` run besides the server and directory.
+#[derive(Clone)]
+struct UploadOptions<'a> {
+    root_file: PathBuf,
+    algorithm: Algorithm,
+    sort: SortStrategy,
+    /// Where to save per-file proofs, if anywhere
+    proofs_dir: Option<&'a Path>,
+    /// Only build and print the local tree
+    dry_run: bool,
+    /// Leave the local files in place after the roots match
+    keep_local: bool,
 }
 
 #[derive(serde::Deserialize)]
@@ -41,19 +301,172 @@ struct UploadResp {
     files_count: usize,
 }
 
+/// `GET /root?format=json`
+#[derive(serde::Deserialize)]
+struct RootResp {
+    root: Option<String>,
+    leaf_count: usize,
+    algorithm: HashAlgorithm,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ListResp {
+    files: Vec<String>,
+    root: Option<String>,
+    count: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct FileResp {
+    file_bytes: String, // base64
+    /// Set when `file_bytes` is gzipped
+    #[serde(default)]
+    compressed: bool,
+    /// MIME type detected by the server; absent from older servers
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(flatten)]
+    bundle: ProofBundle,
+}
+
+/// A single file's proof with everything needed to check it, as written by
+/// `prove`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ProofFile {
+    name: String,
+    algorithm: HashAlgorithm,
+    /// Number of files in the tree the proof was generated from
+    leaf_count: usize,
+    #[serde(flatten)]
+    bundle: ProofBundle, // leaf_hash (hex), proof, root (hex)
+}
+
+/// How often to retry requests that fail transiently, with exponential backoff.
+#[derive(Clone, Copy, Debug)]
+struct RetryPolicy {
+    /// Retries after the first attempt
+    retries: u32,
+    /// Wait before the first retry
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn new(retries: u32) -> Self {
+        RetryPolicy {
+            retries,
+            base_delay: RETRY_BASE_DELAY,
+        }
+    }
+}
+
+/// The server turned an upload away because the previous one from this
+/// client was too recent.
+#[derive(Debug)]
+struct RateLimited {
+    /// Wait the server asked for in `Retry-After`, or a second if it gave none
+    retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "upload rate limited by the server; retry after {:?}",
+            self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// A downloaded file whose proof checked out.
+#[derive(Debug)]
+struct VerifiedFile {
+    bytes: Vec<u8>,
+    content_type: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    let retry = RetryPolicy::new(cli.retries);
     match cli.cmd {
-        Commands::Upload { dir, root_file } => {
-            upload_dir(&cli.server, dir, root_file).await?;
+        Commands::Upload {
+            dir,
+            tar,
+            root_file,
+            algorithm,
+            sort,
+            proofs_dir,
+            dry_run,
+            keep_local,
+            watch,
+        } => {
+            let options = UploadOptions {
+                root_file,
+                algorithm,
+                sort: sort.into(),
+                proofs_dir: proofs_dir.as_deref(),
+                dry_run,
+                keep_local: keep_local || watch,
+            };
+            match (dir, tar) {
+                (_, Some(archive)) => upload_tar(&cli.server, retry, &archive, options).await?,
+                (Some(dir), None) if watch => watch_dir(&cli.server, retry, dir, options).await?,
+                (Some(dir), None) => upload_dir(&cli.server, retry, dir, options).await?,
+                (None, None) => anyhow::bail!("one of --dir or --tar is required"),
+            }
         }
         Commands::Request {
             name,
             root_file,
             out,
+            algorithm,
         } => {
-            request_file(&cli.server, &name, root_file, out).await?;
+            request_file(&cli.server, retry, &name, root_file, out, algorithm).await?;
+        }
+        Commands::Status {
+            dir,
+            algorithm,
+            sort,
+        } => {
+            if !status_dir(&cli.server, retry, &dir, algorithm, sort.into()).await? {
+                anyhow::bail!("local root does not match the server's");
+            }
+        }
+        Commands::List { offset, limit } => {
+            list_files(&cli.server, retry, offset, limit).await?;
+        }
+        Commands::Download {
+            dir,
+            root_file,
+            algorithm,
+        } => {
+            download_all(&cli.server, retry, &dir, &root_file, algorithm).await?;
+        }
+        Commands::Verify {
+            dir,
+            root_file,
+            algorithm,
+            sort,
+        } => {
+            verify_dir(&dir, &root_file, algorithm, sort.into())?;
+        }
+        Commands::Prove {
+            dir,
+            name,
+            out,
+            algorithm,
+            sort,
+        } => {
+            prove_file(&dir, &name, &out, algorithm, sort.into())?;
+        }
+        Commands::VerifyProof {
+            proof,
+            file,
+            expected_root,
+        } => {
+            verify_proof_file(&proof, file.as_deref(), expected_root.as_deref())?;
         }
     }
     Ok(())
@@ -66,7 +479,7 @@ fn validate_filename(name: &str) -> anyhow::Result<()> {
     if name.contains("..") || name.contains('/') || name.contains('\\') {
         anyhow::bail!("invalid filename '{}': path traversal not allowed", name);
     }
-    if name == "manifest.json" || name == "root.hex" {
+    if RESERVED_NAMES.contains(&name) {
         anyhow::bail!("invalid filename '{}': reserved name", name);
     }
     if name.chars().any(|c| c.is_control() || c == '\0') {
@@ -78,13 +491,101 @@ fn validate_filename(name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn upload_dir(server: &str, dir: PathBuf, root_file: PathBuf) -> anyhow::Result<()> {
-    // 1. Read and sort local files
-    let mut entries: Vec<_> = fs::read_dir(&dir)?
+/// Contents of a saved root file: `<algorithm>:<hex root>`.
+fn format_root_file(algorithm: Algorithm, root: &[u8]) -> String {
+    format!("{}:{}", algorithm.name(), hex::encode(root))
+}
+
+/// Parse a saved root file, returning the algorithm prefix (if any) and the root bytes.
+///
+/// Files written before the prefix was introduced hold bare hex.
+fn parse_root_file(contents: &str) -> anyhow::Result<(Option<Algorithm>, Vec<u8>)> {
+    let contents = contents.trim();
+    let (algorithm, root_hex) = match contents.split_once(':') {
+        Some((name, root_hex)) => {
+            let algorithm = Algorithm::from_str(name, true)
+                .map_err(|_| anyhow::anyhow!("unknown algorithm '{}' in root file", name))?;
+            (Some(algorithm), root_hex)
+        }
+        None => (None, contents),
+    };
+    Ok((algorithm, merkle::root_from_reader(root_hex.as_bytes())?))
+}
+
+/// Read a saved root file and settle which algorithm to use with it.
+///
+/// An explicit `--algorithm` must agree with the file's prefix; with neither,
+/// SHA-256 is assumed.
+fn read_root_file(
+    root_file: &Path,
+    requested: Option<Algorithm>,
+) -> anyhow::Result<(Algorithm, Vec<u8>)> {
+    let (algorithm, mut roots) = read_root_candidates(root_file, requested)?;
+    if roots.len() != 1 {
+        anyhow::bail!(
+            "expected a single root in {:?}, found {}",
+            root_file,
+            roots.len()
+        );
+    }
+    Ok((algorithm, roots.remove(0)))
+}
+
+/// Read a root file holding one or more candidate roots, one per line, as
+/// kept during a root rotation.
+///
+/// Every prefixed line must name the same algorithm, which must agree with
+/// an explicit `--algorithm` as in `read_root_file`.
+fn read_root_candidates(
+    root_file: &Path,
+    requested: Option<Algorithm>,
+) -> anyhow::Result<(Algorithm, Vec<Vec<u8>>)> {
+    let mut algorithm = requested;
+    let mut roots = Vec::new();
+    for line in fs::read_to_string(root_file)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (saved, root) = parse_root_file(line)?;
+        algorithm = match (algorithm, saved) {
+            (Some(chosen), Some(saved)) if chosen != saved => anyhow::bail!(
+                "{} root saved in {:?} does not match {}",
+                saved.name(),
+                root_file,
+                chosen.name()
+            ),
+            (chosen, saved) => chosen.or(saved),
+        };
+        roots.push(root);
+    }
+    if roots.is_empty() {
+        anyhow::bail!("no root found in {:?}", root_file);
+    }
+    Ok((algorithm.unwrap_or(Algorithm::Sha256), roots))
+}
+
+/// Read all files in `dir` in `sort` order, validating each filename.
+///
+/// Returns `(name, contents)` pairs in leaf order, ready for
+/// `MerkleTree::from_named_files`.
+fn read_dir_sorted(dir: &Path, sort: SortStrategy) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let entries = list_dir_sorted(dir, sort)?;
+    let mut files: Vec<(String, Vec<u8>)> = Vec::with_capacity(entries.len());
+    for (name, path) in entries {
+        let data = fs::read(path)?;
+        files.push((name, data));
+    }
+
+    Ok(files)
+}
+
+/// Validated `(name, path)` pairs of the regular files directly inside
+/// `dir`, in `sort` order.
+fn list_dir_sorted(dir: &Path, sort: SortStrategy) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?
         .filter_map(|r| r.ok())
         .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.file_name().into_string().ok())
-        .filter_map(|s| s)
+        .filter_map(|e| e.file_name().into_string().ok())
         .collect();
     entries.sort();
 
@@ -92,39 +593,345 @@ async fn upload_dir(server: &str, dir: PathBuf, root_file: PathBuf) -> anyhow::R
         anyhow::bail!("No files found in directory");
     }
 
-    // Validate all filenames before uploading
+    // Validate all filenames before using them
     for name in &entries {
         validate_filename(name)?;
     }
 
-    let mut files_bytes: Vec<Vec<u8>> = Vec::with_capacity(entries.len());
-    for name in &entries {
-        let p = dir.join(name);
-        let data = fs::read(&p)?;
-        files_bytes.push(data);
+    let mut files: Vec<(String, PathBuf)> = entries
+        .into_iter()
+        .map(|name| {
+            let path = dir.join(&name);
+            (name, path)
+        })
+        .collect();
+    sort.sort_files(&mut files)?;
+    Ok(files)
+}
+
+/// Read the regular-file entries of the tar archive at `archive` into memory,
+/// in `sort` order, validating each entry path as a filename.
+///
+/// Directory entries, links and other special entries are rejected, as are
+/// paths that occur twice. `ModifiedTime` orders by the mtime recorded in
+/// each entry header.
+fn read_tar_sorted(archive: &Path, sort: SortStrategy) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut entries: Vec<(String, u64, Vec<u8>)> = Vec::new();
+    for entry in tar::Archive::new(fs::File::open(archive)?).entries()? {
+        let mut entry = entry?;
+        let name = entry
+            .path()?
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("entry path in {:?} is not valid UTF-8", archive))?
+            .to_string();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            anyhow::bail!(
+                "directory entry '{}' in {:?} is not supported",
+                name,
+                archive
+            );
+        }
+        if !entry_type.is_file() {
+            anyhow::bail!("entry '{}' in {:?} is not a regular file", name, archive);
+        }
+        validate_filename(&name)?;
+        if entries.iter().any(|(existing, _, _)| *existing == name) {
+            anyhow::bail!("entry '{}' appears twice in {:?}", name, archive);
+        }
+
+        let mtime = entry.header().mtime()?;
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut contents)?;
+        entries.push((name, mtime, contents));
+    }
+
+    if entries.is_empty() {
+        anyhow::bail!("No files found in archive");
+    }
+
+    entries.sort_by(|a, b| match sort {
+        SortStrategy::Lexicographic => a.0.cmp(&b.0),
+        SortStrategy::Numeric => natural_cmp(&a.0, &b.0),
+        SortStrategy::ModifiedTime => a.1.cmp(&b.1).then(a.0.cmp(&b.0)),
+    });
+    Ok(entries
+        .into_iter()
+        .map(|(name, _, contents)| (name, contents))
+        .collect())
+}
+
+/// Multipart part that streams `path` from disk in `UPLOAD_CHUNK_SIZE` chunks.
+///
+/// The file is opened only when the part is sent, so one file is open and
+/// at most one chunk is buffered at a time.
+fn file_part(name: &str, path: PathBuf) -> anyhow::Result<reqwest::multipart::Part> {
+    let len = fs::metadata(&path)?.len();
+    let contents = stream::once(tokio::fs::File::open(path))
+        .map_ok(|file| ReaderStream::with_capacity(file, UPLOAD_CHUNK_SIZE))
+        .try_flatten();
+    Ok(
+        reqwest::multipart::Part::stream_with_length(reqwest::Body::wrap_stream(contents), len)
+            .file_name(name.to_string()),
+    )
+}
+
+/// Send the request built by `request`, retrying per `retry` on connection
+/// errors, timeouts and 5xx responses.
+///
+/// The request is rebuilt for every attempt, since a streamed body can only
+/// be sent once. Once retries run out the last response is returned as is,
+/// so callers report its status as usual; 4xx responses are never retried.
+async fn send_with_retry<F>(retry: RetryPolicy, mut request: F) -> anyhow::Result<reqwest::Response>
+where
+    F: FnMut() -> anyhow::Result<reqwest::RequestBuilder>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = request()?.send().await;
+        let transient = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !transient || attempt >= retry.retries {
+            return Ok(result?);
+        }
+
+        let delay = retry
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt));
+        attempt += 1;
+        match &result {
+            Ok(resp) => eprintln!(
+                "server returned {}; retrying in {:?} ({}/{})",
+                resp.status(),
+                delay,
+                attempt,
+                retry.retries
+            ),
+            Err(e) => eprintln!(
+                "request failed: {}; retrying in {:?} ({}/{})",
+                e, delay, attempt, retry.retries
+            ),
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Upload every file in `dir`, check the server's root against the local one
+/// and, on a match, save the root and delete the local files.
+///
+/// With `dry_run` only the local tree is built and printed; the server is
+/// not contacted and nothing is written or deleted.
+async fn upload_dir(
+    server: &str,
+    retry: RetryPolicy,
+    dir: PathBuf,
+    options: UploadOptions<'_>,
+) -> anyhow::Result<()> {
+    // contents are streamed from disk, never held
+    let files = list_dir_sorted(&dir, options.sort)?;
+    upload_files(server, retry, LocalFiles::Dir(files), options).await
+}
+
+/// Upload the entries of the tar archive at `archive` like `upload_dir`,
+/// without extracting it; the archive itself is never deleted.
+async fn upload_tar(
+    server: &str,
+    retry: RetryPolicy,
+    archive: &Path,
+    options: UploadOptions<'_>,
+) -> anyhow::Result<()> {
+    let entries = read_tar_sorted(archive, options.sort)?;
+    upload_files(server, retry, LocalFiles::Tar(entries), options).await
+}
+
+/// Upload `dir` now and again after every burst of changes to it, until
+/// interrupted.
+///
+/// Every upload replaces the server's whole store, so the local files are
+/// always kept. Failed cycles are reported and retried on the next change.
+/// The root file and proofs directory must live outside `dir`, since writing
+/// them after each upload would otherwise count as another change.
+async fn watch_dir(
+    server: &str,
+    retry: RetryPolicy,
+    dir: PathBuf,
+    options: UploadOptions<'_>,
+) -> anyhow::Result<()> {
+    for output in std::iter::once(options.root_file.as_path()).chain(options.proofs_dir) {
+        if is_within(&dir, output)? {
+            anyhow::bail!(
+                "{:?} is inside the watched directory {:?}; writing it after each upload \
+                 would trigger another one",
+                output,
+                dir
+            );
+        }
+    }
+
+    let (changes, changed) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if event.kind.is_access() => {}
+            Ok(_) => {
+                let _ = changes.send(());
+            }
+            Err(e) => eprintln!("watch error: {}", e),
+        })?;
+    notify::Watcher::watch(&mut watcher, &dir, notify::RecursiveMode::NonRecursive)?;
+    println!("Watching {:?} for changes", dir);
+    watch_uploads(server, retry, &dir, options, changed, WATCH_DEBOUNCE).await
+}
+
+/// Upload `dir` once, then once more for each burst of `changed` signals that
+/// is followed by `debounce` without another; returns when `changed` closes.
+///
+/// An upload turned away by the server's rate limit is retried as soon as
+/// the server allows, without waiting for another change, so the last state
+/// of a burst is never left unsynced.
+async fn watch_uploads(
+    server: &str,
+    retry: RetryPolicy,
+    dir: &Path,
+    options: UploadOptions<'_>,
+    mut changed: tokio::sync::mpsc::UnboundedReceiver<()>,
+    debounce: Duration,
+) -> anyhow::Result<()> {
+    loop {
+        // changes signalled so far are covered by the upload about to start
+        while changed.try_recv().is_ok() {}
+
+        match upload_dir(server, retry, dir.to_path_buf(), options.clone()).await {
+            Ok(()) => println!("Synced {:?}; waiting for changes", dir),
+            Err(e) => match e.downcast_ref::<RateLimited>() {
+                Some(limited) => {
+                    eprintln!("{}", limited);
+                    tokio::time::sleep(limited.retry_after).await;
+                    continue;
+                }
+                // the removal is itself a change, so the next cycle picks it up
+                None if vanished_mid_scan(&e) => {
+                    eprintln!("a file was removed while uploading; retrying after the next change")
+                }
+                None => eprintln!("upload failed: {:#}; retrying after the next change", e),
+            },
+        }
+
+        if changed.recv().await.is_none() {
+            return Ok(());
+        }
+        while let Ok(Some(())) = tokio::time::timeout(debounce, changed.recv()).await {}
     }
+}
+
+/// Whether `path` is `dir` or lies somewhere below it. Symlinks and relative
+/// parts are resolved for whatever prefix of `path` already exists.
+fn is_within(dir: &Path, path: &Path) -> anyhow::Result<bool> {
+    let dir = fs::canonicalize(dir)?;
+    let path = std::path::absolute(path)?;
+    let mut existing = path.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => break,
+        }
+    }
+    let mut resolved = fs::canonicalize(existing)?;
+    resolved.extend(missing.iter().rev());
+    Ok(resolved.starts_with(&dir))
+}
+
+/// Whether `e` comes from a listed file that no longer existed when it was
+/// read, as when a file is deleted between listing and hashing or sending.
+fn vanished_mid_scan(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == std::io::ErrorKind::NotFound)
+    })
+}
 
-    // 2. Build local Merkle tree and compute root
-    let tree = MerkleTree::from_bytes_vec(&files_bytes)?;
+/// Upload `files`, compare roots and, on a match, save the root (and proofs)
+/// and delete files that came from a directory unless `keep_local` is set.
+async fn upload_files(
+    server: &str,
+    retry: RetryPolicy,
+    files: LocalFiles,
+    options: UploadOptions<'_>,
+) -> anyhow::Result<()> {
+    let UploadOptions {
+        root_file,
+        algorithm,
+        sort: _,
+        proofs_dir,
+        dry_run,
+        keep_local,
+    } = options;
+    let names = files.names();
+
+    // 1. Build local Merkle tree (leaves bind names to contents) and compute root
+    let config = TreeConfig::with_algorithm(algorithm.into());
+    let tree = files.tree(config)?;
     let local_root_hex = hex::encode(tree.root_hash_ref()?);
     println!("Local root: {}", local_root_hex);
 
-    // 3. Build multipart form with all files
+    if dry_run {
+        for (name, leaf) in names.iter().zip(tree.get_leaves()) {
+            println!("{}  {}", hex::encode(leaf), name);
+        }
+        println!(
+            "Dry run: {} files would be uploaded; nothing sent or deleted",
+            names.len()
+        );
+        return Ok(());
+    }
+
+    // The server keeps its own algorithm, and a mismatch would only show
+    // after the upload had replaced its store, so check it first
+    if HashAlgorithm::from(algorithm) != HashAlgorithm::default() {
+        let remote = fetch_server_root(server, retry).await?;
+        if remote.algorithm != HashAlgorithm::from(algorithm) {
+            anyhow::bail!(
+                "the server hashes with {:?}, not {}; nothing was uploaded",
+                remote.algorithm,
+                algorithm.name()
+            );
+        }
+    }
+
+    // 2. Build multipart form with all files, plus a checksum of their contents
     let client = Client::new();
+    let body_digest_hex = hex::encode(files.body_digest()?);
     let url = format!("{}/upload", server.trim_end_matches('/'));
 
-    let mut form = reqwest::multipart::Form::new();
-    for (i, name) in entries.iter().enumerate() {
-        let bytes = files_bytes[i].clone();
-        let part = reqwest::multipart::Part::bytes(bytes).file_name(name.clone());
-        form = form.part(name.clone(), part);
+    for name in &names {
         println!("Adding {} to upload", name);
     }
 
-    // 4. Send upload request
-    println!("Uploading {} files...", entries.len());
-    let resp = client.post(&url).multipart(form).send().await?;
+    // 3. Send upload request; parts may stream from disk, so each attempt
+    // needs a fresh form
+    println!("Uploading {} files...", names.len());
+    let resp = send_with_retry(retry, || {
+        Ok(client
+            .post(&url)
+            .header(UPLOAD_SHA256_HEADER, &body_digest_hex)
+            .multipart(files.form()?))
+    })
+    .await?;
 
+    if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map_or(Duration::from_secs(1), Duration::from_secs);
+        return Err(RateLimited { retry_after }.into());
+    }
     if !resp.status().is_success() {
         anyhow::bail!("upload failed: {}", resp.text().await?);
     }
@@ -135,7 +942,7 @@ async fn upload_dir(server: &str, dir: PathBuf, root_file: PathBuf) -> anyhow::R
         upload_obj.files_count, upload_obj.root
     );
 
-    // 5. Compare local root vs server root
+    // 4. Compare local root vs server root
     if upload_obj.root != local_root_hex {
         anyhow::bail!(
             "root mismatch: local {} vs server {}",
@@ -146,68 +953,1327 @@ async fn upload_dir(server: &str, dir: PathBuf, root_file: PathBuf) -> anyhow::R
 
     println!("Root hashes match!");
 
-    // 6. On match, persist local root and delete local files
-    fs::write(&root_file, local_root_hex.as_bytes())?;
-    for name in &entries {
-        let p = dir.join(name);
-        fs::remove_file(p)?;
-        println!("deleted local {}", name);
+    // 5. On match, persist local root (and proofs, if asked) and delete local
+    // files unless asked to keep them
+    fs::write(
+        &root_file,
+        format_root_file(algorithm, tree.root_hash_ref()?).as_bytes(),
+    )?;
+    if let Some(proofs_dir) = proofs_dir {
+        save_proofs(&tree, &names, proofs_dir)?;
+    }
+    match &files {
+        LocalFiles::Dir(_) if keep_local => println!("kept {} local files", names.len()),
+        LocalFiles::Dir(files) => {
+            for (name, path) in files {
+                fs::remove_file(path)?;
+                println!("deleted local {}", name);
+            }
+        }
+        LocalFiles::Tar(_) => {}
     }
 
     println!("Upload complete; local root saved at {:?}", root_file);
     Ok(())
 }
 
+/// Write the proof of every file as `<name>.proof.json` into `proofs_dir`.
+///
+/// Proofs come from the locally built tree, so they can be checked later
+/// against the saved root without the server or the original files.
+fn save_proofs(tree: &MerkleTree, names: &[String], proofs_dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(proofs_dir)?;
+    for (index, name) in names.iter().enumerate() {
+        let bundle = ProofBundle::from_tree(tree, index)?;
+        let proof_path = proofs_dir.join(format!("{}.proof.json", name));
+        fs::write(&proof_path, serde_json::to_string_pretty(&bundle)?)?;
+    }
+    println!("Saved {} proofs to {:?}", names.len(), proofs_dir);
+    Ok(())
+}
+
 async fn request_file(
     server: &str,
+    retry: RetryPolicy,
     name: &str,
     root_file: PathBuf,
     out: Option<PathBuf>,
+    algorithm: Option<Algorithm>,
 ) -> anyhow::Result<()> {
     // validate filename
     validate_filename(name)?;
 
-    // read local saved root
-    let saved_root = fs::read_to_string(&root_file)?;
-    let saved_root_bytes = hex::decode(saved_root.trim())?;
+    // read local saved roots; during a rotation the file may hold several
+    let (algorithm, saved_roots) = read_root_candidates(&root_file, algorithm)?;
+    let saved_roots: Vec<&[u8]> = saved_roots.iter().map(Vec::as_slice).collect();
+
+    let file =
+        fetch_and_verify(&Client::new(), server, retry, name, algorithm, &saved_roots).await?;
+    println!("File verified against local saved root.");
+
+    // write file only if verification succeeded
+    let out_path = out.unwrap_or_else(|| default_out_path(name, file.content_type.as_deref()));
+    let mut f = fs::File::create(&out_path)?;
+    f.write_all(&file.bytes)?;
+    println!("Wrote file to {:?}", out_path);
+
+    Ok(())
+}
+
+/// Where to write `name` when no `--out` is given: the name itself, plus an
+/// extension matching `content_type` if the name has none.
+fn default_out_path(name: &str, content_type: Option<&str>) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.extension().is_some() {
+        return path;
+    }
+    let extension = match content_type {
+        Some("image/png") => "png",
+        Some("image/jpeg") => "jpg",
+        Some("image/gif") => "gif",
+        Some("application/pdf") => "pdf",
+        Some("application/zip") => "zip",
+        Some("application/gzip") => "gz",
+        Some("application/json") => "json",
+        Some("text/plain") => "txt",
+        _ => return path,
+    };
+    path.with_extension(extension)
+}
+
+/// Fetch `name` from the server and verify its proof against any of
+/// `saved_roots`.
+///
+/// With a single saved root the server is asked to refuse the request if its
+/// own root differs. Returns the (decompressed) file contents only if
+/// verification succeeds; nothing is written to disk, so callers decide what
+/// to do with the bytes.
+async fn fetch_and_verify(
+    client: &Client,
+    server: &str,
+    retry: RetryPolicy,
+    name: &str,
+    algorithm: Algorithm,
+    saved_roots: &[&[u8]],
+) -> anyhow::Result<VerifiedFile> {
+    let hash_algorithm = HashAlgorithm::from(algorithm);
 
     // fetch from server
-    let url = format!(
+    let mut url = format!(
         "{}/file/{}",
         server.trim_end_matches('/'),
         urlencoding::encode(name)
     );
-    let resp = reqwest::get(&url).await?;
+    if let [saved_root] = saved_roots {
+        url.push_str(&format!("?expected_root={}", hex::encode(saved_root)));
+    }
+    let resp = send_with_retry(retry, || {
+        Ok(client
+            .get(&url)
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip"))
+    })
+    .await?;
+    if resp.status() == reqwest::StatusCode::CONFLICT {
+        let body = resp.text().await.unwrap_or_default();
+        anyhow::bail!(
+            "Server root differs from local saved root; the server's files have changed: {}",
+            body
+        );
+    }
     if !resp.status().is_success() {
         anyhow::bail!("server returned error: {}", resp.status());
     }
-    let json: serde_json::Value = resp.json().await?;
-    let file_b64 = json["file_bytes"]
-        .as_str()
-        .ok_or_else(|| anyhow::anyhow!("missing file_bytes"))?;
-    let proof_val = &json["proof"];
-    let server_root_hex = json["root"].as_str().unwrap_or_default();
+    let file_resp: FileResp = resp.json().await?;
+    let mut file_bytes = general_purpose::STANDARD.decode(&file_resp.file_bytes)?;
+    if file_resp.compressed {
+        // hash the original contents, which is what the proof covers
+        let mut decompressed = Vec::new();
+        GzDecoder::new(file_bytes.as_slice()).read_to_end(&mut decompressed)?;
+        file_bytes = decompressed;
+    }
+    let leaf_hash = TreeConfig::with_algorithm(hash_algorithm).hash_named_leaf(name, &file_bytes);
+    let bundle = file_resp.bundle;
 
-    let file_bytes = general_purpose::STANDARD.decode(file_b64)?;
-    let proof: Vec<ProofNode> = serde_json::from_value(proof_val.clone())?;
-    let leaf_hash = sha256(&file_bytes);
+    if leaf_hash != bundle.leaf_hash {
+        anyhow::bail!(
+            "Verification FAILED: file name and bytes do not match the server's leaf hash. File rejected."
+        );
+    }
 
-    // verify using local saved root
-    let ok_local = MerkleTree::verify_proof(&leaf_hash, &proof, &saved_root_bytes);
-    if !ok_local {
+    // verify using local saved roots
+    let ok_local = MerkleTree::verify_proof_any_with_config(
+        &leaf_hash,
+        &bundle.proof,
+        saved_roots,
+        &TreeConfig::with_algorithm(hash_algorithm),
+    );
+    if ok_local.is_none() {
         anyhow::bail!(
             "Verification FAILED: proof does not match local saved root. Server root: {}. File rejected.",
-            server_root_hex
+            hex::encode(&bundle.root)
         );
     }
 
-    println!("File verified against local saved root.");
+    Ok(VerifiedFile {
+        bytes: file_bytes,
+        content_type: file_resp.content_type,
+    })
+}
 
-    // write file only if verification succeeded
-    let out_path = out.unwrap_or_else(|| PathBuf::from(name));
-    let mut f = fs::File::create(&out_path)?;
-    f.write_all(&file_bytes)?;
-    println!("Wrote file to {:?}", out_path);
+/// Restore every file the server lists into `dir`, verifying each one first.
+///
+/// Files whose proof fails are reported and skipped; the command fails if
+/// any file could not be verified.
+async fn download_all(
+    server: &str,
+    retry: RetryPolicy,
+    dir: &Path,
+    root_file: &Path,
+    algorithm: Option<Algorithm>,
+) -> anyhow::Result<()> {
+    let (algorithm, saved_root_bytes) = read_root_file(root_file, algorithm)?;
+    let client = Client::new();
+    let list = fetch_list(&client, server, retry, 0, None).await?;
+    fs::create_dir_all(dir)?;
+
+    let mut succeeded = 0;
+    let mut failed = 0;
+    for name in &list.files {
+        // names come from the server, so check them before touching the disk
+        let result = match validate_filename(name) {
+            Ok(()) => {
+                fetch_and_verify(
+                    &client,
+                    server,
+                    retry,
+                    name,
+                    algorithm,
+                    &[&saved_root_bytes],
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(file) => {
+                fs::write(dir.join(name), &file.bytes)?;
+                println!("verified {}", name);
+                succeeded += 1;
+            }
+            Err(e) => {
+                eprintln!("FAILED {}: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "Download complete: {} verified, {} failed, written to {:?}",
+        succeeded, failed, dir
+    );
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} files failed verification",
+            failed,
+            list.files.len()
+        );
+    }
+    Ok(())
+}
+
+/// Fetch one page of the server's file listing.
+async fn fetch_list(
+    client: &Client,
+    server: &str,
+    retry: RetryPolicy,
+    offset: usize,
+    limit: Option<usize>,
+) -> anyhow::Result<ListResp> {
+    let mut url = format!("{}/list?offset={}", server.trim_end_matches('/'), offset);
+    if let Some(limit) = limit {
+        url.push_str(&format!("&limit={}", limit));
+    }
+    let resp = send_with_retry(retry, || Ok(client.get(&url))).await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("server returned error: {}", resp.status());
+    }
+    Ok(resp.json().await?)
+}
+
+/// Print one page of the server's file listing and its root.
+async fn list_files(
+    server: &str,
+    retry: RetryPolicy,
+    offset: usize,
+    limit: Option<usize>,
+) -> anyhow::Result<()> {
+    let list = fetch_list(&Client::new(), server, retry, offset, limit).await?;
+
+    for name in &list.files {
+        println!("{}", name);
+    }
+    println!(
+        "Showing {} of {} files (offset {})",
+        list.files.len(),
+        list.count,
+        offset
+    );
+    match list.root {
+        Some(root) => println!("Server root: {}", root),
+        None => println!("Server root: none (no files stored)"),
+    }
+    Ok(())
+}
+
+/// Build the tree over `dir` and compare its root with the server's current
+/// root, printing both; returns whether they match.
+///
+/// Read-only on both sides: nothing is uploaded, written or deleted.
+async fn status_dir(
+    server: &str,
+    retry: RetryPolicy,
+    dir: &Path,
+    algorithm: Algorithm,
+    sort: SortStrategy,
+) -> anyhow::Result<bool> {
+    let files = list_dir_sorted(dir, sort)?;
+    let config = TreeConfig::with_algorithm(algorithm.into());
+    let tree = MerkleTree::from_named_file_paths_streaming_with_config(&files, config)?;
+    let local_root_hex = hex::encode(tree.root_hash_ref()?);
+    let remote = fetch_server_root(server, retry).await?;
+
+    println!("Local root:  {} ({} files)", local_root_hex, files.len());
+    match &remote.root {
+        Some(root) => println!("Server root: {} ({} files)", root, remote.leaf_count),
+        None => println!("Server root: none (no files stored)"),
+    }
+
+    let matches = remote.root.as_deref() == Some(local_root_hex.as_str());
+    if matches {
+        println!("Match: {:?} is in sync with the server.", dir);
+    } else {
+        println!("Mismatch: {:?} differs from the server.", dir);
+        if remote.algorithm != HashAlgorithm::from(algorithm) {
+            println!(
+                "note: the server hashes with {:?}, not {}",
+                remote.algorithm,
+                algorithm.name()
+            );
+        }
+    }
+    Ok(matches)
+}
+
+/// The server's current root, leaf count and algorithm.
+async fn fetch_server_root(server: &str, retry: RetryPolicy) -> anyhow::Result<RootResp> {
+    let client = Client::new();
+    let url = format!("{}/root?format=json", server.trim_end_matches('/'));
+    let resp = send_with_retry(retry, || Ok(client.get(&url))).await?;
+    if !resp.status().is_success() {
+        anyhow::bail!("server returned error: {}", resp.status());
+    }
+    Ok(resp.json().await?)
+}
+
+/// Rebuild the tree from `dir` and compare its root to the one saved in `root_file`.
+fn verify_dir(
+    dir: &Path,
+    root_file: &Path,
+    algorithm: Option<Algorithm>,
+    sort: SortStrategy,
+) -> anyhow::Result<()> {
+    let (algorithm, saved_root) = read_root_file(root_file, algorithm)?;
+    let saved_root_hex = hex::encode(&saved_root);
+
+    let files = read_dir_sorted(dir, sort)?;
+    let config = TreeConfig::with_algorithm(algorithm.into());
+    let tree = MerkleTree::from_named_files_with_config(&files, config)?;
+    for ((name, _), leaf) in files.iter().zip(tree.get_leaves()) {
+        println!("{}  {}", hex::encode(leaf), name);
+    }
+
+    let computed_root_hex = hex::encode(tree.root_hash_ref()?);
+    println!("Computed root: {}", computed_root_hex);
+    println!("Saved root:    {}", saved_root_hex);
+
+    if computed_root_hex != saved_root_hex {
+        anyhow::bail!(
+            "root mismatch: {} files in {:?} do not match the saved root",
+            files.len(),
+            dir
+        );
+    }
+
+    println!("Match: {} files verified against saved root.", files.len());
+    Ok(())
+}
 
+/// Build the tree over `dir` and write the proof for `name` to `out`.
+fn prove_file(
+    dir: &Path,
+    name: &str,
+    out: &Path,
+    algorithm: Algorithm,
+    sort: SortStrategy,
+) -> anyhow::Result<()> {
+    validate_filename(name)?;
+    let files = read_dir_sorted(dir, sort)?;
+    let index = files
+        .iter()
+        .position(|(file_name, _)| file_name == name)
+        .ok_or_else(|| anyhow::anyhow!("'{}' not found in {:?}", name, dir))?;
+    let config = TreeConfig::with_algorithm(algorithm.into());
+    let tree = MerkleTree::from_named_files_with_config(&files, config)?;
+
+    let proof_file = ProofFile {
+        name: name.to_string(),
+        algorithm: algorithm.into(),
+        leaf_count: tree.leaf_count(),
+        bundle: ProofBundle::from_tree(&tree, index)?,
+    };
+    fs::write(out, serde_json::to_string_pretty(&proof_file)?)?;
+    println!(
+        "Wrote proof for '{}' (leaf {} of {}) to {:?}",
+        name,
+        index,
+        tree.leaf_count(),
+        out
+    );
+    println!("Root: {}", hex::encode(&proof_file.bundle.root));
     Ok(())
 }
+
+/// Check that a proof file's proof links its leaf to its root and, given
+/// `file`, that the file hashes to that leaf under the recorded name.
+///
+/// With `expected_root` (parsed like a root file), the embedded root must
+/// also equal it, so an auditor is not left trusting the proof file's own
+/// claim about the root.
+fn verify_proof_file(
+    proof_path: &Path,
+    file: Option<&Path>,
+    expected_root: Option<&str>,
+) -> anyhow::Result<()> {
+    let proof_file: ProofFile = serde_json::from_str(&fs::read_to_string(proof_path)?)?;
+    let config = TreeConfig::with_algorithm(proof_file.algorithm);
+    if let Some(expected_root) = expected_root {
+        let (algorithm, root) = parse_root_file(expected_root)?;
+        if let Some(algorithm) = algorithm.filter(|a| HashAlgorithm::from(*a) != config.algorithm) {
+            anyhow::bail!(
+                "expected root is {} but the proof uses {:?}",
+                algorithm.name(),
+                config.algorithm
+            );
+        }
+        if !merkle::constant_time_eq(&root, &proof_file.bundle.root) {
+            anyhow::bail!(
+                "Verification FAILED: proof root {} is not the expected root {}",
+                hex::encode(&proof_file.bundle.root),
+                hex::encode(&root)
+            );
+        }
+    }
+    if !proof_file.bundle.verify_with_config(&config) {
+        anyhow::bail!(
+            "Verification FAILED: proof for '{}' does not lead to root {}",
+            proof_file.name,
+            hex::encode(&proof_file.bundle.root)
+        );
+    }
+    if let Some(file) = file {
+        let leaf_hash = config.hash_named_leaf(&proof_file.name, &fs::read(file)?);
+        if leaf_hash != proof_file.bundle.leaf_hash {
+            anyhow::bail!(
+                "Verification FAILED: {:?} does not match the proven contents of '{}'",
+                file,
+                proof_file.name
+            );
+        }
+    }
+
+    println!(
+        "Proof verified: '{}' is one of {} files under root {}",
+        proof_file.name,
+        proof_file.leaf_count,
+        hex::encode(&proof_file.bundle.root)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Retry policy for tests: the default retries without the wait.
+    const FAST_RETRY: RetryPolicy = RetryPolicy {
+        retries: 3,
+        base_delay: Duration::from_millis(1),
+    };
+
+    /// Default upload settings, saving the root to `root_file`.
+    fn upload_options(root_file: PathBuf) -> UploadOptions<'static> {
+        UploadOptions {
+            root_file,
+            algorithm: Algorithm::Sha256,
+            sort: SortStrategy::default(),
+            proofs_dir: None,
+            dry_run: false,
+            keep_local: false,
+        }
+    }
+
+    #[test]
+    fn test_root_file_roundtrip() {
+        let root = vec![0xab; 64];
+        let contents = format_root_file(Algorithm::Sha512, &root);
+        assert!(contents.starts_with("sha512:"));
+        let (algorithm, parsed) = parse_root_file(&format!("{}\n", contents)).unwrap();
+        assert_eq!(algorithm, Some(Algorithm::Sha512));
+        assert_eq!(parsed, root);
+    }
+
+    #[test]
+    fn test_root_file_without_prefix() {
+        let (algorithm, parsed) = parse_root_file("00ff").unwrap();
+        assert_eq!(algorithm, None);
+        assert_eq!(parsed, vec![0x00, 0xff]);
+
+        assert!(parse_root_file("md5:00ff").is_err());
+        assert!(parse_root_file("sha256:zz").is_err());
+    }
+
+    #[test]
+    fn test_algorithm_flag_must_match_root_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_file = dir.path().join("root.hex");
+        fs::write(&root_file, "blake3:00ff").unwrap();
+
+        let (algorithm, _) = read_root_file(&root_file, None).unwrap();
+        assert_eq!(algorithm, Algorithm::Blake3);
+        assert!(read_root_file(&root_file, Some(Algorithm::Blake3)).is_ok());
+        assert!(read_root_file(&root_file, Some(Algorithm::Sha256)).is_err());
+    }
+
+    /// Mock server answering `GET /file/a.txt` with `file_bytes` and the
+    /// genuine proof for "alpha"; returns the server and the tree root.
+    async fn mock_file_server(file_bytes: &[u8]) -> (MockServer, Vec<u8>) {
+        let (body, root) = file_body(file_bytes);
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/file/a.txt"))
+            .and(query_param("expected_root", hex::encode(&root)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+        (server, root)
+    }
+
+    /// `GET /file/a.txt` response body carrying `file_bytes` and the genuine
+    /// proof for "alpha"; returns the body and the tree root.
+    fn file_body(file_bytes: &[u8]) -> (serde_json::Value, Vec<u8>) {
+        let files = vec![
+            ("a.txt".to_string(), b"alpha".to_vec()),
+            ("b.txt".to_string(), b"bravo".to_vec()),
+        ];
+        let tree = MerkleTree::from_named_files(&files).unwrap();
+        let bundle = ProofBundle::from_tree(&tree, 0).unwrap();
+        let mut body = serde_json::to_value(&bundle).unwrap();
+        body["file_name"] = "a.txt".into();
+        body["file_bytes"] = general_purpose::STANDARD.encode(file_bytes).into();
+        body["compressed"] = false.into();
+        body["content_type"] = "text/plain".into();
+        (body, bundle.root)
+    }
+
+    /// Write `files` into `dir/upload` and mock a server accepting them.
+    async fn mock_upload_server(
+        dir: &Path,
+        files: &[(String, Vec<u8>)],
+    ) -> (MockServer, PathBuf, String) {
+        let upload_dir_path = dir.join("upload");
+        fs::create_dir(&upload_dir_path).unwrap();
+        for (name, contents) in files {
+            fs::write(upload_dir_path.join(name), contents).unwrap();
+        }
+        let tree = MerkleTree::from_named_files(files).unwrap();
+        let root_hex = hex::encode(tree.root_hash_ref().unwrap());
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "root": root_hex,
+                "files_count": files.len(),
+            })))
+            .mount(&server)
+            .await;
+        (server, upload_dir_path, root_hex)
+    }
+
+    #[tokio::test]
+    async fn test_upload_streams_large_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        // Each file spans several read buffers; together they span many
+        let files: Vec<(String, Vec<u8>)> = (0..3u8)
+            .map(|i| {
+                let contents = (0..3 * UPLOAD_CHUNK_SIZE + 123)
+                    .map(|j| (j % 251) as u8 ^ i)
+                    .collect();
+                (format!("part{}.bin", i), contents)
+            })
+            .collect();
+        let (server, upload_dir_path, root_hex) = mock_upload_server(dir.path(), &files).await;
+
+        let root_file = dir.path().join("saved.root");
+        upload_dir(
+            &server.uri(),
+            FAST_RETRY,
+            upload_dir_path.clone(),
+            upload_options(root_file.clone()),
+        )
+        .await
+        .unwrap();
+
+        // Every file arrived intact and the local files were removed
+        let requests = server.received_requests().await.unwrap();
+        let body = &requests[0].body;
+        let all_contents: Vec<u8> = files.iter().flat_map(|(_, c)| c.clone()).collect();
+        assert_eq!(
+            requests[0].headers.get(UPLOAD_SHA256_HEADER).unwrap(),
+            hex::encode(merkle::sha256(&all_contents)).as_str()
+        );
+        for (name, contents) in &files {
+            assert!(
+                body.windows(contents.len())
+                    .any(|w| w == contents.as_slice())
+            );
+            assert!(!upload_dir_path.join(name).exists());
+        }
+        assert_eq!(
+            fs::read_to_string(&root_file).unwrap(),
+            format!("sha256:{}", root_hex)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            ("a.txt".to_string(), b"alpha".to_vec()),
+            ("b.txt".to_string(), b"bravo".to_vec()),
+        ];
+        let (server, upload_dir_path, _) = mock_upload_server(dir.path(), &files).await;
+
+        let root_file = dir.path().join("saved.root");
+        upload_dir(
+            &server.uri(),
+            FAST_RETRY,
+            upload_dir_path.clone(),
+            UploadOptions {
+                dry_run: true,
+                ..upload_options(root_file.clone())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(server.received_requests().await.unwrap().is_empty());
+        for (name, contents) in &files {
+            assert_eq!(&fs::read(upload_dir_path.join(name)).unwrap(), contents);
+        }
+        assert!(!root_file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_upload_keep_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            ("a.txt".to_string(), b"alpha".to_vec()),
+            ("b.txt".to_string(), b"bravo".to_vec()),
+        ];
+        let (server, upload_dir_path, root_hex) = mock_upload_server(dir.path(), &files).await;
+
+        let root_file = dir.path().join("saved.root");
+        upload_dir(
+            &server.uri(),
+            FAST_RETRY,
+            upload_dir_path.clone(),
+            UploadOptions {
+                keep_local: true,
+                ..upload_options(root_file.clone())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+        assert_eq!(
+            fs::read_to_string(&root_file).unwrap(),
+            format!("sha256:{}", root_hex)
+        );
+        for (name, contents) in &files {
+            assert_eq!(&fs::read(upload_dir_path.join(name)).unwrap(), contents);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_reuploads_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let upload_dir_path = dir.path().join("upload");
+        fs::create_dir(&upload_dir_path).unwrap();
+        fs::write(upload_dir_path.join("a.txt"), b"first").unwrap();
+        let root_of = |contents: &[u8]| {
+            let files = [("a.txt".to_string(), contents.to_vec())];
+            hex::encode(
+                MerkleTree::from_named_files(&files)
+                    .unwrap()
+                    .root_hash_ref()
+                    .unwrap(),
+            )
+        };
+        let (first_root, second_root) = (root_of(b"first"), root_of(b"second"));
+
+        // The server answers the initial upload, then the one after the change
+        let server = MockServer::start().await;
+        for (root, priority) in [(&first_root, 1), (&second_root, 2)] {
+            Mock::given(method("POST"))
+                .and(path("/upload"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "root": root,
+                    "files_count": 1,
+                })))
+                .up_to_n_times(1)
+                .with_priority(priority)
+                .mount(&server)
+                .await;
+        }
+
+        let root_file = dir.path().join("saved.root");
+        let (changes, changed) = tokio::sync::mpsc::unbounded_channel();
+        let watch = {
+            let (uri, upload_dir_path) = (server.uri(), upload_dir_path.clone());
+            let options = UploadOptions {
+                keep_local: true,
+                ..upload_options(root_file.clone())
+            };
+            tokio::spawn(async move {
+                watch_uploads(
+                    &uri,
+                    FAST_RETRY,
+                    &upload_dir_path,
+                    options,
+                    changed,
+                    Duration::from_millis(20),
+                )
+                .await
+            })
+        };
+        while server.received_requests().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // A burst of change events leads to a single re-upload
+        fs::write(upload_dir_path.join("a.txt"), b"second").unwrap();
+        for _ in 0..3 {
+            changes.send(()).unwrap();
+        }
+        drop(changes);
+        watch.await.unwrap().unwrap();
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        assert_eq!(
+            fs::read_to_string(&root_file).unwrap(),
+            format!("sha256:{}", second_root)
+        );
+        assert_eq!(fs::read(upload_dir_path.join("a.txt")).unwrap(), b"second");
+    }
+
+    #[tokio::test]
+    async fn test_upload_checks_server_algorithm_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![("a.txt".to_string(), b"alpha".to_vec())];
+        let (server, upload_dir_path, _) = mock_upload_server(dir.path(), &files).await;
+        Mock::given(method("GET"))
+            .and(path("/root"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "root": null,
+                "leaf_count": 0,
+                "height": 0,
+                "algorithm": "sha256",
+            })))
+            .mount(&server)
+            .await;
+
+        let root_file = dir.path().join("saved.root");
+        let options = UploadOptions {
+            algorithm: Algorithm::Blake3,
+            ..upload_options(root_file.clone())
+        };
+        let err = upload_dir(&server.uri(), FAST_RETRY, upload_dir_path.clone(), options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("nothing was uploaded"));
+
+        // Only the root was asked for; the store was left alone
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, wiremock::http::Method::GET);
+        assert!(!root_file.exists());
+        assert!(upload_dir_path.join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_watch_retries_rate_limited_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![("a.txt".to_string(), b"alpha".to_vec())];
+        let (server, upload_dir_path, root_hex) = mock_upload_server(dir.path(), &files).await;
+        Mock::given(method("POST"))
+            .and(path("/upload"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+
+        // No change follows, so only the retry can get the files uploaded
+        let root_file = dir.path().join("saved.root");
+        let (changes, changed) = tokio::sync::mpsc::unbounded_channel();
+        drop(changes);
+        watch_uploads(
+            &server.uri(),
+            FAST_RETRY,
+            &upload_dir_path,
+            upload_options(root_file.clone()),
+            changed,
+            Duration::from_millis(20),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        assert_eq!(
+            fs::read_to_string(&root_file).unwrap(),
+            format!("sha256:{}", root_hex)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_rejects_outputs_inside_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().join("upload");
+        fs::create_dir(&watched).unwrap();
+
+        let err = watch_dir(
+            "http://127.0.0.1:1",
+            FAST_RETRY,
+            watched.clone(),
+            upload_options(watched.join("saved.root")),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("inside the watched directory"));
+
+        let proofs = watched.join("proofs");
+        let options = UploadOptions {
+            proofs_dir: Some(&proofs),
+            ..upload_options(dir.path().join("saved.root"))
+        };
+        assert!(
+            watch_dir("http://127.0.0.1:1", FAST_RETRY, watched.clone(), options)
+                .await
+                .is_err()
+        );
+        assert!(!proofs.exists());
+
+        assert!(is_within(&watched, &watched.join("..").join("upload").join("x")).unwrap());
+        assert!(!is_within(&watched, &dir.path().join("saved.root")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_upload_of_vanished_file_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = LocalFiles::Dir(vec![("gone.txt".to_string(), dir.path().join("gone.txt"))]);
+
+        let err = upload_files(
+            "http://127.0.0.1:1",
+            FAST_RETRY,
+            files,
+            upload_options(dir.path().join("saved.root")),
+        )
+        .await
+        .unwrap_err();
+        assert!(vanished_mid_scan(&err));
+        assert!(!vanished_mid_scan(&anyhow::anyhow!("root mismatch")));
+    }
+
+    #[tokio::test]
+    async fn test_upload_saves_offline_proofs() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<(String, Vec<u8>)> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    format!("contents of {}", name).into_bytes(),
+                )
+            })
+            .collect();
+        let (server, upload_dir_path, _) = mock_upload_server(dir.path(), &files).await;
+
+        let root_file = dir.path().join("saved.root");
+        let proofs_dir = dir.path().join("proofs");
+        upload_dir(
+            &server.uri(),
+            FAST_RETRY,
+            upload_dir_path,
+            UploadOptions {
+                proofs_dir: Some(&proofs_dir),
+                ..upload_options(root_file.clone())
+            },
+        )
+        .await
+        .unwrap();
+
+        let (_, saved_root) = read_root_file(&root_file, None).unwrap();
+        for (name, contents) in &files {
+            let saved =
+                fs::read_to_string(proofs_dir.join(format!("{}.proof.json", name))).unwrap();
+            let bundle: ProofBundle = serde_json::from_str(&saved).unwrap();
+            assert_eq!(
+                bundle.leaf_hash,
+                TreeConfig::default().hash_named_leaf(name, contents)
+            );
+            assert!(MerkleTree::verify_proof(
+                &bundle.leaf_hash,
+                &bundle.proof,
+                &saved_root
+            ));
+        }
+    }
+
+    /// Tar archive holding `files` as regular entries with the given mtimes.
+    fn tar_archive(files: &[(&str, &[u8], u64)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents, mtime) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(*mtime);
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_tar() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("files.tar");
+        // Stored out of name order; the oldest entry is last
+        let entries: [(&str, &[u8], u64); 3] = [
+            ("file10.txt", b"ten", 300),
+            ("file2.txt", b"two", 200),
+            ("file1.txt", b"one", 100),
+        ];
+        fs::write(&archive, tar_archive(&entries)).unwrap();
+
+        let numeric = read_tar_sorted(&archive, SortStrategy::Numeric).unwrap();
+        let names: Vec<&str> = numeric.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["file1.txt", "file2.txt", "file10.txt"]);
+        let by_mtime = read_tar_sorted(&archive, SortStrategy::ModifiedTime).unwrap();
+        assert_eq!(by_mtime, numeric);
+
+        let files = read_tar_sorted(&archive, SortStrategy::default()).unwrap();
+        let (server, _, root_hex) = mock_upload_server(dir.path(), &files).await;
+        let root_file = dir.path().join("saved.root");
+        upload_tar(
+            &server.uri(),
+            FAST_RETRY,
+            &archive,
+            upload_options(root_file.clone()),
+        )
+        .await
+        .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let body = String::from_utf8_lossy(&requests[0].body);
+        for (name, contents, _) in &entries {
+            assert!(body.contains(&format!("filename=\"{}\"", name)));
+            assert!(body.contains(std::str::from_utf8(contents).unwrap()));
+        }
+        assert_eq!(
+            fs::read_to_string(&root_file).unwrap(),
+            format!("sha256:{}", root_hex)
+        );
+        assert!(archive.exists());
+    }
+
+    #[test]
+    fn test_read_tar_rejects_bad_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("bad.tar");
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "subdir/", std::io::empty())
+            .unwrap();
+        fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+        let err = read_tar_sorted(&archive, SortStrategy::default()).unwrap_err();
+        assert!(err.to_string().contains("directory entry"));
+
+        for name in ["nested/a.txt", "root.hex"] {
+            fs::write(&archive, tar_archive(&[(name, b"x", 0)])).unwrap();
+            assert!(read_tar_sorted(&archive, SortStrategy::default()).is_err());
+        }
+
+        fs::write(
+            &archive,
+            tar_archive(&[("a.txt", b"x", 0), ("a.txt", b"y", 0)]),
+        )
+        .unwrap();
+        let err = read_tar_sorted(&archive, SortStrategy::default()).unwrap_err();
+        assert!(err.to_string().contains("appears twice"));
+    }
+
+    #[tokio::test]
+    async fn test_status_compares_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![
+            ("a.txt".to_string(), b"alpha".to_vec()),
+            ("b.txt".to_string(), b"bravo".to_vec()),
+        ];
+        for (name, contents) in &files {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let tree = MerkleTree::from_named_files(&files).unwrap();
+        let root_hex = hex::encode(tree.root_hash_ref().unwrap());
+
+        for (server_root, expected) in [(root_hex.as_str(), true), ("00ff", false)] {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path("/root"))
+                .and(query_param("format", "json"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "root": server_root,
+                    "leaf_count": 2,
+                    "height": 2,
+                    "algorithm": "sha256",
+                })))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let matches = status_dir(
+                &server.uri(),
+                FAST_RETRY,
+                dir.path(),
+                Algorithm::Sha256,
+                SortStrategy::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(matches, expected);
+        }
+
+        // Nothing was touched
+        for (name, contents) in &files {
+            assert_eq!(&fs::read(dir.path().join(name)).unwrap(), contents);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), files.len());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_verify() {
+        let (server, root) = mock_file_server(b"alpha").await;
+        let file = fetch_and_verify(
+            &Client::new(),
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            Algorithm::Sha256,
+            &[&root],
+        )
+        .await
+        .unwrap();
+        assert_eq!(file.bytes, b"alpha");
+        assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn test_request_with_candidate_roots() {
+        let (server, root) = mock_file_server(b"alpha").await;
+        // With several candidates the client cannot name one to the server
+        Mock::given(method("GET"))
+            .and(path("/file/a.txt"))
+            .and(query_param_is_missing("expected_root"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(file_body(b"alpha").0))
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root_file = dir.path().join("root.hex");
+        let out = dir.path().join("a.txt");
+        let old_root = format_root_file(Algorithm::Sha256, &[0xab; 32]);
+        let new_root = format_root_file(Algorithm::Sha256, &root);
+        fs::write(&root_file, format!("{}\n{}\n", old_root, new_root)).unwrap();
+        request_file(
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            root_file.clone(),
+            Some(out.clone()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fs::read(&out).unwrap(), b"alpha");
+
+        fs::write(&root_file, format!("{}\n{}\n", old_root, old_root)).unwrap();
+        let err = request_file(&server.uri(), FAST_RETRY, "a.txt", root_file, None, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Verification FAILED"));
+    }
+
+    #[test]
+    fn test_proof_file_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let files_dir = dir.path().join("files");
+        fs::create_dir(&files_dir).unwrap();
+        for (name, contents) in [("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")] {
+            fs::write(files_dir.join(name), contents).unwrap();
+        }
+        let out = dir.path().join("b.proof.json");
+        prove_file(
+            &files_dir,
+            "b.txt",
+            &out,
+            Algorithm::Blake3,
+            SortStrategy::default(),
+        )
+        .unwrap();
+        assert!(
+            prove_file(
+                &files_dir,
+                "missing.txt",
+                &dir.path().join("x.json"),
+                Algorithm::Blake3,
+                SortStrategy::default()
+            )
+            .is_err()
+        );
+
+        let value: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(value["name"], "b.txt");
+        assert_eq!(value["leaf_count"], 3);
+        assert_eq!(value["algorithm"], "blake3");
+
+        // Checks out on its own and against the file, with the directory gone
+        let copy = dir.path().join("b-copy.txt");
+        fs::copy(files_dir.join("b.txt"), &copy).unwrap();
+        fs::remove_dir_all(&files_dir).unwrap();
+        verify_proof_file(&out, None, None).unwrap();
+        verify_proof_file(&out, Some(&copy), None).unwrap();
+        let root = value["root"].as_str().unwrap();
+        verify_proof_file(&out, Some(&copy), Some(&format!("blake3:{}", root))).unwrap();
+    }
+
+    #[test]
+    fn test_verify_proof_file_rejects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let files_dir = dir.path().join("files");
+        fs::create_dir(&files_dir).unwrap();
+        for (name, contents) in [("a.txt", "alpha"), ("b.txt", "bravo"), ("c.txt", "charlie")] {
+            fs::write(files_dir.join(name), contents).unwrap();
+        }
+        let out = dir.path().join("c.proof.json");
+        prove_file(
+            &files_dir,
+            "c.txt",
+            &out,
+            Algorithm::Sha256,
+            SortStrategy::default(),
+        )
+        .unwrap();
+        let value: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        let file = files_dir.join("c.txt");
+        verify_proof_file(&out, Some(&file), None).unwrap();
+
+        // Tampered file
+        fs::write(&file, "charliE").unwrap();
+        assert!(verify_proof_file(&out, Some(&file), None).is_err());
+
+        // Tampered proof: a flipped bit in the first sibling
+        let mut tampered = value.clone();
+        let byte = tampered["proof"][0]["hash"][0].as_u64().unwrap();
+        tampered["proof"][0]["hash"][0] = (byte ^ 1).into();
+        let tampered_path = dir.path().join("tampered.json");
+        fs::write(&tampered_path, tampered.to_string()).unwrap();
+        assert!(verify_proof_file(&tampered_path, None, None).is_err());
+
+        // Consistent proof, but for another root than the auditor expects
+        let other_root = hex::encode([0u8; 32]);
+        assert!(verify_proof_file(&out, None, Some(&other_root)).is_err());
+        let root = value["root"].as_str().unwrap();
+        assert!(verify_proof_file(&out, None, Some(&format!("sha512:{}", root))).is_err());
+        verify_proof_file(&out, None, Some(root)).unwrap();
+    }
+
+    #[test]
+    fn test_root_candidates() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_file = dir.path().join("root.hex");
+        fs::write(&root_file, "blake3:00ff\n\nblake3:ff00\n").unwrap();
+        let (algorithm, roots) = read_root_candidates(&root_file, None).unwrap();
+        assert_eq!(algorithm, Algorithm::Blake3);
+        assert_eq!(roots, vec![vec![0x00, 0xff], vec![0xff, 0x00]]);
+        assert!(read_root_file(&root_file, None).is_err());
+
+        fs::write(&root_file, "blake3:00ff\nsha256:ff00\n").unwrap();
+        assert!(read_root_candidates(&root_file, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec![("a.txt".to_string(), b"alpha".to_vec())];
+        let (server, upload_dir_path, _) = mock_upload_server(dir.path(), &files).await;
+        // Mounted last but matched first until it has answered twice
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+
+        upload_dir(
+            &server.uri(),
+            FAST_RETRY,
+            upload_dir_path,
+            upload_options(dir.path().join("saved.root")),
+        )
+        .await
+        .unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 3);
+        // Every attempt carried the whole file again
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.body.windows(5).any(|w| w == b"alpha"))
+        );
+
+        let (server, root) = mock_file_server(b"alpha").await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let file = fetch_and_verify(
+            &Client::new(),
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            Algorithm::Sha256,
+            &[&root],
+        )
+        .await
+        .unwrap();
+        assert_eq!(file.bytes, b"alpha");
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_give_up() {
+        // Client errors are final
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let err = fetch_and_verify(
+            &Client::new(),
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            Algorithm::Sha256,
+            &[&[0; 32]],
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("404"));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        // Server errors are retried until the retries run out
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(502))
+            .mount(&server)
+            .await;
+        let err = fetch_list(&Client::new(), &server.uri(), FAST_RETRY, 0, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("502"));
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_default_out_path() {
+        assert_eq!(
+            default_out_path("photo", Some("image/png")),
+            PathBuf::from("photo.png")
+        );
+        // Existing extensions and unknown types are left alone
+        assert_eq!(
+            default_out_path("photo.dat", Some("image/png")),
+            PathBuf::from("photo.dat")
+        );
+        assert_eq!(
+            default_out_path("blob", Some("application/octet-stream")),
+            PathBuf::from("blob")
+        );
+        assert_eq!(default_out_path("blob", None), PathBuf::from("blob"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_verify_rejects_tampering() {
+        let (server, root) = mock_file_server(b"alphX").await;
+        let err = fetch_and_verify(
+            &Client::new(),
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            Algorithm::Sha256,
+            &[&root],
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Verification FAILED"));
+
+        // Genuine bytes requested under a different saved root
+        let (server, mut root) = mock_file_server(b"alpha").await;
+        root[0] ^= 0xff;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(409).set_body_string("root mismatch"))
+            .mount(&server)
+            .await;
+        let err = fetch_and_verify(
+            &Client::new(),
+            &server.uri(),
+            FAST_RETRY,
+            "a.txt",
+            Algorithm::Sha256,
+            &[&root],
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Server root differs"));
+    }
+}
@@ -0,0 +1,58 @@
+// Build and proof timings for trees of growing size
+//
+// Run with `cargo bench -p merkle --features instrument` (add `rayon` to
+// compare parallel hashing). Each size is built a few times and the fastest
+// run is reported, which is the least disturbed by other work on the machine.
+
+use std::time::Duration;
+
+use merkle::{MerkleTree, sha256};
+
+/// Leaf counts to build, each a power of ten
+const SIZES: [u32; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+/// Builds per size; the fastest counts
+const RUNS: usize = 3;
+
+/// Proofs generated per tree, spread evenly over the leaves
+const PROOFS: u32 = 1_000;
+
+fn main() {
+    println!(
+        "{:>10} {:>7} {:>12} {:>12}",
+        "leaves", "levels", "build", "proof (avg)"
+    );
+    for size in SIZES {
+        let leaves: Vec<Vec<u8>> = (0..size).map(|i| sha256(&i.to_be_bytes())).collect();
+
+        let mut best: Option<(MerkleTree, merkle::BuildStats)> = None;
+        for _ in 0..RUNS {
+            let (tree, stats) = MerkleTree::from_leaves_instrumented(leaves.clone())
+                .expect("leaves are non-empty and uniform");
+            if best
+                .as_ref()
+                .is_none_or(|(_, fastest)| stats.build_time < fastest.build_time)
+            {
+                best = Some((tree, stats));
+            }
+        }
+        let (tree, stats) = best.expect("at least one run");
+
+        let mut proof_time = Duration::ZERO;
+        for i in 0..PROOFS {
+            let index = (i as u64 * size as u64 / PROOFS as u64) as usize;
+            let (_, proof_stats) = tree
+                .generate_proof_instrumented(index)
+                .expect("index is in bounds");
+            proof_time += proof_stats.proof_time;
+        }
+
+        println!(
+            "{:>10} {:>7} {:>12?} {:>12?}",
+            size,
+            stats.level_count,
+            stats.build_time,
+            proof_time / PROOFS
+        );
+    }
+}
@@ -0,0 +1,286 @@
+// Compact binary encoding for inclusion proofs
+//
+// Each proof node is one flag byte (`1` if the sibling is on the left, `0`
+// otherwise) followed by the raw sibling hash. A proof is its nodes
+// concatenated, leaf level first, with no header; the node size follows from
+// the hash algorithm. For 32-byte hashes this is 33 bytes per node, about half
+// the size of the JSON form. The duplicate nodes of a compressed proof are
+// the lone flag byte `2`, with no hash. In a tree whose leaves are shorter
+// than the digest (e.g. 20-byte leaves under SHA-256) the first node, the
+// leaf's sibling, has the leaf length instead; the decoder has to be told it.
+
+use crate::{HashAlgorithm, MerkleError, MerkleTree, ProofNode, Result};
+
+/// Flag byte of a duplicate node, which has no hash after it.
+const DUPLICATE_FLAG: u8 = 2;
+
+impl ProofNode {
+    /// Encode as the flag byte followed by the hash.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.duplicate {
+            return vec![DUPLICATE_FLAG];
+        }
+        let mut bytes = Vec::with_capacity(1 + self.hash.len());
+        bytes.push(self.is_left as u8);
+        bytes.extend_from_slice(&self.hash);
+        bytes
+    }
+
+    /// Decode a node produced by `to_bytes`; everything after the flag is the hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidProofNode` if `bytes` holds no hash (or
+    /// one after a duplicate flag), or `MerkleError::InvalidProofFlag` if the
+    /// flag is not 0, 1 or 2.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = MerkleError::InvalidProofNode { len: bytes.len() };
+        if bytes.first() == Some(&DUPLICATE_FLAG) {
+            if bytes.len() != 1 {
+                return Err(invalid);
+            }
+            return Ok(ProofNode::duplicate());
+        }
+        let Some((&flag, hash)) = bytes.split_first().filter(|(_, hash)| !hash.is_empty()) else {
+            return Err(invalid);
+        };
+        let is_left = match flag {
+            0 => false,
+            1 => true,
+            other => return Err(MerkleError::InvalidProofFlag(other)),
+        };
+        Ok(ProofNode {
+            hash: hash.to_vec(),
+            is_left,
+            duplicate: false,
+        })
+    }
+}
+
+impl MerkleTree {
+    /// Encode a proof as concatenated `ProofNode::to_bytes` records.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.generate_proof(2)?;
+    /// let bytes = MerkleTree::proof_to_bytes(&proof);
+    /// assert_eq!(bytes.len(), 2 * 33);
+    /// assert_eq!(MerkleTree::proof_from_bytes(&bytes)?, proof);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn proof_to_bytes(proof: &[ProofNode]) -> Vec<u8> {
+        proof.iter().flat_map(ProofNode::to_bytes).collect()
+    }
+
+    /// Decode a binary proof with 32-byte hashes (SHA-256 or BLAKE3).
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidProofLength` if the bytes end partway
+    /// through a 33-byte node, or `MerkleError::InvalidProofFlag` for a bad
+    /// flag byte.
+    pub fn proof_from_bytes(bytes: &[u8]) -> Result<Vec<ProofNode>> {
+        Self::proof_from_bytes_with_algorithm(bytes, HashAlgorithm::default())
+    }
+
+    /// Decode a binary proof whose hashes come from `algorithm`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidProofLength` if the bytes end partway
+    /// through a `1 + algorithm.output_len()` byte node, or
+    /// `MerkleError::InvalidProofFlag` for a bad flag byte.
+    pub fn proof_from_bytes_with_algorithm(
+        bytes: &[u8],
+        algorithm: HashAlgorithm,
+    ) -> Result<Vec<ProofNode>> {
+        Self::proof_from_bytes_with_leaf_len(bytes, algorithm, algorithm.output_len())
+    }
+
+    /// Decode a binary proof from a tree whose leaves are `leaf_len` bytes
+    /// and whose internal nodes come from `algorithm`.
+    ///
+    /// The first node, the leaf's sibling, is read as `1 + leaf_len` bytes
+    /// and every later one as `1 + algorithm.output_len()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidProofLength` if the bytes end partway
+    /// through a node, or `MerkleError::InvalidProofFlag` for a bad flag byte.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{HashAlgorithm, MerkleTree};
+    ///
+    /// let leaves: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 20]).collect();
+    /// let tree = MerkleTree::from_leaves(leaves)?;
+    /// let proof = tree.generate_proof(0)?;
+    /// let bytes = MerkleTree::proof_to_bytes(&proof);
+    /// assert_eq!(bytes.len(), 21 + 33);
+    /// let decoded = MerkleTree::proof_from_bytes_with_leaf_len(&bytes, HashAlgorithm::Sha256, 20)?;
+    /// assert_eq!(decoded, proof);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn proof_from_bytes_with_leaf_len(
+        bytes: &[u8],
+        algorithm: HashAlgorithm,
+        leaf_len: usize,
+    ) -> Result<Vec<ProofNode>> {
+        let node_len = 1 + algorithm.output_len();
+        let mut nodes = Vec::with_capacity(bytes.len() / node_len);
+        let mut rest = bytes;
+        while let Some(&flag) = rest.first() {
+            let len = match flag {
+                DUPLICATE_FLAG => 1,
+                _ if nodes.is_empty() => 1 + leaf_len,
+                _ => node_len,
+            };
+            if rest.len() < len {
+                return Err(MerkleError::InvalidProofLength {
+                    len: bytes.len(),
+                    node_len: len,
+                });
+            }
+            let (node, tail) = rest.split_at(len);
+            nodes.push(ProofNode::from_bytes(node)?);
+            rest = tail;
+        }
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TreeConfig, sha256};
+
+    #[test]
+    fn test_binary_proof_roundtrip() {
+        let files: Vec<Vec<u8>> = (0..13u8).map(|i| vec![i]).collect();
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            let tree = MerkleTree::from_bytes_vec_with_algorithm(&files, algorithm).unwrap();
+            let config = TreeConfig::with_algorithm(algorithm);
+            for (i, item) in files.iter().enumerate() {
+                let proof = tree.generate_proof(i).unwrap();
+                let bytes = MerkleTree::proof_to_bytes(&proof);
+                assert_eq!(bytes.len(), proof.len() * (1 + algorithm.output_len()));
+
+                let decoded =
+                    MerkleTree::proof_from_bytes_with_algorithm(&bytes, algorithm).unwrap();
+                assert_eq!(decoded, proof);
+                assert!(tree.verify(&config.hash_leaf(item), &decoded).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_compressed_proof_roundtrip() {
+        let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let proof = tree.generate_proof_compressed(4).unwrap();
+        let bytes = MerkleTree::proof_to_bytes(&proof);
+        assert_eq!(bytes.len(), 1 + 1 + 33);
+        assert_eq!(&bytes[..2], &[DUPLICATE_FLAG, DUPLICATE_FLAG]);
+
+        let decoded = MerkleTree::proof_from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(tree.verify(&sha256(&[4]), &decoded).unwrap());
+
+        // A duplicate carries nothing, and a real node may not hide behind one
+        assert!(matches!(
+            ProofNode::from_bytes(&[DUPLICATE_FLAG, 0xaa]),
+            Err(MerkleError::InvalidProofNode { len: 2 })
+        ));
+        assert!(matches!(
+            MerkleTree::proof_from_bytes(&bytes[1..bytes.len() - 1]),
+            Err(MerkleError::InvalidProofLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_binary_proof_roundtrip_short_leaves() {
+        let leaves: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 20]).collect();
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Sha512] {
+            let config = TreeConfig::with_algorithm(algorithm);
+            let tree = MerkleTree::from_leaves_with_config(leaves.clone(), config).unwrap();
+            for (i, leaf) in leaves.iter().enumerate() {
+                for proof in [
+                    tree.generate_proof(i).unwrap(),
+                    tree.generate_proof_compressed(i).unwrap(),
+                ] {
+                    let bytes = MerkleTree::proof_to_bytes(&proof);
+                    let decoded =
+                        MerkleTree::proof_from_bytes_with_leaf_len(&bytes, algorithm, 20).unwrap();
+                    assert_eq!(decoded, proof);
+                    assert!(tree.verify(leaf, &decoded).unwrap());
+                }
+            }
+
+            // Read as full-length nodes, the short first node misaligns the rest
+            let bytes = MerkleTree::proof_to_bytes(&tree.generate_proof(0).unwrap());
+            assert!(MerkleTree::proof_from_bytes_with_algorithm(&bytes, algorithm).is_err());
+        }
+    }
+
+    #[test]
+    fn test_binary_proof_is_smaller_than_json() {
+        let files: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let proof = tree.generate_proof(5).unwrap();
+
+        let json = serde_json::to_vec(&proof).unwrap();
+        let bytes = MerkleTree::proof_to_bytes(&proof);
+        assert_eq!(bytes.len(), 4 * 33);
+        assert!(bytes.len() * 2 <= json.len());
+    }
+
+    #[test]
+    fn test_binary_proof_rejects_bad_input() {
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        let mut bytes = MerkleTree::proof_to_bytes(&tree.generate_proof(0).unwrap());
+
+        assert!(matches!(
+            MerkleTree::proof_from_bytes(&bytes[..32]),
+            Err(MerkleError::InvalidProofLength {
+                len: 32,
+                node_len: 33
+            })
+        ));
+        // A SHA-256 proof is not a whole number of SHA-512 nodes
+        assert!(matches!(
+            MerkleTree::proof_from_bytes_with_algorithm(&bytes, HashAlgorithm::Sha512),
+            Err(MerkleError::InvalidProofLength { .. })
+        ));
+
+        bytes[0] = 7;
+        assert!(matches!(
+            MerkleTree::proof_from_bytes(&bytes),
+            Err(MerkleError::InvalidProofFlag(7))
+        ));
+
+        assert!(MerkleTree::proof_from_bytes(&[]).unwrap().is_empty());
+        assert!(matches!(
+            ProofNode::from_bytes(&[1]),
+            Err(MerkleError::InvalidProofNode { len: 1 })
+        ));
+        assert!(matches!(
+            ProofNode::from_bytes(&[]),
+            Err(MerkleError::InvalidProofNode { len: 0 })
+        ));
+
+        let node = ProofNode::from_bytes(&[1, 0xaa, 0xbb]).unwrap();
+        assert!(node.is_left);
+        assert_eq!(node.hash, vec![0xaa, 0xbb]);
+        assert_eq!(node.to_bytes(), vec![1, 0xaa, 0xbb]);
+    }
+}
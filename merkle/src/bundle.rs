@@ -0,0 +1,121 @@
+// Self-contained proof bundles
+//
+// A `ProofBundle` carries everything needed to check one leaf's inclusion, so
+// server and client can exchange a single typed object.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, MerkleTree, ProofNode, Result, TreeConfig};
+
+/// A leaf hash together with its inclusion proof and the root it proves against.
+///
+/// `leaf_hash` and `root` serialize as hex strings; proof nodes keep their
+/// usual representation.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProofBundle {
+    /// Hash of the proven leaf
+    #[serde(with = "hex_serde")]
+    pub leaf_hash: Hash,
+    /// Sibling path from the leaf up to the root
+    pub proof: Vec<ProofNode>,
+    /// Root the proof resolves to
+    #[serde(with = "hex_serde")]
+    pub root: Hash,
+}
+
+impl ProofBundle {
+    /// Bundle the proof for the leaf at `index` of `tree`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, ProofBundle};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let bundle = ProofBundle::from_tree(&tree, 1)?;
+    /// assert!(bundle.verify());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_tree(tree: &MerkleTree, index: usize) -> Result<Self> {
+        let proof = tree.generate_proof(index)?;
+        Ok(ProofBundle {
+            leaf_hash: tree.get_leaves()[index].clone(),
+            proof,
+            root: tree.root_hash_ref()?.to_vec(),
+        })
+    }
+
+    /// Check that `proof` links `leaf_hash` to `root` in a SHA-256 tree.
+    pub fn verify(&self) -> bool {
+        self.verify_with_config(&TreeConfig::default())
+    }
+
+    /// Check the bundle for a tree built with `config`.
+    pub fn verify_with_config(&self, config: &TreeConfig) -> bool {
+        MerkleTree::verify_proof_with_config(&self.leaf_hash, &self.proof, &self.root, config)
+    }
+}
+
+/// Serde adapter encoding byte vectors as hex strings.
+pub(crate) mod hex_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256;
+
+    fn sample_bundle() -> ProofBundle {
+        let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        ProofBundle::from_tree(&tree, 2).unwrap()
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let bundle = sample_bundle();
+        assert_eq!(bundle.leaf_hash, sha256(b"c"));
+        assert!(bundle.verify());
+
+        let json = serde_json::to_string(&bundle).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["leaf_hash"], hex::encode(sha256(b"c")));
+        assert_eq!(value["root"], hex::encode(&bundle.root));
+
+        let decoded: ProofBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, bundle);
+        assert!(decoded.verify());
+    }
+
+    #[test]
+    fn test_bundle_tamper() {
+        let mut wrong_leaf = sample_bundle();
+        wrong_leaf.leaf_hash = sha256(b"x");
+        assert!(!wrong_leaf.verify());
+
+        let mut wrong_proof = sample_bundle();
+        wrong_proof.proof[0].hash[0] ^= 0xff;
+        assert!(!wrong_proof.verify());
+
+        let mut wrong_root = sample_bundle();
+        wrong_root.root[31] ^= 0x01;
+        assert!(!wrong_root.verify());
+
+        let bad_hex = r#"{"leaf_hash":"zz","proof":[],"root":"00"}"#;
+        assert!(serde_json::from_str::<ProofBundle>(bad_hex).is_err());
+    }
+}
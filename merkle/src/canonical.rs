@@ -0,0 +1,158 @@
+// Canonical encoding of JSON leaves
+//
+// Two JSON documents can mean the same thing and still differ byte for byte:
+// object keys may come in any order and whitespace is free. Hashing the raw
+// text would give them different leaves, so JSON values are re-serialized in
+// one canonical form first.
+
+use serde_json::Value;
+
+use crate::{Hash, MerkleTree, Result, TreeConfig};
+
+/// Serialize `value` in canonical form.
+///
+/// The rules:
+///
+/// - Object members are sorted by key, comparing the keys' UTF-8 bytes.
+/// - No whitespace is emitted between tokens.
+/// - Arrays keep their order.
+/// - Strings use `serde_json`'s escaping: only `"`, `\` and control
+///   characters are escaped, everything else is written as UTF-8.
+/// - Numbers are written as `serde_json` parsed them, so `1` and `1.0` stay
+///   distinct, while `1e2` and `100.0` both become `100.0`.
+///
+/// # Examples
+///
+/// ```
+/// let a: serde_json::Value = serde_json::from_str(r#"{ "b": [1, 2], "a": null }"#)?;
+/// assert_eq!(merkle::canonical_json(&a), r#"{"a":null,"b":[1,2]}"#);
+/// # Ok::<(), serde_json::Error>(())
+/// ```
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            // `Map` is only sorted without serde_json's `preserve_order`
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, member)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(member, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+impl MerkleTree {
+    /// Build from JSON values, hashing the canonical form of each
+    /// (see `canonical_json`) so that equal values give equal leaves however
+    /// they were formatted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `values` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let value = serde_json::json!({ "id": 7, "tags": ["x"] });
+    /// let tree = MerkleTree::from_json_values(&[value])?;
+    /// assert_eq!(tree.get_leaves()[0], sha256(br#"{"id":7,"tags":["x"]}"#));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_json_values(values: &[Value]) -> Result<Self> {
+        Self::from_json_values_with_config(values, TreeConfig::default())
+    }
+
+    /// Build from JSON values, hashing each canonical form with
+    /// `config.hash_leaf`.
+    pub fn from_json_values_with_config(values: &[Value], config: TreeConfig) -> Result<Self> {
+        let leaves: Vec<Hash> = values
+            .iter()
+            .map(|value| config.hash_leaf(canonical_json(value).as_bytes()))
+            .collect();
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleError, sha256};
+
+    #[test]
+    fn test_equal_json_gives_equal_leaves() {
+        let compact: Value =
+            serde_json::from_str(r#"{"name":"a.txt","meta":{"size":5,"tags":["x","y"]}}"#).unwrap();
+        let spaced: Value = serde_json::from_str(
+            r#"
+            {
+                "meta": { "tags": [ "x", "y" ], "size": 5 },
+                "name": "a.txt"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            canonical_json(&spaced),
+            r#"{"meta":{"size":5,"tags":["x","y"]},"name":"a.txt"}"#
+        );
+
+        let a = MerkleTree::from_json_values(&[compact.clone(), Value::Null]).unwrap();
+        let b = MerkleTree::from_json_values(&[spaced, Value::Null]).unwrap();
+        assert_eq!(a.get_leaves(), b.get_leaves());
+        assert_eq!(a.root_hash_ref().unwrap(), b.root_hash_ref().unwrap());
+
+        // Array order and number spelling still matter
+        let reordered =
+            serde_json::json!({ "name": "a.txt", "meta": { "size": 5, "tags": ["y", "x"] } });
+        let float =
+            serde_json::json!({ "name": "a.txt", "meta": { "size": 5.0, "tags": ["x", "y"] } });
+        for other in [reordered, float] {
+            let tree = MerkleTree::from_json_values(&[other, Value::Null]).unwrap();
+            assert_ne!(tree.get_leaves()[0], a.get_leaves()[0]);
+        }
+    }
+
+    #[test]
+    fn test_canonical_scalars_and_escapes() {
+        let value = serde_json::json!({ "q\"uote": "line\nbreak", "é": true, "n": -0.5 });
+        assert_eq!(
+            canonical_json(&value),
+            r#"{"n":-0.5,"q\"uote":"line\nbreak","é":true}"#
+        );
+        let tree = MerkleTree::from_json_values(&[value]).unwrap();
+        assert_eq!(
+            tree.get_leaves()[0],
+            sha256(r#"{"n":-0.5,"q\"uote":"line\nbreak","é":true}"#.as_bytes())
+        );
+
+        assert!(matches!(
+            MerkleTree::from_json_values(&[]),
+            Err(MerkleError::EmptyLeaves)
+        ));
+    }
+}
@@ -0,0 +1,217 @@
+// Leaves-only trees for archival
+//
+// Every level above the leaves is a pure function of the leaves and the tree
+// config, so a stored tree only needs those two. `CompactTree` holds just that
+// and rebuilds the levels the first time a root or proof is asked for; the
+// same leaves-only layout keeps persisted `tree.json` files small.
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FORMAT_VERSION, Hash, MerkleTree, ProofNode, Result, TreeConfig};
+
+/// A tree stored as its leaves and config, with levels rebuilt on demand.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompactTree {
+    leaves: Vec<Hash>,
+    #[serde(flatten)]
+    config: TreeConfig,
+    /// The full tree, built on first use
+    #[serde(skip)]
+    expanded: OnceLock<MerkleTree>,
+}
+
+/// Borrowed leaves-only layout, shared by both tree types' serializers.
+#[derive(Serialize)]
+struct LeavesOnly<'a> {
+    format_version: u32,
+    leaves: &'a [Hash],
+    #[serde(flatten)]
+    config: &'a TreeConfig,
+}
+
+impl MerkleTree {
+    /// Drop every level above the leaves.
+    ///
+    /// The returned tree rebuilds them the first time a root or proof is
+    /// needed, and serializes in the leaves-only layout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let root = tree.root_hash_ref()?.to_vec();
+    /// let proof = tree.generate_proof(1)?;
+    ///
+    /// let compact = tree.compact();
+    /// assert_eq!(compact.root_hash_ref()?, root.as_slice());
+    /// assert_eq!(compact.generate_proof(1)?, proof);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn compact(mut self) -> CompactTree {
+        self.levels.truncate(1);
+        CompactTree {
+            leaves: self.levels.pop().unwrap_or_default(),
+            config: self.config,
+            expanded: OnceLock::new(),
+        }
+    }
+
+    /// Serialize only the leaves and config, tagged with `format_version`.
+    ///
+    /// `from_json` reads this layout back by rebuilding the levels.
+    pub fn to_json_leaves_only(&self) -> Result<String> {
+        Ok(serde_json::to_string(&LeavesOnly {
+            format_version: FORMAT_VERSION,
+            leaves: self.get_leaves(),
+            config: &self.config,
+        })?)
+    }
+}
+
+impl CompactTree {
+    /// The full tree, rebuilding the levels on first call.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `MerkleTree::from_leaves_with_config` if the
+    /// leaves (e.g. from a hand-edited file) do not form a valid tree. No
+    /// leaves at all give an empty tree, as from `MerkleTree::empty`.
+    pub fn tree(&self) -> Result<&MerkleTree> {
+        if let Some(tree) = self.expanded.get() {
+            return Ok(tree);
+        }
+        let tree = expand(self.leaves.clone(), self.config.clone())?;
+        Ok(self.expanded.get_or_init(|| tree))
+    }
+
+    /// Rebuild the full tree, consuming the compact form.
+    pub fn into_tree(self) -> Result<MerkleTree> {
+        match self.expanded.into_inner() {
+            Some(tree) => Ok(tree),
+            None => expand(self.leaves, self.config),
+        }
+    }
+
+    /// Get the root hash, rebuilding the levels if needed.
+    pub fn root_hash_ref(&self) -> Result<&[u8]> {
+        self.tree()?.root_hash_ref()
+    }
+
+    /// Generate an inclusion proof, rebuilding the levels if needed.
+    pub fn generate_proof(&self, index: usize) -> Result<Vec<ProofNode>> {
+        self.tree()?.generate_proof(index)
+    }
+
+    /// Number of leaves.
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Get all leaf hashes.
+    pub fn get_leaves(&self) -> &[Hash] {
+        &self.leaves
+    }
+
+    /// Hashing parameters the tree was built with.
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    /// Serialize in the leaves-only layout, tagged with `format_version`.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&LeavesOnly {
+            format_version: FORMAT_VERSION,
+            leaves: &self.leaves,
+            config: &self.config,
+        })?)
+    }
+}
+
+/// The tree over `leaves`, or an empty tree keeping `config` if there are none.
+fn expand(leaves: Vec<Hash>, config: TreeConfig) -> Result<MerkleTree> {
+    if leaves.is_empty() {
+        return Ok(MerkleTree {
+            levels: vec![leaves],
+            config,
+        });
+    }
+    MerkleTree::from_leaves_with_config(leaves, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EMPTY_ROOT, MerkleError, OddStrategy};
+
+    #[test]
+    fn test_compacted_empty_tree() {
+        let compact = MerkleTree::empty().compact();
+        assert_eq!(compact.leaf_count(), 0);
+        assert_eq!(compact.root_hash_ref().unwrap(), EMPTY_ROOT);
+        assert!(compact.generate_proof(0).is_err());
+
+        let restored = MerkleTree::from_json(&compact.to_json().unwrap()).unwrap();
+        assert_eq!(restored.leaf_count(), 0);
+        assert_eq!(restored.root_hash_ref().unwrap(), EMPTY_ROOT);
+        assert_eq!(compact.into_tree().unwrap().tree_height(), 0);
+    }
+
+    #[test]
+    fn test_compacted_tree_reproduces_proofs() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                leaf_salt: b"salt".to_vec(),
+                ..TreeConfig::rfc6962()
+            },
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            let data: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::from_bytes_vec_with_config(&data, config).unwrap();
+            let full_json = tree.to_json().unwrap();
+            let proofs: Vec<_> = (0..7).map(|i| tree.generate_proof(i).unwrap()).collect();
+            let root = tree.root_hash_ref().unwrap().to_vec();
+
+            let compact = tree.clone().compact();
+            let compact_json = compact.to_json().unwrap();
+            assert_eq!(compact_json, tree.to_json_leaves_only().unwrap());
+            assert!(compact_json.len() < full_json.len());
+
+            let restored = MerkleTree::from_json(&compact_json).unwrap();
+            assert_eq!(restored.levels(), tree.levels());
+            assert_eq!(restored.config(), tree.config());
+            assert_eq!(restored.root_hash_ref().unwrap(), root.as_slice());
+            for (i, proof) in proofs.iter().enumerate() {
+                assert_eq!(&restored.generate_proof(i).unwrap(), proof);
+                assert_eq!(&compact.generate_proof(i).unwrap(), proof);
+            }
+            assert_eq!(compact.into_tree().unwrap().levels(), tree.levels());
+        }
+    }
+
+    #[test]
+    fn test_leaves_only_json_is_validated() {
+        // no leaves is the layout of an empty tree, not a malformed one
+        let empty = serde_json::json!({ "format_version": 1, "leaves": [] }).to_string();
+        assert_eq!(MerkleTree::from_json(&empty).unwrap().tree_height(), 0);
+
+        let ragged = serde_json::json!({ "leaves": [[1, 2], [3]] }).to_string();
+        assert!(matches!(
+            MerkleTree::from_json(&ragged),
+            Err(MerkleError::InconsistentHashLength { .. })
+        ));
+
+        let future = serde_json::json!({ "format_version": 2, "leaves": [[1]] }).to_string();
+        assert!(matches!(
+            MerkleTree::from_json(&future),
+            Err(MerkleError::UnsupportedFormatVersion { found: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,314 @@
+// Consistency proofs between two tree sizes (RFC 6962 / RFC 9162)
+//
+// A consistency proof shows that the tree of the first `old_size` leaves is a
+// prefix of the current tree, i.e. the log was only appended to.
+//
+// The proof relies on the RFC 6962 tree shape, where a tree of `n` leaves is
+// split at the largest power of two below `n`. Level-by-level pairing with
+// `OddStrategy::Promote` produces exactly that shape; duplication does not, so
+// `Duplicate` trees are rejected. `TreeConfig::rfc6962()` combined with
+// `OddStrategy::Promote` yields trees that are byte-for-byte RFC 6962 trees.
+
+use crate::{Hash, MerkleError, MerkleTree, OddStrategy, Result, TreeConfig, constant_time_eq};
+
+impl MerkleTree {
+    /// Prove that the first `old_size` leaves form a prefix of this tree.
+    ///
+    /// Returns the RFC 6962 `PROOF(old_size, D[n])` node list. An empty proof
+    /// is returned when `old_size` equals the current leaf count.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::UnsupportedOddStrategy` unless the tree uses
+    /// `OddStrategy::Promote`, and `MerkleError::IndexOutOfBounds` if
+    /// `old_size` is 0 or greater than the leaf count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, OddStrategy, TreeConfig, sha256};
+    ///
+    /// let config = TreeConfig { odd_strategy: OddStrategy::Promote, ..TreeConfig::default() };
+    /// let leaves: Vec<_> = (0..3u8).map(|i| sha256(&[i])).collect();
+    /// let old = MerkleTree::from_leaves_with_config(leaves[..2].to_vec(), config.clone())?;
+    /// let new = MerkleTree::from_leaves_with_config(leaves, config)?;
+    ///
+    /// let proof = new.consistency_proof(2)?;
+    /// assert!(MerkleTree::verify_consistency(
+    ///     old.root_hash_ref()?,
+    ///     new.root_hash_ref()?,
+    ///     2,
+    ///     3,
+    ///     &proof,
+    /// ));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn consistency_proof(&self, old_size: usize) -> Result<Vec<Hash>> {
+        if self.config.odd_strategy != OddStrategy::Promote {
+            return Err(MerkleError::UnsupportedOddStrategy {
+                required: OddStrategy::Promote,
+            });
+        }
+        let leaf_count = self.leaf_count();
+        if old_size == 0 || old_size > leaf_count {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: old_size,
+                leaf_count,
+            });
+        }
+
+        let mut proof = Vec::new();
+        self.subproof(old_size, 0, leaf_count, true, &mut proof);
+        Ok(proof)
+    }
+
+    /// RFC 6962 `SUBPROOF(m, D[start:end], b)`, appending nodes to `proof`.
+    fn subproof(&self, m: usize, start: usize, end: usize, complete: bool, proof: &mut Vec<Hash>) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                proof.push(self.range_hash(start, end));
+            }
+            return;
+        }
+
+        let k = largest_power_of_two_below(n);
+        if m <= k {
+            self.subproof(m, start, start + k, complete, proof);
+            proof.push(self.range_hash(start + k, end));
+        } else {
+            self.subproof(m - k, start + k, end, false, proof);
+            proof.push(self.range_hash(start, start + k));
+        }
+    }
+
+    /// RFC 6962 `MTH(D[start:end])` for a range of this tree's leaves.
+    ///
+    /// Aligned power-of-two ranges are read straight from `levels`; other
+    /// ranges are split at the largest power of two, matching the
+    /// `Promote` tree shape.
+    pub(crate) fn range_hash(&self, start: usize, end: usize) -> Hash {
+        let n = end - start;
+        if n.is_power_of_two() && start & (n - 1) == 0 {
+            let level = n.trailing_zeros() as usize;
+            return self.levels[level][start >> level].clone();
+        }
+
+        let k = largest_power_of_two_below(n);
+        self.config.hash_concat(
+            &self.range_hash(start, start + k),
+            &self.range_hash(start + k, end),
+        )
+    }
+
+    /// Verify a consistency proof for SHA-256 trees built with `OddStrategy::Promote`.
+    ///
+    /// See `verify_consistency_with_config` for other configurations.
+    pub fn verify_consistency(
+        old_root: &[u8],
+        new_root: &[u8],
+        old_size: usize,
+        new_size: usize,
+        proof: &[Hash],
+    ) -> bool {
+        let config = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..TreeConfig::default()
+        };
+        Self::verify_consistency_with_config(old_root, new_root, old_size, new_size, proof, &config)
+    }
+
+    /// Verify a consistency proof (RFC 9162 section 2.1.4.2) under `config`.
+    ///
+    /// Returns false for `Duplicate` configs, for which consistency proofs are
+    /// not defined.
+    pub fn verify_consistency_with_config(
+        old_root: &[u8],
+        new_root: &[u8],
+        old_size: usize,
+        new_size: usize,
+        proof: &[Hash],
+        config: &TreeConfig,
+    ) -> bool {
+        if config.odd_strategy != OddStrategy::Promote || old_size == 0 || old_size > new_size {
+            return false;
+        }
+        if old_size == new_size {
+            return proof.is_empty() && constant_time_eq(old_root, new_root);
+        }
+
+        let mut path: Vec<&[u8]> = proof.iter().map(|h| h.as_slice()).collect();
+        if old_size.is_power_of_two() {
+            path.insert(0, old_root);
+        }
+        let Some((first, rest)) = path.split_first() else {
+            return false;
+        };
+
+        let mut fn_ = old_size - 1;
+        let mut sn = new_size - 1;
+        while fn_ & 1 == 1 {
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+
+        let mut fr: Hash = first.to_vec();
+        let mut sr: Hash = first.to_vec();
+        for c in rest {
+            if sn == 0 {
+                return false;
+            }
+            if fn_ & 1 == 1 || fn_ == sn {
+                fr = config.hash_concat(c, &fr);
+                sr = config.hash_concat(c, &sr);
+                while fn_ & 1 == 0 && fn_ != 0 {
+                    fn_ >>= 1;
+                    sn >>= 1;
+                }
+            } else {
+                sr = config.hash_concat(&sr, c);
+            }
+            fn_ >>= 1;
+            sn >>= 1;
+        }
+
+        sn == 0 && constant_time_eq(&fr, old_root) && constant_time_eq(&sr, new_root)
+    }
+}
+
+/// Largest power of two strictly less than `n` (`n` must be at least 2).
+fn largest_power_of_two_below(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256;
+
+    fn promote(base: TreeConfig) -> TreeConfig {
+        TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..base
+        }
+    }
+
+    #[test]
+    fn test_largest_power_of_two_below() {
+        let cases = [(2, 1), (3, 2), (4, 2), (5, 4), (8, 4), (9, 8), (17, 16)];
+        for (n, expected) in cases {
+            assert_eq!(largest_power_of_two_below(n), expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn test_consistency_across_sizes() {
+        for config in [
+            promote(TreeConfig::default()),
+            promote(TreeConfig::rfc6962()),
+        ] {
+            let leaves: Vec<Hash> = (0..20u8).map(|i| sha256(&[i])).collect();
+
+            // Append leaf by leaf, recording each intermediate root
+            let mut tree =
+                MerkleTree::from_leaves_with_config(vec![leaves[0].clone()], config.clone())
+                    .unwrap();
+            let mut roots = vec![tree.root_hash_ref().unwrap().to_vec()];
+            for leaf in &leaves[1..] {
+                tree.push_leaf(leaf.clone()).unwrap();
+                roots.push(tree.root_hash_ref().unwrap().to_vec());
+            }
+
+            for new_size in 1..=leaves.len() {
+                let new_tree = MerkleTree::from_leaves_with_config(
+                    leaves[..new_size].to_vec(),
+                    config.clone(),
+                )
+                .unwrap();
+                for old_size in 1..=new_size {
+                    let proof = new_tree.consistency_proof(old_size).unwrap();
+                    assert!(
+                        MerkleTree::verify_consistency_with_config(
+                            &roots[old_size - 1],
+                            &roots[new_size - 1],
+                            old_size,
+                            new_size,
+                            &proof,
+                            &config
+                        ),
+                        "consistency {} -> {} should verify",
+                        old_size,
+                        new_size
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_consistency_rejects_forks_and_tampering() {
+        let config = promote(TreeConfig::default());
+        let leaves: Vec<Hash> = (0..10u8).map(|i| sha256(&[i])).collect();
+        let old =
+            MerkleTree::from_leaves_with_config(leaves[..6].to_vec(), config.clone()).unwrap();
+        let new = MerkleTree::from_leaves_with_config(leaves.clone(), config.clone()).unwrap();
+        let old_root = old.root_hash_ref().unwrap();
+        let new_root = new.root_hash_ref().unwrap();
+        let proof = new.consistency_proof(6).unwrap();
+        assert!(MerkleTree::verify_consistency(
+            old_root, new_root, 6, 10, &proof
+        ));
+
+        // History rewritten: leaf 2 replaced in the new tree
+        let mut forked_leaves = leaves.clone();
+        forked_leaves[2] = sha256(b"rewritten");
+        let forked = MerkleTree::from_leaves_with_config(forked_leaves, config).unwrap();
+        let forked_proof = forked.consistency_proof(6).unwrap();
+        assert!(!MerkleTree::verify_consistency(
+            old_root,
+            forked.root_hash_ref().unwrap(),
+            6,
+            10,
+            &forked_proof
+        ));
+
+        let mut tampered = proof.clone();
+        tampered[0][0] ^= 0xff;
+        assert!(!MerkleTree::verify_consistency(
+            old_root, new_root, 6, 10, &tampered
+        ));
+        assert!(!MerkleTree::verify_consistency(
+            old_root, new_root, 5, 10, &proof
+        ));
+        assert!(!MerkleTree::verify_consistency(
+            old_root,
+            new_root,
+            6,
+            10,
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_consistency_requires_promote() {
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        assert!(matches!(
+            tree.consistency_proof(1),
+            Err(MerkleError::UnsupportedOddStrategy { .. })
+        ));
+
+        let tree = MerkleTree::from_bytes_vec_with_config(
+            &[b"a".to_vec(), b"b".to_vec()],
+            promote(TreeConfig::default()),
+        )
+        .unwrap();
+        assert!(matches!(
+            tree.consistency_proof(0),
+            Err(MerkleError::IndexOutOfBounds { .. })
+        ));
+        assert!(matches!(
+            tree.consistency_proof(3),
+            Err(MerkleError::IndexOutOfBounds { .. })
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+// Building trees from directories on disk
+//
+// Files are identified by their path relative to the root directory, with
+// components joined by `/` on every platform, and leaves are ordered by that
+// string. This keeps roots identical across operating systems.
+//
+// Files are read and hashed on a bounded pool of threads, each claiming the
+// next unread index, and every leaf is stored at its file's index, so the
+// leaf order never depends on which thread finishes first.
+
+use std::fs::{self, File};
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::{Hash, MerkleTree, Result, TreeConfig};
+
+/// List the files under `dir` as sorted, `/`-separated relative paths.
+///
+/// Without `recursive` only regular files directly inside `dir` are listed;
+/// with it, subdirectories are walked as well. Symlinks are skipped in both
+/// modes so a link cannot pull files from outside `dir` into the tree.
+///
+/// # Errors
+///
+/// Returns `MerkleError::Io` if a directory cannot be read or a file name is
+/// not valid UTF-8.
+pub fn directory_entries(dir: &Path, recursive: bool) -> Result<Vec<String>> {
+    let mut entries = Vec::new();
+    collect_entries(dir, "", recursive, &mut entries)?;
+    entries.sort();
+    Ok(entries)
+}
+
+fn collect_entries(dir: &Path, prefix: &str, recursive: bool, out: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("non UTF-8 file name: {:?}", name),
+            )
+        })?;
+        let relative = format!("{}{}", prefix, name);
+
+        let file_type = entry.file_type()?;
+        if file_type.is_file() {
+            out.push(relative);
+        } else if file_type.is_dir() && recursive {
+            collect_entries(&entry.path(), &format!("{}/", relative), recursive, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hash every file in `paths` under `config`, reading up to `threads` files
+/// at once; leaves come back in the order of `paths`.
+///
+/// If several files fail, the error of the first one in `paths` is returned.
+fn hash_files(paths: &[PathBuf], config: &TreeConfig, threads: usize) -> Result<Vec<Hash>> {
+    let threads = threads.clamp(1, paths.len().max(1));
+    let next = AtomicUsize::new(0);
+    let mut leaves: Vec<Option<Result<Hash>>> = (0..paths.len()).map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut hashed = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(index) else {
+                            break;
+                        };
+                        let leaf = File::open(path)
+                            .map_err(Into::into)
+                            .and_then(|file| config.hash_leaf_reader(file));
+                        hashed.push((index, leaf));
+                    }
+                    hashed
+                })
+            })
+            .collect();
+        for worker in workers {
+            let hashed = worker
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            for (index, leaf) in hashed {
+                leaves[index] = Some(leaf);
+            }
+        }
+    });
+
+    // every index was claimed by exactly one worker, so nothing is dropped
+    leaves.into_iter().flatten().collect()
+}
+
+/// Number of file-reading threads used by default: one per available CPU.
+fn default_read_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+impl MerkleTree {
+    /// Build from the files under `dir`, ordered by relative path.
+    ///
+    /// Leaves are the SHA-256 hashes of the file contents, streamed from disk,
+    /// in the order returned by `directory_entries(dir, recursive)`. Files
+    /// are read on one thread per available CPU; see
+    /// `from_directory_with_threads`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if no files are found, or
+    /// `MerkleError::Io` if the directory or a file cannot be read.
+    pub fn from_directory(dir: &Path, recursive: bool) -> Result<Self> {
+        MerkleTree::from_directory_with_config(dir, recursive, TreeConfig::default())
+    }
+
+    /// Build from the files under `dir`, hashing leaves under `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if no files are found, or
+    /// `MerkleError::Io` if the directory or a file cannot be read.
+    pub fn from_directory_with_config(
+        dir: &Path,
+        recursive: bool,
+        config: TreeConfig,
+    ) -> Result<Self> {
+        MerkleTree::from_directory_with_threads(dir, recursive, config, default_read_threads())
+    }
+
+    /// Build from the files under `dir`, reading at most `threads` files at
+    /// once (at least one).
+    ///
+    /// Reading is I/O-bound, so more threads than CPUs can pay off on fast
+    /// storage; the result is the same for every thread count.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if no files are found, or
+    /// `MerkleError::Io` if the directory or a file cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use merkle::{MerkleTree, TreeConfig};
+    ///
+    /// let tree = MerkleTree::from_directory_with_threads(
+    ///     Path::new("./files"),
+    ///     true,
+    ///     TreeConfig::default(),
+    ///     16,
+    /// )?;
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_directory_with_threads(
+        dir: &Path,
+        recursive: bool,
+        config: TreeConfig,
+        threads: usize,
+    ) -> Result<Self> {
+        let paths: Vec<PathBuf> = directory_entries(dir, recursive)?
+            .iter()
+            .map(|relative| dir.join(relative))
+            .collect();
+        let leaves = hash_files(&paths, &config, threads)?;
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleError;
+
+    /// Two-level fixture: files at the top, in `docs/` and in `docs/img/`.
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("docs/img")).unwrap();
+        fs::create_dir(root.join("empty")).unwrap();
+        fs::write(root.join("z.txt"), b"zulu").unwrap();
+        fs::write(root.join("a.txt"), b"alpha").unwrap();
+        fs::write(root.join("docs/readme.md"), b"readme").unwrap();
+        fs::write(root.join("docs/img/logo.png"), b"logo").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_directory_entries() {
+        let dir = fixture();
+        assert_eq!(
+            directory_entries(dir.path(), true).unwrap(),
+            vec!["a.txt", "docs/img/logo.png", "docs/readme.md", "z.txt"]
+        );
+        assert_eq!(
+            directory_entries(dir.path(), false).unwrap(),
+            vec!["a.txt", "z.txt"]
+        );
+    }
+
+    #[test]
+    fn test_recursive_root_is_stable() {
+        let expected = MerkleTree::from_bytes_vec(&[
+            b"alpha".to_vec(),
+            b"logo".to_vec(),
+            b"readme".to_vec(),
+            b"zulu".to_vec(),
+        ])
+        .unwrap();
+
+        // Fresh fixtures give fresh inode and read_dir orders
+        for _ in 0..3 {
+            let dir = fixture();
+            let tree = MerkleTree::from_directory(dir.path(), true).unwrap();
+            assert_eq!(
+                tree.root_hash_ref().unwrap(),
+                expected.root_hash_ref().unwrap()
+            );
+        }
+
+        let flat = MerkleTree::from_directory(fixture().path(), false).unwrap();
+        assert_eq!(flat.leaf_count(), 2);
+    }
+
+    #[test]
+    fn test_thread_count_does_not_change_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut contents = Vec::new();
+        for i in 0..300 {
+            let name = format!("f{:03}.txt", i);
+            fs::write(dir.path().join(&name), format!("file {}", i)).unwrap();
+            contents.push(format!("file {}", i).into_bytes());
+        }
+        let expected = MerkleTree::from_bytes_vec(&contents).unwrap();
+
+        for threads in [0, 1, 3, 8, 64, 1000] {
+            let tree = MerkleTree::from_directory_with_threads(
+                dir.path(),
+                false,
+                TreeConfig::default(),
+                threads,
+            )
+            .unwrap();
+            assert_eq!(tree.levels(), expected.levels(), "{} threads", threads);
+        }
+        let tree = MerkleTree::from_directory(dir.path(), false).unwrap();
+        assert_eq!(tree.levels(), expected.levels());
+    }
+
+    #[test]
+    fn test_empty_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        assert!(matches!(
+            MerkleTree::from_directory(dir.path(), true),
+            Err(MerkleError::EmptyLeaves)
+        ));
+    }
+}
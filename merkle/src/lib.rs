@@ -1,12 +1,45 @@
 // Merkle Tree Library
 //
-// A SHA-256 based Merkle tree implementation for verifiable data integrity in distributed systems.
+// A Merkle tree implementation for verifiable data integrity in distributed systems.
+// SHA-256 is used by default; SHA-512 and BLAKE3 are available via `HashAlgorithm`.
 
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::io::{self};
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 use thiserror::Error;
 
+mod binary;
+mod bundle;
+mod canonical;
+mod compact;
+mod consistency;
+mod directory;
+mod mmr;
+mod multiproof;
+mod nonmembership;
+mod ordering;
+mod range;
+mod sparse;
+#[cfg(feature = "instrument")]
+mod stats;
+mod streaming;
+
+pub use bundle::ProofBundle;
+pub use canonical::canonical_json;
+pub use compact::CompactTree;
+pub use directory::directory_entries;
+pub use mmr::{MerkleMountainRange, MmrProof};
+pub use multiproof::MultiProof;
+pub use nonmembership::{Neighbor, NonMembershipProof};
+pub use ordering::{SortStrategy, natural_cmp};
+pub use range::RangeProof;
+pub use sparse::{SparseKey, SparseMerkleTree, SparseProof};
+#[cfg(feature = "instrument")]
+pub use stats::{BuildStats, ProofStats};
+pub use streaming::LeafHasher;
+
 /// Type alias for backward compatibility
 pub type Hash = Vec<u8>;
 
@@ -30,398 +63,4477 @@ pub enum MerkleError {
 
     #[error("Proof verification failed")]
     VerificationFailed,
-}
 
-/// Result type for Merkle tree operations
-pub type Result<T> = std::result::Result<T, MerkleError>;
+    #[error("Operation requires the {required:?} odd strategy")]
+    UnsupportedOddStrategy { required: OddStrategy },
 
-/// A single item in a Merkle proof.
-///
-/// Contains the sibling hash and its position (left or right) needed to
-/// reconstruct the path from a leaf to the root.
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub struct ProofNode {
-    /// Sibling hash bytes
-    pub hash: Hash,
-    /// True if this sibling is on the left of the current node
-    pub is_left: bool,
-}
+    #[error("Malformed proof: expected {expected_len} nodes, got {actual_len}")]
+    MalformedProof {
+        expected_len: usize,
+        actual_len: usize,
+    },
 
-/// A Merkle tree for verifiable data integrity.
-///
-/// The tree is built from leaf hashes and stores all levels from leaves to root.
-/// Nodes at each level are paired and hashed together. When a level has an odd
-/// number of nodes, the last node is duplicated.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MerkleTree {
-    /// levels[0] = leaves, levels[1] = parent level, ... last level contains root only
-    levels: Vec<Vec<Hash>>,
+    #[error("Invalid binary proof: {len} bytes end partway through a {node_len}-byte node")]
+    InvalidProofLength { len: usize, node_len: usize },
+
+    #[error("Invalid binary proof node flag {0:#04x}")]
+    InvalidProofFlag(u8),
+
+    /// A single binary proof node is empty, has no hash after a side flag,
+    /// or has one after the duplicate flag.
+    #[error("Invalid binary proof node of {len} bytes")]
+    InvalidProofNode { len: usize },
+
+    #[error("Inconsistent hash length: expected {expected} bytes, found {found}")]
+    InconsistentHashLength { expected: usize, found: usize },
+
+    #[error("Unsupported tree format version {found} (supported up to {supported})")]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+
+    /// A hex string failed to decode; `index` is its position in the input
+    /// list (or argument list), and 0 for a single value such as a root.
+    #[error("Invalid hex at index {index}: {source}")]
+    InvalidHex {
+        index: usize,
+        source: hex::FromHexError,
+    },
+
+    #[error("Leaves are not in ascending order")]
+    UnsortedLeaves,
+
+    #[error("Value is present in the tree at index {index}")]
+    LeafPresent { index: usize },
+
+    #[error("Trees were built with different configs")]
+    ConfigMismatch,
+
+    /// Precomputed levels do not have the shape of a tree; a level past the
+    /// root is reported with `expected` 0, a missing one with `found` 0.
+    #[error("Level {level} has {found} nodes, expected {expected}")]
+    InvalidLevelSize {
+        level: usize,
+        expected: usize,
+        found: usize,
+    },
 }
 
-impl MerkleTree {
-    /// Build from raw file bytes (hash each file with SHA-256).
-    /// # Arguments
-    ///
-    /// * `files` - Vector of bytes
-    ///
-    /// # Errors
+impl MerkleError {
+    /// Whether the error stems from the caller's input rather than a failure
+    /// inside the crate or the environment.
     ///
-    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty.
+    /// Servers can use this to answer with a 4xx instead of a 500: bad
+    /// indices, unknown leaves, empty inputs and invalid proofs are client
+    /// errors, while I/O and serialization failures are internal.
     ///
     /// # Examples
     ///
     /// ```
     /// use merkle::MerkleTree;
     ///
-    /// let files = vec![b"file1".to_vec(), b"file2".to_vec()];
-    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec()])?;
+    /// let err = tree.generate_proof(5).unwrap_err();
+    /// assert!(err.is_client_error());
     /// # Ok::<(), merkle::MerkleError>(())
     /// ```
-    pub fn from_bytes_vec(files: &[Vec<u8>]) -> Result<Self> {
-        let leaves: Vec<Hash> = files.iter().map(|b| sha256(b)).collect();
-        MerkleTree::from_leaves(leaves)
+    pub fn is_client_error(&self) -> bool {
+        match self {
+            MerkleError::EmptyLeaves
+            | MerkleError::IndexOutOfBounds { .. }
+            | MerkleError::LeafNotFound
+            | MerkleError::VerificationFailed
+            | MerkleError::UnsupportedOddStrategy { .. }
+            | MerkleError::MalformedProof { .. }
+            | MerkleError::InvalidProofLength { .. }
+            | MerkleError::InvalidProofFlag(_)
+            | MerkleError::InvalidProofNode { .. }
+            | MerkleError::InconsistentHashLength { .. }
+            | MerkleError::UnsupportedFormatVersion { .. }
+            | MerkleError::InvalidHex { .. }
+            | MerkleError::UnsortedLeaves
+            | MerkleError::LeafPresent { .. }
+            | MerkleError::ConfigMismatch
+            | MerkleError::InvalidLevelSize { .. } => true,
+            MerkleError::Io(_) | MerkleError::Json(_) => false,
+        }
     }
+}
 
-    /// Build a Merkle tree from leaf hashes.
-    fn from_leaves(leaves: Vec<Hash>) -> Result<Self> {
-        if leaves.is_empty() {
-            return Err(MerkleError::EmptyLeaves);
+/// Result type for Merkle tree operations
+pub type Result<T> = std::result::Result<T, MerkleError>;
+
+/// Hash function used for leaves and internal nodes.
+///
+/// The algorithm is stored alongside the tree so that serialized trees
+/// reconstruct with the same hashing behavior.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// SHA-256 (32-byte digests). The default.
+    #[default]
+    Sha256,
+    /// SHA-512 (64-byte digests).
+    Sha512,
+    /// BLAKE3 (32-byte digests).
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Compute the digest of `bytes` with this algorithm.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{HashAlgorithm, sha256};
+    ///
+    /// assert_eq!(HashAlgorithm::Sha256.digest(b"abc"), sha256(b"abc"));
+    /// assert_eq!(HashAlgorithm::Sha512.digest(b"abc").len(), 64);
+    /// ```
+    pub fn digest(&self, bytes: &[u8]) -> Hash {
+        self.digest_parts(&[bytes])
+    }
+
+    /// Root of an empty tree under this algorithm: the digest of the empty
+    /// string. `EMPTY_ROOT` is the SHA-256 one.
+    pub fn empty_root(&self) -> &'static [u8] {
+        match self {
+            HashAlgorithm::Sha256 => &EMPTY_ROOT,
+            HashAlgorithm::Sha512 => &EMPTY_ROOT_SHA512,
+            HashAlgorithm::Blake3 => &EMPTY_ROOT_BLAKE3,
         }
+    }
 
-        let mut levels: Vec<Vec<Hash>> = Vec::new();
-        levels.push(leaves);
+    /// Length in bytes of the digests this algorithm produces.
+    pub fn output_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
 
-        while levels.last().ok_or(MerkleError::EmptyLeaves)?.len() > 1 {
-            let current = levels.last().ok_or(MerkleError::EmptyLeaves)?;
-            let mut next_level: Vec<Hash> = Vec::with_capacity((current.len() + 1) / 2);
+    /// Hash the concatenation of `parts` without allocating an intermediate buffer.
+    pub(crate) fn digest_parts(&self, parts: &[&[u8]]) -> Hash {
+        let mut state = DigestState::new(*self);
+        for part in parts {
+            state.update(part);
+        }
+        state.finalize()
+    }
+}
 
-            let mut i = 0;
-            while i < current.len() {
-                let left = &current[i];
-                let right = if i + 1 < current.len() {
-                    &current[i + 1]
-                } else {
-                    left // duplicate last if odd
-                };
-                let parent = hash_concat(left, right);
-                next_level.push(parent);
-                i += 2;
+/// Incremental hasher state for any `HashAlgorithm`.
+#[derive(Clone)]
+pub(crate) enum DigestState {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl DigestState {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => DigestState::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => DigestState::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => DigestState::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match self {
+            DigestState::Sha256(h) => h.update(bytes),
+            DigestState::Sha512(h) => h.update(bytes),
+            DigestState::Blake3(h) => {
+                h.update(bytes);
             }
-            levels.push(next_level);
         }
+    }
 
-        Ok(MerkleTree { levels })
+    pub(crate) fn finalize(self) -> Hash {
+        match self {
+            DigestState::Sha256(h) => h.finalize().to_vec(),
+            DigestState::Sha512(h) => h.finalize().to_vec(),
+            DigestState::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        }
     }
 
-    /// Generate Merkle proof for a leaf at `index` (0-based).
+    /// Write the digest into `out`, reusing its allocation.
+    pub(crate) fn finalize_into(self, out: &mut Vec<u8>) {
+        out.clear();
+        match self {
+            DigestState::Sha256(h) => out.extend_from_slice(&h.finalize()),
+            DigestState::Sha512(h) => out.extend_from_slice(&h.finalize()),
+            DigestState::Blake3(h) => out.extend_from_slice(h.finalize().as_bytes()),
+        }
+    }
+}
+
+/// Chunk size used when streaming file contents into a hasher.
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Levels with fewer nodes than this are hashed serially even with the
+/// `rayon` feature, since thread dispatch would outweigh the hashing work.
+#[cfg(feature = "rayon")]
+const PARALLEL_THRESHOLD: usize = 1024;
+
+/// Domain separation tag prepended to leaf data (RFC 6962).
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// Domain separation tag prepended to internal node pairs (RFC 6962).
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// Layout version written by `MerkleTree::to_json`.
+///
+/// Bump it whenever the serialized layout changes so that older builds refuse
+/// newer blobs instead of misreading them.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// File names the server keeps its metadata under, which uploaded files may
+/// not take. The client checks names against the same list before uploading.
+pub const RESERVED_NAMES: &[&str] = &[
+    "manifest.json",
+    "root.hex",
+    "tree.json",
+    "tree.json.zst",
+    "leaves.hex",
+    ".upload-staging",
+];
+
+/// Root of `MerkleTree::empty()`: SHA-256 of the empty string, as in RFC 6962.
+pub const EMPTY_ROOT: [u8; 32] = [
+    0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
+    0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55,
+];
+
+const EMPTY_ROOT_SHA512: [u8; 64] = [
+    0xcf, 0x83, 0xe1, 0x35, 0x7e, 0xef, 0xb8, 0xbd, 0xf1, 0x54, 0x28, 0x50, 0xd6, 0x6d, 0x80, 0x07,
+    0xd6, 0x20, 0xe4, 0x05, 0x0b, 0x57, 0x15, 0xdc, 0x83, 0xf4, 0xa9, 0x21, 0xd3, 0x6c, 0xe9, 0xce,
+    0x47, 0xd0, 0xd1, 0x3c, 0x5d, 0x85, 0xf2, 0xb0, 0xff, 0x83, 0x18, 0xd2, 0x87, 0x7e, 0xec, 0x2f,
+    0x63, 0xb9, 0x31, 0xbd, 0x47, 0x41, 0x7a, 0x81, 0xa5, 0x38, 0x32, 0x7a, 0xf9, 0x27, 0xda, 0x3e,
+];
+
+const EMPTY_ROOT_BLAKE3: [u8; 32] = [
+    0xaf, 0x13, 0x49, 0xb9, 0xf5, 0xf9, 0xa1, 0xa6, 0xa0, 0x40, 0x4d, 0xea, 0x36, 0xdc, 0xc9, 0x49,
+    0x9b, 0xcb, 0x25, 0xc9, 0xad, 0xc1, 0x12, 0xb7, 0xcc, 0x9a, 0x93, 0xca, 0xe4, 0x1f, 0x32, 0x62,
+];
+
+/// How a level with an odd number of nodes produces its last parent.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OddStrategy {
+    /// Pair the last node with itself (Bitcoin-style). The default.
+    #[default]
+    Duplicate,
+    /// Carry the last node up to the next level unchanged.
     ///
-    /// Returns a vector of ProofNode ordered from leaf-level upward.
+    /// Avoids the duplicate-leaf forgery where `[a, b, c]` and `[a, b, c, c]`
+    /// share a root. Proofs omit the missing sibling at promoted levels.
+    Promote,
+}
+
+/// How the two children of a node are ordered before hashing.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderedPairs {
+    /// Hash `left || right` by tree position. The default.
+    #[default]
+    Positional,
+    /// Hash `min(a, b) || max(a, b)` (byte-wise), as OpenZeppelin-style
+    /// verifiers do. Proofs then verify regardless of `ProofNode::is_left`.
+    Sorted,
+}
+
+/// Hashing parameters a tree is built with.
+///
+/// Proofs only verify under the same configuration the tree was built with,
+/// so the config is serialized alongside the tree.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeConfig {
+    /// Hash function for leaves and internal nodes
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    /// Tag leaves with `0x00` and internal nodes with `0x01` before hashing (RFC 6962).
     ///
-    /// # Errors
+    /// Without the tags an internal node value can be presented as a leaf,
+    /// enabling second-preimage attacks against the tree.
+    #[serde(default)]
+    pub domain_separation: bool,
+    /// Handling of the last node on odd-sized levels
+    #[serde(default)]
+    pub odd_strategy: OddStrategy,
+    /// Child ordering applied before hashing each pair
+    #[serde(default)]
+    pub pair_ordering: OrderedPairs,
+    /// Per-tree salt hashed in front of every leaf's contents (hex when serialized).
     ///
-    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    /// Unlike domain separation this randomizes leaves per tree, so identical
+    /// files in differently salted trees get unrelated leaf hashes. Empty by
+    /// default, which leaves hashing unchanged.
+    #[serde(
+        default,
+        with = "bundle::hex_serde",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub leaf_salt: Vec<u8>,
+    /// Prefix both children with their byte length, as a little-endian `u64`,
+    /// when hashing a parent: `len || left || len || right`.
+    ///
+    /// Equal-width children always split at the same point, but once nodes
+    /// of different lengths share a level (e.g. a short leaf promoted next
+    /// to full digests) `left || right` alone no longer says where one child
+    /// ends. Off by default, which leaves hashing unchanged.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub length_prefixed_nodes: bool,
+}
+
+impl TreeConfig {
+    /// Config using `algorithm` without domain separation.
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
+        TreeConfig {
+            algorithm,
+            ..TreeConfig::default()
+        }
+    }
+
+    /// RFC 6962 config: SHA-256 with leaf/internal node domain separation.
+    pub fn rfc6962() -> Self {
+        TreeConfig {
+            algorithm: HashAlgorithm::Sha256,
+            domain_separation: true,
+            ..TreeConfig::default()
+        }
+    }
+
+    /// SHA-256 config that length-prefixes both children of every parent.
     ///
     /// # Examples
     ///
     /// ```
-    /// use merkle::{MerkleTree, sha256};
+    /// use merkle::{MerkleTree, TreeConfig, sha256};
     ///
-    /// let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
-    /// let tree = MerkleTree::from_bytes_vec(&files)?;
-    /// let proof = tree.generate_proof(1)?;
+    /// let leaves = vec![sha256(b"a"), sha256(b"b")];
+    /// let prefixed = MerkleTree::from_leaves_with_config(leaves.clone(), TreeConfig::length_prefixed())?;
+    /// let plain = MerkleTree::from_leaves(leaves)?;
+    /// assert_ne!(prefixed.root_hash_ref()?, plain.root_hash_ref()?);
     /// # Ok::<(), merkle::MerkleError>(())
     /// ```
-    pub fn generate_proof(&self, mut index: usize) -> Result<Vec<ProofNode>> {
-        if index >= self.leaf_count() {
-            return Err(MerkleError::IndexOutOfBounds {
-                index,
-                leaf_count: self.leaf_count(),
-            });
+    pub fn length_prefixed() -> Self {
+        TreeConfig {
+            length_prefixed_nodes: true,
+            ..TreeConfig::default()
         }
+    }
 
-        let mut proof: Vec<ProofNode> = Vec::with_capacity(self.levels.len() - 1);
-
-        for level in 0..(self.levels.len() - 1) {
-            let level_nodes = &self.levels[level];
-            let is_right = index % 2 == 1;
-            let sibling_index = if is_right { index - 1 } else { index + 1 };
-
-            // if sibling index beyond bounds, sibling is the same node (duplication)
-            let sibling_hash = if sibling_index < level_nodes.len() {
-                level_nodes[sibling_index].clone()
-            } else {
-                level_nodes[index].clone()
-            };
-
-            proof.push(ProofNode {
-                hash: sibling_hash,
-                is_left: is_right, // if current is right, the sibling is left
-            });
-
-            // move to parent index
-            index /= 2;
+    /// Config hashing every leaf as `leaf_salt || contents`.
+    pub fn with_leaf_salt(leaf_salt: &[u8]) -> Self {
+        TreeConfig {
+            leaf_salt: leaf_salt.to_vec(),
+            ..TreeConfig::default()
         }
+    }
 
-        Ok(proof)
+    /// Compute the leaf hash for raw leaf data.
+    ///
+    /// The digest input is `LEAF_PREFIX` (with domain separation), then
+    /// `leaf_salt`, then `data`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{TreeConfig, sha256};
+    ///
+    /// assert_eq!(TreeConfig::default().hash_leaf(b"a"), sha256(b"a"));
+    /// assert_eq!(TreeConfig::rfc6962().hash_leaf(b"a"), sha256(b"\x00a"));
+    /// ```
+    pub fn hash_leaf(&self, data: &[u8]) -> Hash {
+        self.algorithm
+            .digest_parts(&[self.leaf_prefix(), &self.leaf_salt, data])
     }
 
-    /// Verify a proof against this tree's root.
+    /// Compute the leaf hash of everything `reader` yields, reading in
+    /// `READ_CHUNK_SIZE` chunks instead of buffering the whole input.
+    ///
+    /// Produces the same hash as `hash_leaf` over the full contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::Io` if reading fails.
+    pub fn hash_leaf_reader<R: Read>(&self, reader: R) -> Result<Hash> {
+        Self::finish_reader(self.leaf_state(), reader)
+    }
+
+    /// Compute a leaf hash that binds `name` to `data`.
+    ///
+    /// The digest input is, in order:
+    ///
+    /// 1. `LEAF_PREFIX` (one byte), only when domain separation is enabled
+    /// 2. `leaf_salt`, empty unless set
+    /// 3. the byte length of `name` as a little-endian `u64` (8 bytes), as
+    ///    for the child lengths of `length_prefixed_nodes`
+    /// 4. `name` as UTF-8
+    /// 5. `data`
+    ///
+    /// The length prefix keeps the boundary between name and contents
+    /// unambiguous, so `("ab", "c")` and `("a", "bc")` hash differently.
     ///
     /// # Examples
     ///
     /// ```
-    /// use merkle::{MerkleTree, sha256};
+    /// use merkle::{TreeConfig, sha256};
     ///
-    /// let files = vec![b"a".to_vec(), b"b".to_vec()];
-    /// let tree = MerkleTree::from_bytes_vec(&files)?;
-    /// let proof = tree.generate_proof(0)?;
-    /// let leaf_hash = sha256(b"a");
-    /// assert!(tree.verify(&leaf_hash, &proof)?);
-    /// # Ok::<(), merkle::MerkleError>(())
+    /// let leaf = TreeConfig::default().hash_named_leaf("a.txt", b"hi");
+    /// assert_eq!(leaf, sha256(b"\x05\0\0\0\0\0\0\0a.txthi"));
     /// ```
-    pub fn verify(&self, leaf_hash: &[u8], proof: &[ProofNode]) -> Result<bool> {
-        Ok(Self::verify_proof(leaf_hash, proof, self.root_hash_ref()?))
+    pub fn hash_named_leaf(&self, name: &str, data: &[u8]) -> Hash {
+        let name_len = (name.len() as u64).to_le_bytes();
+        self.algorithm.digest_parts(&[
+            self.leaf_prefix(),
+            &self.leaf_salt,
+            &name_len,
+            name.as_bytes(),
+            data,
+        ])
     }
 
-    /// Verify a proof: starting from leaf_hash, apply proof nodes to derive root and compare.
+    /// Streaming counterpart of `hash_named_leaf`, reading the contents from
+    /// `reader` in `READ_CHUNK_SIZE` chunks.
     ///
-    /// This is a static method for verifying proofs without needing the full tree.
-    pub fn verify_proof(leaf_hash: &[u8], proof: &[ProofNode], expected_root: &[u8]) -> bool {
-        let computed_root = Self::compute_root_from_proof(leaf_hash, proof);
-        computed_root == expected_root
+    /// # Errors
+    ///
+    /// Returns `MerkleError::Io` if reading fails.
+    pub fn hash_named_leaf_reader<R: Read>(&self, name: &str, reader: R) -> Result<Hash> {
+        let mut state = self.leaf_state();
+        state.update(&(name.len() as u64).to_le_bytes());
+        state.update(name.as_bytes());
+        Self::finish_reader(state, reader)
     }
 
-    /// Compute the root hash by applying a proof to a leaf hash.
-    fn compute_root_from_proof(leaf_hash: &[u8], proof: &[ProofNode]) -> Hash {
-        let mut current: Hash = leaf_hash.to_vec();
+    /// `LEAF_PREFIX` when domain separation is enabled, otherwise empty.
+    fn leaf_prefix(&self) -> &'static [u8] {
+        if self.domain_separation {
+            &[LEAF_PREFIX]
+        } else {
+            &[]
+        }
+    }
 
-        for node in proof {
-            if node.is_left {
-                // sibling is left: hash(sibling || current)
-                current = hash_concat(&node.hash, &current);
-            } else {
-                // sibling is right: hash(current || sibling)
-                current = hash_concat(&current, &node.hash);
-            }
+    /// Fresh digest state with the leaf prefix and salt applied.
+    pub(crate) fn leaf_state(&self) -> DigestState {
+        let mut state = DigestState::new(self.algorithm);
+        state.update(self.leaf_prefix());
+        state.update(&self.leaf_salt);
+        state
+    }
+
+    /// Feed everything `reader` yields into `state` and finalize it.
+    fn finish_reader<R: Read>(mut state: DigestState, mut reader: R) -> Result<Hash> {
+        let mut buf = vec![0u8; READ_CHUNK_SIZE];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            state.update(&buf[..n]);
         }
 
-        current
+        Ok(state.finalize())
     }
 
-    /// Return a reference to the root hash.
-    pub fn root_hash_ref(&self) -> Result<&[u8]> {
-        self.levels
-            .last()
-            .and_then(|level| level.first())
-            .map(|hash| hash.as_slice())
-            .ok_or(MerkleError::EmptyLeaves)
+    /// Hash concatenation helper for parent node computation.
+    ///
+    /// In `OrderedPairs::Sorted` mode the operands are ordered by value first,
+    /// which makes the position of each sibling irrelevant.
+    fn hash_concat(&self, left: &[u8], right: &[u8]) -> Hash {
+        self.concat_state(left, right).finalize()
     }
 
-    /// Number of leaves in the tree.
-    pub fn leaf_count(&self) -> usize {
-        self.levels[0].len()
+    /// Hasher state fed with a node pair, ready to finalize.
+    ///
+    /// Lets callers finalize into a buffer that held one of the operands.
+    fn concat_state(&self, left: &[u8], right: &[u8]) -> DigestState {
+        let (left, right) = match self.pair_ordering {
+            OrderedPairs::Sorted if right < left => (right, left),
+            _ => (left, right),
+        };
+        let mut state = DigestState::new(self.algorithm);
+        if self.domain_separation {
+            state.update(&[NODE_PREFIX]);
+        }
+        for child in [left, right] {
+            if self.length_prefixed_nodes {
+                state.update(&(child.len() as u64).to_le_bytes());
+            }
+            state.update(child);
+        }
+        state
     }
 
-    /// Height of the tree (number of levels).
-    pub fn tree_height(&self) -> usize {
-        self.levels.len()
+    /// Compute the parent at `parent_index` from the nodes of the level below.
+    fn parent_hash(&self, level_nodes: &[Hash], parent_index: usize) -> Hash {
+        let i = parent_index * 2;
+        let left = &level_nodes[i];
+        if i + 1 < level_nodes.len() {
+            self.hash_concat(left, &level_nodes[i + 1])
+        } else {
+            match self.odd_strategy {
+                OddStrategy::Duplicate => self.hash_concat(left, left), // duplicate last if odd
+                OddStrategy::Promote => left.clone(),
+            }
+        }
     }
 
-    /// Get all leaf hashes.
-    pub fn get_leaves(&self) -> &[Hash] {
-        &self.levels[0]
+    /// Hash one level into its parent level.
+    ///
+    /// With the `rayon` feature, levels of at least `PARALLEL_THRESHOLD`
+    /// nodes are hashed in parallel; the result is identical either way.
+    fn next_level(&self, level_nodes: &[Hash]) -> Vec<Hash> {
+        #[cfg(feature = "rayon")]
+        if level_nodes.len() >= PARALLEL_THRESHOLD {
+            return self.next_level_parallel(level_nodes);
+        }
+        self.next_level_serial(level_nodes)
     }
 
-    /// Serialize the tree to JSON.
-    pub fn to_json(&self) -> Result<String> {
-        Ok(serde_json::to_string(self)?)
+    fn next_level_serial(&self, level_nodes: &[Hash]) -> Vec<Hash> {
+        level_nodes
+            .chunks(2)
+            .map(|pair| self.parent_hash(pair, 0))
+            .collect()
     }
 
-    /// Deserialize a tree from JSON.
-    pub fn from_json(json: &str) -> Result<Self> {
-        Ok(serde_json::from_str(json)?)
+    #[cfg(feature = "rayon")]
+    fn next_level_parallel(&self, level_nodes: &[Hash]) -> Vec<Hash> {
+        use rayon::prelude::*;
+
+        level_nodes
+            .par_chunks(2)
+            .map(|pair| self.parent_hash(pair, 0))
+            .collect()
     }
 }
 
-/// Compute SHA-256 digest of data.
+/// Side of the current node on which a proof sibling sits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The sibling is the left input of the parent hash
+    Left,
+    /// The sibling is the right input of the parent hash
+    Right,
+}
+
+/// A single item in a Merkle proof.
 ///
-/// # Examples
+/// Contains the sibling hash and its position (left or right) needed to
+/// reconstruct the path from a leaf to the root.
 ///
-/// ```
-/// use merkle::sha256;
+/// Serializes as `{"hash": [...], "is_left": bool}`. Deserialization also
+/// accepts `{"hash": [...], "direction": "left" | "right"}`; a node giving
+/// both must have them agree. A duplicate node (see `ProofNode::duplicate`)
+/// adds `"duplicate": true` and has an empty hash.
 ///
-/// let hash = sha256(b"hello world");
-/// assert_eq!(hash.len(), 32);
-/// ```
-pub fn sha256(bytes: &[u8]) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(bytes);
-    hasher.finalize().to_vec()
-}
-
-/// Hash concatenation helper for parent node computation.
-fn hash_concat(left: &[u8], right: &[u8]) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(left);
-    hasher.update(right);
-    hasher.finalize().to_vec()
+/// The duplicate marker is private, so nodes are built with `left`, `right`,
+/// `new` or `duplicate` rather than a struct literal.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "ProofNodeRepr")]
+pub struct ProofNode {
+    /// Sibling hash bytes
+    pub hash: Hash,
+    /// True if this sibling is on the left of the current node
+    pub is_left: bool,
+    /// True if the sibling is a copy of the current node and `hash` is left
+    /// out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    duplicate: bool,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl ProofNode {
+    /// A sibling on the left: the parent is `hash(sibling || current)`.
+    pub fn left(hash: Hash) -> Self {
+        ProofNode {
+            hash,
+            is_left: true,
+            duplicate: false,
+        }
+    }
 
-    #[test]
-    fn test_single_leaf() {
-        let data = vec![b"single".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
-        assert_eq!(tree.leaf_count(), 1);
-        assert_eq!(tree.tree_height(), 1);
+    /// A sibling on the right: the parent is `hash(current || sibling)`.
+    pub fn right(hash: Hash) -> Self {
+        ProofNode {
+            hash,
+            is_left: false,
+            duplicate: false,
+        }
+    }
 
-        let proof = tree.generate_proof(0).unwrap();
-        assert!(proof.is_empty()); // single leaf has no siblings
+    /// A sibling on the given side of the current node.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{Direction, ProofNode, sha256};
+    ///
+    /// let node = ProofNode::new(sha256(b"a"), Direction::Left);
+    /// assert_eq!(node, ProofNode::left(sha256(b"a")));
+    /// assert_eq!(node.direction(), Direction::Left);
+    /// assert!(node.is_left);
+    /// ```
+    pub fn new(hash: Hash, direction: Direction) -> Self {
+        match direction {
+            Direction::Left => ProofNode::left(hash),
+            Direction::Right => ProofNode::right(hash),
+        }
+    }
 
-        let leaf_hash = sha256(b"single");
-        assert!(tree.verify(&leaf_hash, &proof).unwrap());
+    /// A sibling equal to the current node, as produced by duplicating the
+    /// odd last node of a level; its hash is left out (see
+    /// `MerkleTree::generate_proof_compressed`).
+    pub fn duplicate() -> Self {
+        ProofNode {
+            hash: Vec::new(),
+            is_left: false,
+            duplicate: true,
+        }
     }
 
-    #[test]
-    fn test_two_leaves() {
-        let data = vec![b"left".to_vec(), b"right".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
-        assert_eq!(tree.leaf_count(), 2);
-        assert_eq!(tree.tree_height(), 2);
+    /// Whether this node stands for a duplicate of the current node rather
+    /// than carrying a hash.
+    pub fn is_duplicate(&self) -> bool {
+        self.duplicate
+    }
+
+    /// Side of the current node on which this sibling sits.
+    pub fn direction(&self) -> Direction {
+        if self.is_left {
+            Direction::Left
+        } else {
+            Direction::Right
+        }
+    }
+}
+
+/// Either serialized form of a `ProofNode`.
+#[derive(Deserialize)]
+struct ProofNodeRepr {
+    hash: Hash,
+    is_left: Option<bool>,
+    direction: Option<Direction>,
+    #[serde(default)]
+    duplicate: bool,
+}
+
+impl TryFrom<ProofNodeRepr> for ProofNode {
+    type Error = String;
+
+    fn try_from(repr: ProofNodeRepr) -> std::result::Result<Self, Self::Error> {
+        if repr.duplicate {
+            if !repr.hash.is_empty() {
+                return Err("duplicate proof node must not carry a hash".to_string());
+            }
+            return Ok(ProofNode::duplicate());
+        }
+        let direction = match (repr.is_left, repr.direction) {
+            (Some(is_left), Some(direction)) if is_left != (direction == Direction::Left) => {
+                return Err(format!(
+                    "proof node has is_left {} but direction {:?}",
+                    is_left, direction
+                ));
+            }
+            (_, Some(direction)) => direction,
+            (Some(true), None) => Direction::Left,
+            (Some(false), None) => Direction::Right,
+            (None, None) => return Err("proof node needs is_left or direction".to_string()),
+        };
+        Ok(ProofNode::new(repr.hash, direction))
+    }
+}
+
+/// A Merkle tree for verifiable data integrity.
+///
+/// The tree is built from leaf hashes and stores all levels from leaves to root.
+/// Nodes at each level are paired and hashed together. When a level has an odd
+/// number of nodes, the last node is duplicated or promoted according to the
+/// tree's `OddStrategy`.
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "MerkleTreeRepr")]
+pub struct MerkleTree {
+    /// levels[0] = leaves, levels[1] = parent level, ... last level contains root only
+    levels: Vec<Vec<Hash>>,
+    /// Hashing parameters used for internal nodes (and leaves when built from bytes)
+    #[serde(flatten)]
+    config: TreeConfig,
+}
+
+/// Deserialized fields of a `MerkleTree`, before their shape is checked.
+#[derive(Deserialize)]
+struct MerkleTreeRepr {
+    levels: Vec<Vec<Hash>>,
+    #[serde(flatten)]
+    config: TreeConfig,
+}
+
+impl TryFrom<MerkleTreeRepr> for MerkleTree {
+    type Error = MerkleError;
+
+    fn try_from(repr: MerkleTreeRepr) -> Result<Self> {
+        MerkleTree::from_stored_levels(repr.levels, repr.config)
+    }
+}
+
+impl MerkleTree {
+    /// Levels read back from storage: the single empty level `empty()`
+    /// serializes to gives an empty tree again, anything else is checked by
+    /// `try_from_levels_with_config`.
+    fn from_stored_levels(levels: Vec<Vec<Hash>>, config: TreeConfig) -> Result<Self> {
+        match levels.as_slice() {
+            [leaves] if leaves.is_empty() => Ok(MerkleTree { levels, config }),
+            _ => MerkleTree::try_from_levels_with_config(levels, config),
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Build from raw file bytes (hash each file with SHA-256).
+    /// # Arguments
+    ///
+    /// * `files` - Vector of bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files = vec![b"file1".to_vec(), b"file2".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_bytes_vec(files: &[Vec<u8>]) -> Result<Self> {
+        MerkleTree::from_bytes_vec_with_algorithm(files, HashAlgorithm::default())
+    }
+
+    /// Build from raw file bytes, hashing each file with `algorithm`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the files vector is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{HashAlgorithm, MerkleTree};
+    ///
+    /// let files = vec![b"file1".to_vec(), b"file2".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec_with_algorithm(&files, HashAlgorithm::Blake3)?;
+    /// assert_eq!(tree.algorithm(), HashAlgorithm::Blake3);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_bytes_vec_with_algorithm(
+        files: &[Vec<u8>],
+        algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        MerkleTree::from_bytes_vec_with_config(files, TreeConfig::with_algorithm(algorithm))
+    }
+
+    /// Build from raw file bytes, hashing each file as `leaf_salt || contents`.
+    ///
+    /// The salt is stored in the tree's config and serialized with it; leaf
+    /// hashes for verification must come from `tree.config().hash_leaf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the files vector is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files = vec![b"file1".to_vec(), b"file2".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec_with_salt(&files, b"salt")?;
+    /// assert_eq!(tree.get_leaves()[0], sha256(b"saltfile1"));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_bytes_vec_with_salt(files: &[Vec<u8>], leaf_salt: &[u8]) -> Result<Self> {
+        MerkleTree::from_bytes_vec_with_config(files, TreeConfig::with_leaf_salt(leaf_salt))
+    }
+
+    /// Build from raw file bytes, hashing each file with `config.hash_leaf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the files vector is empty.
+    pub fn from_bytes_vec_with_config(files: &[Vec<u8>], config: TreeConfig) -> Result<Self> {
+        let leaves: Vec<Hash> = files.iter().map(|b| config.hash_leaf(b)).collect();
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// Build from an iterator of byte chunks, hashing each item as one leaf.
+    ///
+    /// Items are hashed as they are yielded and dropped immediately, so only
+    /// the leaf hashes are retained. Suited to lazily produced data such as
+    /// database rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the iterator yields nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let rows = (0..10u32).map(|i| i.to_be_bytes().to_vec());
+    /// let tree = MerkleTree::from_reader_iter(rows)?;
+    /// assert_eq!(tree.leaf_count(), 10);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_reader_iter<I, B>(items: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        MerkleTree::from_reader_iter_with_config(items, TreeConfig::default())
+    }
+
+    /// Build from an iterator of byte chunks, hashing each with `config.hash_leaf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the iterator yields nothing.
+    pub fn from_reader_iter_with_config<I, B>(items: I, config: TreeConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let leaves: Vec<Hash> = items
+            .into_iter()
+            .map(|item| config.hash_leaf(item.as_ref()))
+            .collect();
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// Build from file paths, streaming each file through SHA-256.
+    ///
+    /// Files are hashed in `READ_CHUNK_SIZE` chunks so no file is ever fully
+    /// loaded into memory. Leaves are identical to those of `from_bytes_vec`
+    /// over the same file contents, so roots match.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `paths` is empty, or
+    /// `MerkleError::Io` if a file cannot be read.
+    pub fn from_file_paths_streaming<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        MerkleTree::from_file_paths_streaming_with_config(paths, TreeConfig::default())
+    }
+
+    /// Build from file paths, streaming each file through `config.hash_leaf_reader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `paths` is empty, or
+    /// `MerkleError::Io` if a file cannot be read.
+    pub fn from_file_paths_streaming_with_config<P: AsRef<Path>>(
+        paths: &[P],
+        config: TreeConfig,
+    ) -> Result<Self> {
+        let leaves = paths
+            .iter()
+            .map(|p| config.hash_leaf_reader(File::open(p)?))
+            .collect::<Result<Vec<Hash>>>()?;
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// Build from `(name, contents)` pairs, binding each file name into its leaf.
+    ///
+    /// Leaves are `TreeConfig::hash_named_leaf(name, contents)`, so a proof
+    /// only verifies for the name it was generated under: serving one file's
+    /// contents under another's name fails verification. Entries are used in
+    /// the order given.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `entries` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, TreeConfig};
+    ///
+    /// let entries = vec![
+    ///     ("a.txt".to_string(), b"alpha".to_vec()),
+    ///     ("b.txt".to_string(), b"bravo".to_vec()),
+    /// ];
+    /// let tree = MerkleTree::from_named_files(&entries)?;
+    /// let proof = tree.generate_proof(1)?;
+    /// let leaf = TreeConfig::default().hash_named_leaf("b.txt", b"bravo");
+    /// assert!(tree.verify(&leaf, &proof)?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_named_files(entries: &[(String, Vec<u8>)]) -> Result<Self> {
+        MerkleTree::from_named_files_with_config(entries, TreeConfig::default())
+    }
+
+    /// Build from `(name, contents)` pairs, hashing leaves under `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `entries` is empty.
+    pub fn from_named_files_with_config(
+        entries: &[(String, Vec<u8>)],
+        config: TreeConfig,
+    ) -> Result<Self> {
+        let leaves = entries
+            .iter()
+            .map(|(name, contents)| config.hash_named_leaf(name, contents))
+            .collect();
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// Build from `(name, path)` pairs, streaming each file into a named leaf.
+    ///
+    /// Produces the same tree as `from_named_files` over the file contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `entries` is empty, or
+    /// `MerkleError::Io` if a file cannot be read.
+    pub fn from_named_file_paths_streaming<P: AsRef<Path>>(
+        entries: &[(String, P)],
+    ) -> Result<Self> {
+        MerkleTree::from_named_file_paths_streaming_with_config(entries, TreeConfig::default())
+    }
+
+    /// Build from `(name, path)` pairs, streaming each file into a named leaf
+    /// hashed under `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `entries` is empty, or
+    /// `MerkleError::Io` if a file cannot be read.
+    pub fn from_named_file_paths_streaming_with_config<P: AsRef<Path>>(
+        entries: &[(String, P)],
+        config: TreeConfig,
+    ) -> Result<Self> {
+        let leaves = entries
+            .iter()
+            .map(|(name, path)| config.hash_named_leaf_reader(name, File::open(path)?))
+            .collect::<Result<Vec<Hash>>>()?;
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// A SHA-256 tree with no leaves, for "nothing stored yet".
+    ///
+    /// Its root is `EMPTY_ROOT`, its leaf count and height are 0, and any
+    /// proof request fails with `MerkleError::IndexOutOfBounds`. Leaves can be
+    /// added with `push_leaf`. The constructors that take leaves still reject
+    /// empty input; `from_json` and `from_borsh` accept a serialized empty
+    /// tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{EMPTY_ROOT, MerkleTree, sha256};
+    ///
+    /// let mut tree = MerkleTree::empty();
+    /// assert_eq!(tree.root_hash_ref()?, EMPTY_ROOT);
+    /// assert_eq!(tree.leaf_count(), 0);
+    ///
+    /// tree.push_leaf(sha256(b"a"))?;
+    /// assert_eq!(tree.root_hash_ref()?, sha256(b"a"));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn empty() -> Self {
+        MerkleTree::empty_with_config(TreeConfig::default())
+    }
+
+    /// A tree with no leaves that hashes under `config`.
+    ///
+    /// Its root is `config.algorithm.empty_root()`, and the first leaf pushed
+    /// must be `config.algorithm.output_len()` bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{HashAlgorithm, MerkleTree, TreeConfig};
+    ///
+    /// let mut tree = MerkleTree::empty_with_config(TreeConfig::with_algorithm(HashAlgorithm::Sha512));
+    /// assert_eq!(tree.root_hash_ref()?, HashAlgorithm::Sha512.digest(b"").as_slice());
+    ///
+    /// tree.push_leaf(HashAlgorithm::Sha512.digest(b"a"))?;
+    /// assert_eq!(tree.leaf_count(), 1);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn empty_with_config(config: TreeConfig) -> Self {
+        MerkleTree {
+            levels: vec![Vec::new()],
+            config,
+        }
+    }
+
+    /// Build a Merkle tree from leaf hashes using SHA-256 for internal nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty, or
+    /// `MerkleError::InconsistentHashLength` if the leaves differ in length.
+    pub fn from_leaves(leaves: Vec<Hash>) -> Result<Self> {
+        MerkleTree::from_leaves_with_algorithm(leaves, HashAlgorithm::default())
+    }
+
+    /// Build a SHA-256 tree from hex-encoded leaf hashes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidHex` with the index of the first entry that
+    /// fails to decode, `MerkleError::EmptyLeaves` if `hexes` is empty, or
+    /// `MerkleError::InconsistentHashLength` if the decoded leaves differ in
+    /// length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let hexes = vec![hex::encode(sha256(b"a")), hex::encode(sha256(b"b"))];
+    /// let tree = MerkleTree::from_hex_leaves(&hexes)?;
+    /// assert_eq!(tree.get_leaves()[1], sha256(b"b"));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_hex_leaves(hexes: &[String]) -> Result<Self> {
+        let leaves = hexes
+            .iter()
+            .enumerate()
+            .map(|(index, h)| {
+                hex::decode(h).map_err(|source| MerkleError::InvalidHex { index, source })
+            })
+            .collect::<Result<Vec<Hash>>>()?;
+        MerkleTree::from_leaves(leaves)
+    }
+
+    /// Build a Merkle tree from leaf hashes using `algorithm` for internal nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty, or
+    /// `MerkleError::InconsistentHashLength` if the leaves differ in length.
+    pub fn from_leaves_with_algorithm(leaves: Vec<Hash>, algorithm: HashAlgorithm) -> Result<Self> {
+        MerkleTree::from_leaves_with_config(leaves, TreeConfig::with_algorithm(algorithm))
+    }
+
+    /// Build an RFC 6962 style tree with leaf/internal node domain separation.
+    ///
+    /// Each leaf value is tagged and hashed as `H(0x00 || leaf)`, and internal
+    /// nodes as `H(0x01 || left || right)`. Roots differ from trees built with
+    /// `from_leaves`, so both kinds of trees keep verifying independently.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, TreeConfig, sha256};
+    ///
+    /// let leaves = vec![sha256(b"a"), sha256(b"b")];
+    /// let tree = MerkleTree::from_leaves_rfc6962(leaves.clone())?;
+    /// let proof = tree.generate_proof(0)?;
+    /// let leaf_hash = TreeConfig::rfc6962().hash_leaf(&leaves[0]);
+    /// assert!(tree.verify(&leaf_hash, &proof)?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_leaves_rfc6962(leaves: Vec<Hash>) -> Result<Self> {
+        let config = TreeConfig::rfc6962();
+        let leaves = leaves.iter().map(|l| config.hash_leaf(l)).collect();
+        MerkleTree::from_leaves_with_config(leaves, config)
+    }
+
+    /// Build a Merkle tree from leaf hashes using `config` for internal nodes.
+    ///
+    /// Leaves are stored as given; use `TreeConfig::hash_leaf` to derive them
+    /// from raw data. They may have any length (e.g. truncated or 20-byte
+    /// digests) as long as every leaf has the same one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if the leaves vector is empty, or
+    /// `MerkleError::InconsistentHashLength` if the leaves differ in length.
+    pub fn from_leaves_with_config(leaves: Vec<Hash>, config: TreeConfig) -> Result<Self> {
+        let Some(first) = leaves.first() else {
+            return Err(MerkleError::EmptyLeaves);
+        };
+        let expected = first.len();
+        if let Some(leaf) = leaves.iter().find(|leaf| leaf.len() != expected) {
+            return Err(MerkleError::InconsistentHashLength {
+                expected,
+                found: leaf.len(),
+            });
+        }
+
+        let mut levels: Vec<Vec<Hash>> = Vec::new();
+        levels.push(leaves);
+
+        while levels.last().ok_or(MerkleError::EmptyLeaves)?.len() > 1 {
+            let current = levels.last().ok_or(MerkleError::EmptyLeaves)?;
+            let next_level = config.next_level(current);
+            levels.push(next_level);
+        }
+
+        Ok(MerkleTree { levels, config })
+    }
+
+    /// Build a SHA-256 tree from precomputed levels, checking their shape.
+    ///
+    /// See `try_from_levels_with_config`.
+    pub fn try_from_levels(levels: Vec<Vec<Hash>>) -> Result<Self> {
+        MerkleTree::try_from_levels_with_config(levels, TreeConfig::default())
+    }
+
+    /// Build a tree from precomputed levels without trusting their shape.
+    ///
+    /// Each level must have half as many nodes as the one below, rounded up,
+    /// up to a single root. Leaves must share one length and internal nodes
+    /// must be digests of `config`'s algorithm, except a node promoted
+    /// unchanged by `OddStrategy::Promote`. Node values are not rehashed, so
+    /// a well-shaped tree can still hold wrong hashes; proofs from it then
+    /// simply fail to verify.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if there are no leaves,
+    /// `MerkleError::InconsistentHashLength` for a node of the wrong length,
+    /// or `MerkleError::InvalidLevelSize` for a level of the wrong size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleError, MerkleTree};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let rebuilt = MerkleTree::try_from_levels(tree.levels().to_vec())?;
+    /// assert_eq!(rebuilt.root_hash_ref()?, tree.root_hash_ref()?);
+    ///
+    /// let mut truncated = tree.levels().to_vec();
+    /// truncated.pop();
+    /// assert!(matches!(
+    ///     MerkleTree::try_from_levels(truncated),
+    ///     Err(MerkleError::InvalidLevelSize { level: 2, expected: 1, found: 0 })
+    /// ));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn try_from_levels_with_config(levels: Vec<Vec<Hash>>, config: TreeConfig) -> Result<Self> {
+        let Some(first) = levels.first().and_then(|leaves| leaves.first()) else {
+            return Err(MerkleError::EmptyLeaves);
+        };
+        let leaf_len = first.len();
+        if let Some(leaf) = levels[0].iter().find(|leaf| leaf.len() != leaf_len) {
+            return Err(MerkleError::InconsistentHashLength {
+                expected: leaf_len,
+                found: leaf.len(),
+            });
+        }
+
+        let node_len = config.algorithm.output_len();
+        for level in 1..=levels.len() {
+            let children = &levels[level - 1];
+            let found = levels.get(level).map_or(0, Vec::len);
+            let expected = if children.len() > 1 {
+                children.len().div_ceil(2)
+            } else {
+                0
+            };
+            if found != expected {
+                return Err(MerkleError::InvalidLevelSize {
+                    level,
+                    expected,
+                    found,
+                });
+            }
+            for (index, node) in levels.get(level).into_iter().flatten().enumerate() {
+                let promoted = config.odd_strategy == OddStrategy::Promote
+                    && index == found - 1
+                    && children.len() % 2 == 1;
+                let expected = if promoted {
+                    children[index * 2].len()
+                } else {
+                    node_len
+                };
+                if node.len() != expected {
+                    return Err(MerkleError::InconsistentHashLength {
+                        expected,
+                        found: node.len(),
+                    });
+                }
+            }
+        }
+
+        Ok(MerkleTree { levels, config })
+    }
+
+    /// Append a leaf hash, recomputing only the path from the new leaf to the root.
+    ///
+    /// The new leaf is always the last node of every level it touches, so only
+    /// the last node of each level needs rehashing. The tree grows a level when
+    /// the leaf count crosses a power of two. The result is identical to
+    /// rebuilding with `from_leaves_with_config` over all leaves.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InconsistentHashLength` if `hash` differs in
+    /// length from the existing leaves or, in an empty tree, from the
+    /// algorithm's digest. The tree is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let mut tree = MerkleTree::from_leaves(vec![sha256(b"a")])?;
+    /// tree.push_leaf(sha256(b"b"))?;
+    /// let batch = MerkleTree::from_leaves(vec![sha256(b"a"), sha256(b"b")])?;
+    /// assert_eq!(tree.root_hash_ref()?, batch.root_hash_ref()?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn push_leaf(&mut self, hash: Hash) -> Result<()> {
+        let expected = self.levels[0]
+            .first()
+            .map_or(self.config.algorithm.output_len(), Vec::len);
+        if hash.len() != expected {
+            return Err(MerkleError::InconsistentHashLength {
+                expected,
+                found: hash.len(),
+            });
+        }
+        self.levels[0].push(hash);
+
+        let mut index = self.levels[0].len() - 1;
+        let mut level = 0;
+        while self.levels[level].len() > 1 {
+            let parent_index = index / 2;
+            let parent = self.config.parent_hash(&self.levels[level], parent_index);
+
+            if level + 1 == self.levels.len() {
+                self.levels.push(Vec::with_capacity(1));
+            }
+            let next_level = &mut self.levels[level + 1];
+            if parent_index < next_level.len() {
+                next_level[parent_index] = parent;
+            } else {
+                next_level.push(parent);
+            }
+
+            index = parent_index;
+            level += 1;
+        }
+        Ok(())
+    }
+
+    /// Combine two trees over adjacent leaf ranges into the tree over `left`'s
+    /// leaves followed by `right`'s.
+    ///
+    /// The result is identical to rebuilding over the combined leaves, but
+    /// levels are reused where they cannot change: while `left`'s level has
+    /// an even length its nodes pair only among themselves, so the bottom
+    /// `t + 1` levels, where `2^t` is the largest power of two dividing
+    /// `left`'s leaf count, are both trees' levels side by side. A `left`
+    /// built over a power-of-two range therefore only needs the levels above
+    /// its root hashed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::ConfigMismatch` if the trees were built with
+    /// different configs, or `MerkleError::InconsistentHashLength` if their
+    /// leaves differ in length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let leaves: Vec<_> = (0..6u8).map(|i| sha256(&[i])).collect();
+    /// let left = MerkleTree::from_leaves(leaves[..4].to_vec())?;
+    /// let right = MerkleTree::from_leaves(leaves[4..].to_vec())?;
+    /// let merged = MerkleTree::concat(left, right)?;
+    /// assert_eq!(merged.root_hash_ref()?, MerkleTree::from_leaves(leaves)?.root_hash_ref()?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn concat(left: MerkleTree, right: MerkleTree) -> Result<MerkleTree> {
+        if left.config != right.config {
+            return Err(MerkleError::ConfigMismatch);
+        }
+        if left.leaf_count() == 0 {
+            return Ok(right);
+        }
+        if right.leaf_count() == 0 {
+            return Ok(left);
+        }
+        let (expected, found) = (left.levels[0][0].len(), right.levels[0][0].len());
+        if expected != found {
+            return Err(MerkleError::InconsistentHashLength { expected, found });
+        }
+
+        let config = left.config;
+        let shared = left.levels[0].len().trailing_zeros() as usize;
+        let mut right_levels = right.levels.into_iter();
+        let mut right_part: Vec<Hash> = Vec::new();
+        let mut levels: Vec<Vec<Hash>> = Vec::new();
+        for mut level in left.levels.into_iter().take(shared + 1) {
+            // Above `right`'s root its last node keeps being paired (or
+            // promoted) at the odd end of the combined level
+            right_part = match right_levels.next() {
+                Some(right_level) => right_level,
+                None => config.next_level(&right_part),
+            };
+            level.extend(right_part.iter().cloned());
+            levels.push(level);
+        }
+
+        while let Some(current) = levels.last().filter(|level| level.len() > 1) {
+            let next_level = config.next_level(current);
+            levels.push(next_level);
+        }
+
+        Ok(MerkleTree { levels, config })
+    }
+
+    /// Replace the leaf at `index`, recomputing only its ancestors.
+    ///
+    /// Touches one node per level, so the cost is O(log n); the tree is left
+    /// identical to a rebuild over the updated leaves.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count, or
+    /// `MerkleError::InconsistentHashLength` if `new_hash` differs in length
+    /// from the existing leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let mut tree = MerkleTree::from_leaves(vec![sha256(b"a"), sha256(b"b")])?;
+    /// tree.update_leaf(1, sha256(b"c"))?;
+    /// let rebuilt = MerkleTree::from_leaves(vec![sha256(b"a"), sha256(b"c")])?;
+    /// assert_eq!(tree.root_hash_ref()?, rebuilt.root_hash_ref()?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn update_leaf(&mut self, mut index: usize, new_hash: Hash) -> Result<()> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+        let expected = self.levels[0][index].len();
+        if new_hash.len() != expected {
+            return Err(MerkleError::InconsistentHashLength {
+                expected,
+                found: new_hash.len(),
+            });
+        }
+
+        self.levels[0][index] = new_hash;
+        for level in 0..(self.levels.len() - 1) {
+            index /= 2;
+            let parent = self.config.parent_hash(&self.levels[level], index);
+            self.levels[level + 1][index] = parent;
+        }
+        Ok(())
+    }
+
+    /// Generate Merkle proof for a leaf at `index` (0-based).
+    ///
+    /// Returns a vector of ProofNode ordered from leaf-level upward.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.generate_proof(1)?;
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn generate_proof(&self, index: usize) -> Result<Vec<ProofNode>> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+
+        // the node on the leaf's path at `level` is `index >> level`
+        Ok((0..(self.levels.len() - 1))
+            .filter_map(|level| self.sibling_node(level, index >> level))
+            .collect())
+    }
+
+    /// Number of nodes `generate_proof(index)` would return, without building
+    /// the proof.
+    ///
+    /// This is the tree height minus one (0 for a single leaf), less any levels
+    /// where a promoting tree passes the node up without a sibling.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// assert_eq!(tree.proof_len(4)?, tree.generate_proof(4)?.len());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn proof_len(&self, index: usize) -> Result<usize> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+
+        let depth = self.levels.len() - 1;
+        Ok(match self.config.odd_strategy {
+            OddStrategy::Duplicate => depth,
+            OddStrategy::Promote => (0..depth)
+                .filter(|&level| ((index >> level) ^ 1) < self.levels[level].len())
+                .count(),
+        })
+    }
+
+    /// `(level, index)` coordinates of each node `generate_proof(index)` would
+    /// return, in the same order.
+    ///
+    /// Lets callers that keep node hashes in their own store, keyed by level
+    /// and position, fetch a proof's siblings without the in-memory tree.
+    /// Where a duplicating tree pairs the odd last node with itself, the
+    /// coordinate is that node's own.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// assert_eq!(tree.proof_path_indices(4)?, vec![(0, 4), (1, 2), (2, 0)]);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn proof_path_indices(&self, index: usize) -> Result<Vec<(usize, usize)>> {
+        if index >= self.leaf_count() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index,
+                leaf_count: self.leaf_count(),
+            });
+        }
+
+        Ok((0..(self.levels.len() - 1))
+            .filter_map(|level| {
+                self.sibling_index(level, index >> level)
+                    .map(|sibling| (level, sibling))
+            })
+            .collect())
+    }
+
+    /// Generate a proof in which siblings that merely duplicate the current
+    /// node are replaced by `ProofNode::duplicate()`.
+    ///
+    /// Only `OddStrategy::Duplicate` trees with an odd level on the leaf's
+    /// path have such siblings; every other proof comes back unchanged. The
+    /// result verifies like the full proof with `verify_proof` and friends,
+    /// which rebuild the omitted hashes from the running node.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.generate_proof_compressed(4)?;
+    /// assert_eq!(proof.iter().filter(|node| node.is_duplicate()).count(), 2);
+    /// assert!(MerkleTree::verify_proof(&sha256(&[4]), &proof, tree.root_hash_ref()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn generate_proof_compressed(&self, index: usize) -> Result<Vec<ProofNode>> {
+        let proof = self.generate_proof(index)?;
+        let path = self.proof_path_indices(index)?;
+        Ok(proof
+            .into_iter()
+            .zip(path)
+            .map(|(node, (level, sibling))| {
+                if sibling == index >> level {
+                    ProofNode::duplicate()
+                } else {
+                    node
+                }
+            })
+            .collect())
+    }
+
+    /// Generate the proof for the first leaf equal to `leaf_hash`, returning
+    /// its index alongside.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::LeafNotFound` if `leaf_hash` is not a leaf.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let (index, proof) = tree.generate_proof_by_hash(&sha256(b"c"))?;
+    /// assert_eq!(index, 2);
+    /// assert_eq!(proof, tree.generate_proof(2)?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn generate_proof_by_hash(&self, leaf_hash: &[u8]) -> Result<(usize, Vec<ProofNode>)> {
+        let index = self.levels[0]
+            .iter()
+            .position(|leaf| leaf == leaf_hash)
+            .ok_or(MerkleError::LeafNotFound)?;
+        Ok((index, self.generate_proof(index)?))
+    }
+
+    /// Yield `(leaf_index, proof)` for every leaf, in index order.
+    ///
+    /// Consecutive leaves share the upper part of their paths, so each step
+    /// only recomputes the levels below the point where the paths meet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// for (index, proof) in tree.all_proofs() {
+    ///     assert_eq!(proof, tree.generate_proof(index)?);
+    /// }
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn all_proofs(&self) -> impl Iterator<Item = (usize, Vec<ProofNode>)> + '_ {
+        let depth = self.levels.len() - 1;
+        let mut path: Vec<Option<ProofNode>> = vec![None; depth];
+        (0..self.leaf_count()).map(move |index| {
+            // index and index - 1 share every path node above their lowest
+            // differing bit; index 0 fills the whole path
+            let changed = (index.trailing_zeros() as usize + 1).min(depth);
+            for (level, node) in path.iter_mut().enumerate().take(changed) {
+                *node = self.sibling_node(level, index >> level);
+            }
+            (index, path.iter().flatten().cloned().collect())
+        })
+    }
+
+    /// Position of the sibling of node `index` at `level`, or `None` when the
+    /// node is the odd last node of a promoting tree and has no sibling at all.
+    fn sibling_index(&self, level: usize, index: usize) -> Option<usize> {
+        let sibling_index = index ^ 1;
+
+        // if sibling index beyond bounds, sibling is the same node (duplication)
+        // or there is no sibling at all (promotion)
+        if sibling_index < self.levels[level].len() {
+            Some(sibling_index)
+        } else {
+            match self.config.odd_strategy {
+                OddStrategy::Duplicate => Some(index),
+                OddStrategy::Promote => None,
+            }
+        }
+    }
+
+    /// Sibling of node `index` at `level`, or `None` when the node is the odd
+    /// last node of a promoting tree and has no sibling at all.
+    fn sibling_node(&self, level: usize, index: usize) -> Option<ProofNode> {
+        let sibling_hash = self.levels[level][self.sibling_index(level, index)?].clone();
+        let is_right = index % 2 == 1;
+
+        Some(if is_right {
+            ProofNode::left(sibling_hash)
+        } else {
+            ProofNode::right(sibling_hash)
+        })
+    }
+
+    /// Verify a proof against this tree's root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files = vec![b"a".to_vec(), b"b".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.generate_proof(0)?;
+    /// let leaf_hash = sha256(b"a");
+    /// assert!(tree.verify(&leaf_hash, &proof)?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify(&self, leaf_hash: &[u8], proof: &[ProofNode]) -> Result<bool> {
+        Ok(Self::verify_proof_with_config(
+            leaf_hash,
+            proof,
+            self.root_hash_ref()?,
+            &self.config,
+        ))
+    }
+
+    /// Check that `leaf_hash` is the leaf at `index` and proves into the root.
+    ///
+    /// Generates the proof for `index` and verifies it in one call. Returns
+    /// `false` if `leaf_hash` differs from the stored leaf at `index`, even
+    /// when it occurs elsewhere in the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// assert!(tree.verify_inclusion_by_index(1, &sha256(b"b"))?);
+    /// assert!(!tree.verify_inclusion_by_index(0, &sha256(b"b"))?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify_inclusion_by_index(&self, index: usize, leaf_hash: &[u8]) -> Result<bool> {
+        let proof = self.generate_proof(index)?;
+        if !constant_time_eq(&self.levels[0][index], leaf_hash) {
+            return Ok(false);
+        }
+        self.verify(leaf_hash, &proof)
+    }
+
+    /// Verify a proof: starting from leaf_hash, apply proof nodes to derive root and compare.
+    ///
+    /// This is a static method for verifying proofs without needing the full tree.
+    /// Internal nodes are hashed with SHA-256; use `verify_proof_with_algorithm`
+    /// for trees built with another algorithm.
+    pub fn verify_proof(leaf_hash: &[u8], proof: &[ProofNode], expected_root: &[u8]) -> bool {
+        Self::verify_proof_with_algorithm(leaf_hash, proof, expected_root, HashAlgorithm::default())
+    }
+
+    /// Verify a proof after checking its length against the tree size.
+    ///
+    /// Like `verify_proof`, but a proof that cannot belong to a tree of
+    /// `expected_leaves` leaves is reported as an error instead of a plain
+    /// `false`, so a corrupt proof can be told apart from a wrong leaf. Proofs
+    /// from the default `Duplicate` strategy always have one node per level
+    /// below the root.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `expected_leaves` is 0, or
+    /// `MerkleError::MalformedProof` if the proof length does not match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleError, MerkleTree, sha256};
+    ///
+    /// let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let root = tree.root_hash_ref()?;
+    /// let proof = tree.generate_proof(2)?;
+    /// assert!(MerkleTree::verify_proof_checked(&sha256(b"c"), &proof, root, 3)?);
+    /// assert!(matches!(
+    ///     MerkleTree::verify_proof_checked(&sha256(b"c"), &proof[..1], root, 3),
+    ///     Err(MerkleError::MalformedProof { expected_len: 2, actual_len: 1 })
+    /// ));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify_proof_checked(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        expected_root: &[u8],
+        expected_leaves: usize,
+    ) -> Result<bool> {
+        if expected_leaves == 0 {
+            return Err(MerkleError::EmptyLeaves);
+        }
+        let expected_len = proof_len_for(expected_leaves);
+        if proof.len() != expected_len {
+            return Err(MerkleError::MalformedProof {
+                expected_len,
+                actual_len: proof.len(),
+            });
+        }
+        Ok(Self::verify_proof(leaf_hash, proof, expected_root))
+    }
+
+    /// Verify a proof given the leaf hash and root as hex strings.
+    ///
+    /// Hashes internal nodes with SHA-256, like `verify_proof`. Surrounding
+    /// whitespace is ignored, so a root read from a file can be passed as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::InvalidHex` if either string fails to decode,
+    /// with `index` 0 for `leaf_hex` and 1 for `root_hex`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let proof = tree.generate_proof(1)?;
+    /// let root_hex = hex::encode(tree.root_hash_ref()?);
+    /// assert!(MerkleTree::verify_proof_hex(&hex::encode(sha256(b"b")), &proof, &root_hex)?);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify_proof_hex(leaf_hex: &str, proof: &[ProofNode], root_hex: &str) -> Result<bool> {
+        let decode = |index, text: &str| {
+            hex::decode(text.trim()).map_err(|source| MerkleError::InvalidHex { index, source })
+        };
+        let leaf_hash = decode(0, leaf_hex)?;
+        let root = decode(1, root_hex)?;
+        Ok(Self::verify_proof(&leaf_hash, proof, &root))
+    }
+
+    /// Verify a proof against several candidate roots, e.g. during a root
+    /// rotation, returning the index of the first root it matches.
+    ///
+    /// Hashes internal nodes with SHA-256, like `verify_proof`. The root is
+    /// computed once and compared to each candidate in constant time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let old = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let new = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"c".to_vec()])?;
+    /// let roots = [old.root_hash_ref()?, new.root_hash_ref()?];
+    /// let proof = new.generate_proof(1)?;
+    /// assert_eq!(MerkleTree::verify_proof_any(&sha256(b"c"), &proof, &roots), Some(1));
+    /// assert_eq!(MerkleTree::verify_proof_any(&sha256(b"d"), &proof, &roots), None);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify_proof_any(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        roots: &[&[u8]],
+    ) -> Option<usize> {
+        Self::verify_proof_any_with_config(leaf_hash, proof, roots, &TreeConfig::default())
+    }
+
+    /// Verify a proof against several candidate roots of trees built with `config`.
+    pub fn verify_proof_any_with_config(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        roots: &[&[u8]],
+        config: &TreeConfig,
+    ) -> Option<usize> {
+        let mut computed_root = Vec::new();
+        Self::compute_root_into_with_config(leaf_hash, proof, config, &mut computed_root);
+        roots
+            .iter()
+            .position(|root| constant_time_eq(&computed_root, root))
+    }
+
+    /// Verify a proof for a tree built with `algorithm`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{HashAlgorithm, MerkleTree};
+    ///
+    /// let files = vec![b"a".to_vec(), b"b".to_vec()];
+    /// let tree = MerkleTree::from_bytes_vec_with_algorithm(&files, HashAlgorithm::Sha512)?;
+    /// let proof = tree.generate_proof(1)?;
+    /// let leaf_hash = HashAlgorithm::Sha512.digest(b"b");
+    /// assert!(MerkleTree::verify_proof_with_algorithm(
+    ///     &leaf_hash,
+    ///     &proof,
+    ///     tree.root_hash_ref()?,
+    ///     HashAlgorithm::Sha512,
+    /// ));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn verify_proof_with_algorithm(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        expected_root: &[u8],
+        algorithm: HashAlgorithm,
+    ) -> bool {
+        Self::verify_proof_with_config(
+            leaf_hash,
+            proof,
+            expected_root,
+            &TreeConfig::with_algorithm(algorithm),
+        )
+    }
+
+    /// Verify a proof for a tree built with `config`.
+    ///
+    /// Domain separation tags are applied to internal nodes when enabled;
+    /// `leaf_hash` must already be the (tagged) leaf as stored in the tree.
+    /// The root comparison runs in constant time (see `constant_time_eq`).
+    pub fn verify_proof_with_config(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        expected_root: &[u8],
+        config: &TreeConfig,
+    ) -> bool {
+        let mut computed_root = Vec::new();
+        Self::compute_root_into_with_config(leaf_hash, proof, config, &mut computed_root);
+        constant_time_eq(&computed_root, expected_root)
+    }
+
+    /// Compute the root of a SHA-256 tree by applying a proof to a leaf hash,
+    /// writing it into `buf`.
+    ///
+    /// `buf` is overwritten and its allocation reused, so a verifier checking
+    /// many proofs can pass the same buffer every time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, constant_time_eq, sha256};
+    ///
+    /// let files: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let mut buf = Vec::new();
+    /// for (i, file) in files.iter().enumerate() {
+    ///     MerkleTree::compute_root_into(&sha256(file), &tree.generate_proof(i)?, &mut buf);
+    ///     assert!(constant_time_eq(&buf, tree.root_hash_ref()?));
+    /// }
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn compute_root_into(leaf_hash: &[u8], proof: &[ProofNode], buf: &mut Vec<u8>) {
+        Self::compute_root_into_with_config(leaf_hash, proof, &TreeConfig::default(), buf)
+    }
+
+    /// Compute the root of a tree built with `config` into `buf`.
+    ///
+    /// Promoted levels contribute no proof node, so both odd strategies are
+    /// handled by folding over whatever nodes the proof contains. In sorted
+    /// pair mode `hash_concat` orders the sibling against the running hash, so
+    /// `is_left` has no effect. A `ProofNode::duplicate()` pairs the running
+    /// hash with itself.
+    pub fn compute_root_into_with_config(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        config: &TreeConfig,
+        buf: &mut Vec<u8>,
+    ) {
+        buf.clear();
+        buf.extend_from_slice(leaf_hash);
+
+        for node in proof {
+            let state = if node.is_duplicate() {
+                config.concat_state(buf, buf)
+            } else if node.is_left {
+                // sibling is left: hash(sibling || current)
+                config.concat_state(&node.hash, buf)
+            } else {
+                // sibling is right: hash(current || sibling)
+                config.concat_state(buf, &node.hash)
+            };
+            state.finalize_into(buf);
+        }
+    }
+
+    /// Compute the root of a SHA-256 tree from a proof, also returning every
+    /// intermediate node computed on the way.
+    ///
+    /// The trace has one entry per proof node, from just above the leaf up to
+    /// the root, so its last entry equals the returned root. An empty proof
+    /// gives the leaf itself as root and an empty trace.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let proof = tree.generate_proof(2)?;
+    /// let (root, trace) = MerkleTree::compute_root_with_trace(&sha256(b"c"), &proof);
+    /// assert_eq!(root, tree.root_hash_ref()?);
+    /// assert_eq!(trace.len(), proof.len());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn compute_root_with_trace(leaf_hash: &[u8], proof: &[ProofNode]) -> (Hash, Vec<Hash>) {
+        Self::compute_root_with_trace_with_config(leaf_hash, proof, &TreeConfig::default())
+    }
+
+    /// Compute the root and intermediate nodes for a tree built with `config`.
+    ///
+    /// Folds the proof exactly like `compute_root_into_with_config`.
+    pub fn compute_root_with_trace_with_config(
+        leaf_hash: &[u8],
+        proof: &[ProofNode],
+        config: &TreeConfig,
+    ) -> (Hash, Vec<Hash>) {
+        let mut current = leaf_hash.to_vec();
+        let mut trace = Vec::with_capacity(proof.len());
+        for node in proof {
+            let state = if node.is_duplicate() {
+                config.concat_state(&current, &current)
+            } else if node.is_left {
+                config.concat_state(&node.hash, &current)
+            } else {
+                config.concat_state(&current, &node.hash)
+            };
+            state.finalize_into(&mut current);
+            trace.push(current.clone());
+        }
+        (current, trace)
+    }
+
+    /// Return a reference to the root hash; the algorithm's `empty_root()`
+    /// for a tree without leaves.
+    pub fn root_hash_ref(&self) -> Result<&[u8]> {
+        if self.leaf_count() == 0 {
+            return Ok(self.config.algorithm.empty_root());
+        }
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .map(|hash| hash.as_slice())
+            .ok_or(MerkleError::EmptyLeaves)
+    }
+
+    /// Hash algorithm the tree was built with.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.config.algorithm
+    }
+
+    /// Hashing parameters the tree was built with.
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    /// Number of leaves in the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Height of the tree (number of levels); 0 for an empty tree.
+    pub fn tree_height(&self) -> usize {
+        if self.leaf_count() == 0 {
+            return 0;
+        }
+        self.levels.len()
+    }
+
+    /// Get all leaf hashes.
+    pub fn get_leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    /// Leaf hashes `start..end`, for exporting leaves a page at a time.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if `end` exceeds the leaf
+    /// count (reporting `end`) or `start > end` (reporting `start`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// assert_eq!(tree.leaf_range(1, 3)?, &[sha256(b"b"), sha256(b"c")]);
+    /// assert!(tree.leaf_range(2, 4).is_err());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn leaf_range(&self, start: usize, end: usize) -> Result<&[Hash]> {
+        let leaf_count = self.leaf_count();
+        if end > leaf_count {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: end,
+                leaf_count,
+            });
+        }
+        if start > end {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: start,
+                leaf_count,
+            });
+        }
+        Ok(&self.levels[0][start..end])
+    }
+
+    /// Whether `hash` is one of this tree's leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// assert!(tree.contains_leaf(&sha256(b"b")));
+    /// assert!(!tree.contains_leaf(&sha256(b"c")));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn contains_leaf(&self, hash: &[u8]) -> bool {
+        self.levels[0]
+            .iter()
+            .any(|leaf| constant_time_eq(leaf, hash))
+    }
+
+    /// All levels, from the leaves (`levels()[0]`) up to the root level.
+    ///
+    /// Read-only access intended for visualization and debugging.
+    pub fn levels(&self) -> &[Vec<Hash>] {
+        &self.levels
+    }
+
+    /// Hex-encoded hashes of a single level (0 = leaves).
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if `level >= tree_height`; the
+    /// error's `leaf_count` then holds the tree height.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// assert_eq!(tree.level_hashes_hex(0)?.len(), 2);
+    /// assert_eq!(tree.level_hashes_hex(1)?, vec![hex::encode(tree.root_hash_ref()?)]);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn level_hashes_hex(&self, level: usize) -> Result<Vec<String>> {
+        let nodes = self
+            .levels
+            .get(level)
+            .ok_or(MerkleError::IndexOutOfBounds {
+                index: level,
+                leaf_count: self.tree_height(),
+            })?;
+        Ok(nodes.iter().map(hex::encode).collect())
+    }
+
+    /// Hash of the node at `index` on `level` (0 = leaves), i.e. the root of
+    /// the subtree over leaves `index << level` up to `(index + 1) << level`.
+    ///
+    /// A subtree rebuilt locally from those leaves, with the same config, can
+    /// be checked against the authoritative tree by comparing with this hash.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if `level >= tree_height` (the
+    /// error's `leaf_count` then holds the tree height) or if `index` is not
+    /// a node of that level (its `leaf_count` then holds the level's size).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let files: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let left_half = MerkleTree::from_bytes_vec(&files[..2])?;
+    ///
+    /// assert_eq!(tree.subtree_root(1, 0)?, left_half.root_hash_ref()?);
+    /// assert_eq!(tree.subtree_root(2, 0)?, tree.root_hash_ref()?);
+    /// assert!(tree.subtree_root(1, 2).is_err());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn subtree_root(&self, level: usize, index: usize) -> Result<&[u8]> {
+        let nodes = self
+            .levels
+            .get(level)
+            .ok_or(MerkleError::IndexOutOfBounds {
+                index: level,
+                leaf_count: self.tree_height(),
+            })?;
+        nodes
+            .get(index)
+            .map(Vec::as_slice)
+            .ok_or(MerkleError::IndexOutOfBounds {
+                index,
+                leaf_count: nodes.len(),
+            })
+    }
+
+    /// Indices of leaves that differ between `self` and `other`.
+    ///
+    /// Walks both trees top-down, skipping any subtree whose node hashes match,
+    /// so unchanged regions cost a single comparison. When the trees have
+    /// different leaf counts, every index past the shorter tree's end is
+    /// reported as changed. The result is sorted ascending.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let old = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let new = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"B".to_vec(), b"c".to_vec()])?;
+    /// assert_eq!(old.diff(&new), vec![1, 2]);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn diff(&self, other: &MerkleTree) -> Vec<usize> {
+        let common = self.leaf_count().min(other.leaf_count());
+        let total = self.leaf_count().max(other.leaf_count());
+        // an empty tree shares no leaves, and has no level to walk down from
+        if common == 0 {
+            return (0..total).collect();
+        }
+        let top = self.tree_height().max(other.tree_height()) - 1;
+
+        let mut changed = Vec::new();
+        self.diff_node(other, top, 0, common, &mut changed);
+        changed.extend(common..total);
+        changed
+    }
+
+    /// Recursive step of `diff` for the node at (`level`, `index`).
+    fn diff_node(
+        &self,
+        other: &MerkleTree,
+        level: usize,
+        index: usize,
+        common: usize,
+        changed: &mut Vec<usize>,
+    ) {
+        // first leaf covered by this node lies past the shared range
+        if index << level >= common {
+            return;
+        }
+
+        let a = self.levels.get(level).and_then(|l| l.get(index));
+        let b = other.levels.get(level).and_then(|l| l.get(index));
+        if a.is_some() && a == b {
+            return;
+        }
+
+        if level == 0 {
+            changed.push(index);
+        } else {
+            self.diff_node(other, level - 1, index * 2, common, changed);
+            self.diff_node(other, level - 1, index * 2 + 1, common, changed);
+        }
+    }
+
+    /// Whether `self` and `other` have the same config and identical levels,
+    /// leaves included.
+    ///
+    /// Stricter than comparing roots: under `OddStrategy::Duplicate`,
+    /// `[a, b, c]` and `[a, b, c, c]` share a root but not a structure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let three = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let four = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"c".to_vec()])?;
+    /// assert_eq!(three.root_hash_ref()?, four.root_hash_ref()?);
+    /// assert!(!three.equals_structurally(&four));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn equals_structurally(&self, other: &MerkleTree) -> bool {
+        self.config == other.config && self.levels == other.levels
+    }
+
+    /// Describe where `self` and `other` first diverge, for debugging a root
+    /// mismatch.
+    ///
+    /// Levels are scanned from the leaves up and the first node that differs
+    /// (or exists in only one tree) is reported along with both leaf counts.
+    /// Identical leaves diverging higher up point at a config mismatch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let ours = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])?;
+    /// let theirs = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"B".to_vec(), b"c".to_vec()])?;
+    /// assert!(ours.explain_mismatch(&theirs).contains("level 0, node 1"));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn explain_mismatch(&self, other: &MerkleTree) -> String {
+        let counts = format!(
+            "this tree has {} leaves, the other {}",
+            self.leaf_count(),
+            other.leaf_count()
+        );
+        let divergence = (0..self.tree_height().max(other.tree_height())).find_map(|level| {
+            let a = self
+                .levels
+                .get(level)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let b = other
+                .levels
+                .get(level)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            (0..a.len().max(b.len()))
+                .find(|&i| a.get(i) != b.get(i))
+                .map(|index| (level, index))
+        });
+        let Some((level, index)) = divergence else {
+            return format!("trees are identical ({})", counts);
+        };
+
+        let mut message = format!(
+            "first divergence at level {}, node {} ({})",
+            level, index, counts
+        );
+        if self.levels.last() == other.levels.last() {
+            message.push_str("; roots still match");
+        }
+        if self.config != other.config {
+            message.push_str("; tree configs differ");
+        }
+        message
+    }
+
+    /// Render the tree as a Graphviz DOT graph.
+    ///
+    /// Nodes are labeled with the first 8 hex characters of their hash; leaves
+    /// are drawn as boxes, internal nodes as ellipses. Edges point from each
+    /// child to its parent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let dot = tree.to_dot();
+    /// assert!(dot.starts_with("digraph merkle {"));
+    /// assert!(dot.contains("n0_1 -> n1_0;"));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph merkle {\n    rankdir=BT;\n");
+        for (level, nodes) in self.levels.iter().enumerate() {
+            let shape = if level == 0 { "box" } else { "ellipse" };
+            for (index, hash) in nodes.iter().enumerate() {
+                let mut label = hex::encode(hash);
+                label.truncate(8);
+                dot.push_str(&format!(
+                    "    n{}_{} [label=\"{}\", shape={}];\n",
+                    level, index, label, shape
+                ));
+            }
+        }
+        for (level, children) in self.levels[..self.levels.len() - 1].iter().enumerate() {
+            for index in 0..children.len() {
+                dot.push_str(&format!(
+                    "    n{}_{} -> n{}_{};\n",
+                    level,
+                    index,
+                    level + 1,
+                    index / 2
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Serialize the tree to JSON, tagged with `format_version`.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&VersionedTree {
+            format_version: FORMAT_VERSION,
+            tree: self,
+        })?)
+    }
+
+    /// Deserialize a tree from JSON.
+    ///
+    /// Blobs without a `format_version` predate versioning and are read as
+    /// version 1. Blobs holding `leaves` instead of `levels` (see
+    /// `to_json_leaves_only`) have their levels rebuilt; stored levels are
+    /// checked as by `try_from_levels_with_config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::UnsupportedFormatVersion` if the blob was written
+    /// with a newer (or unknown) layout, or `MerkleError::Json` if it does not
+    /// parse. Leaves-only blobs also fail as `from_leaves_with_config` does,
+    /// and other blobs as `try_from_levels_with_config` does.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let found = match value
+            .as_object_mut()
+            .and_then(|o| o.remove("format_version"))
+        {
+            Some(version) => serde_json::from_value(version)?,
+            None => 1,
+        };
+        if found != FORMAT_VERSION {
+            return Err(MerkleError::UnsupportedFormatVersion {
+                found,
+                supported: FORMAT_VERSION,
+            });
+        }
+        if value.get("leaves").is_some() {
+            return serde_json::from_value::<CompactTree>(value)?.into_tree();
+        }
+        let repr: MerkleTreeRepr = serde_json::from_value(value)?;
+        MerkleTree::try_from(repr)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl MerkleTree {
+    /// Serialize the tree to compact, deterministic Borsh bytes, prefixed
+    /// with `format_version` as a little-endian `u32`.
+    pub fn to_borsh(&self) -> Result<Vec<u8>> {
+        Ok(borsh::to_vec(&(FORMAT_VERSION, self))?)
+    }
+
+    /// Deserialize a tree written by `to_borsh`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::UnsupportedFormatVersion` for another layout
+    /// version, `MerkleError::Io` if the bytes do not decode (including
+    /// trailing bytes), or the errors of `try_from_levels_with_config` if the
+    /// decoded levels are not shaped like a tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()])?;
+    /// let restored = MerkleTree::from_borsh(&tree.to_borsh()?)?;
+    /// assert_eq!(restored.levels(), tree.levels());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_borsh(bytes: &[u8]) -> Result<Self> {
+        let mut reader = bytes;
+        let found: u32 = borsh::BorshDeserialize::deserialize(&mut reader)?;
+        if found != FORMAT_VERSION {
+            return Err(MerkleError::UnsupportedFormatVersion {
+                found,
+                supported: FORMAT_VERSION,
+            });
+        }
+        let tree: MerkleTree = borsh::from_slice(reader)?;
+        MerkleTree::from_stored_levels(tree.levels, tree.config)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl ProofNode {
+    /// Serialize the node to Borsh bytes: the hash (length-prefixed) then the side flag.
+    pub fn to_borsh(&self) -> Result<Vec<u8>> {
+        Ok(borsh::to_vec(self)?)
+    }
+
+    /// Deserialize a node written by `to_borsh`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::Io` if the bytes do not decode.
+    pub fn from_borsh(bytes: &[u8]) -> Result<Self> {
+        Ok(borsh::from_slice(bytes)?)
+    }
+}
+
+/// Serialized form of a tree: the tree's own fields plus the layout version.
+#[derive(Serialize)]
+struct VersionedTree<'a> {
+    format_version: u32,
+    #[serde(flatten)]
+    tree: &'a MerkleTree,
+}
+
+/// Number of levels above the leaves in a tree of `leaf_count` leaves.
+fn proof_len_for(mut leaf_count: usize) -> usize {
+    let mut len = 0;
+    while leaf_count > 1 {
+        leaf_count = leaf_count.div_ceil(2);
+        len += 1;
+    }
+    len
+}
+
+/// Compare two byte strings without short-circuiting on the first difference.
+///
+/// Every byte pair is XORed into an accumulator, so for inputs of equal length
+/// the running time does not depend on where (or whether) they differ. A
+/// length mismatch returns early; digest lengths are not secret. Used for all
+/// root comparisons in this crate.
+///
+/// # Examples
+///
+/// ```
+/// use merkle::{constant_time_eq, sha256};
+///
+/// assert!(constant_time_eq(&sha256(b"a"), &sha256(b"a")));
+/// assert!(!constant_time_eq(&sha256(b"a"), &sha256(b"b")));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // keep the optimizer from turning the fold back into an early-exit compare
+    std::hint::black_box(diff) == 0
+}
+
+/// Read a hex-encoded root from `reader`, ignoring surrounding whitespace.
+///
+/// # Errors
+///
+/// Returns `MerkleError::Io` if reading fails, or `MerkleError::InvalidHex`
+/// if the trimmed input is empty, not valid UTF-8, or not valid hex.
+///
+/// # Examples
+///
+/// ```
+/// use merkle::{root_from_reader, sha256};
+///
+/// let root = sha256(b"root");
+/// let saved = format!("{}\n", hex::encode(&root));
+/// assert_eq!(root_from_reader(saved.as_bytes())?, root);
+/// # Ok::<(), merkle::MerkleError>(())
+/// ```
+pub fn root_from_reader<R: Read>(mut reader: R) -> Result<Hash> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    let invalid = |source| MerkleError::InvalidHex { index: 0, source };
+
+    let text = std::str::from_utf8(&contents)
+        .map_err(|_| invalid(hex::FromHexError::InvalidStringLength))?;
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(invalid(hex::FromHexError::InvalidStringLength));
+    }
+    hex::decode(text).map_err(invalid)
+}
+
+/// Compute SHA-256 digest of data.
+///
+/// # Examples
+///
+/// ```
+/// use merkle::sha256;
+///
+/// let hash = sha256(b"hello world");
+/// assert_eq!(hash.len(), 32);
+/// ```
+pub fn sha256(bytes: &[u8]) -> Hash {
+    HashAlgorithm::Sha256.digest(bytes)
+}
+
+/// Compute the SHA-256 digest of a file without loading it into memory.
+///
+/// Reads the file in `READ_CHUNK_SIZE` chunks; the result equals
+/// `sha256(&fs::read(path)?)`.
+///
+/// # Errors
+///
+/// Returns `MerkleError::Io` if the file cannot be opened or read.
+pub fn sha256_file(path: &Path) -> Result<Hash> {
+    TreeConfig::default().hash_leaf_reader(File::open(path)?)
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_range_loop)] // several tests walk leaf indices
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtree_root() {
+        let files: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i]).collect();
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::rfc6962()
+            },
+        ] {
+            let tree = MerkleTree::from_bytes_vec_with_config(&files, config.clone()).unwrap();
+            let leaves = tree.get_leaves();
+
+            // Every internal node is the hash of its two children
+            let ab = config.hash_concat(&leaves[0], &leaves[1]);
+            let cd = config.hash_concat(&leaves[2], &leaves[3]);
+            let ef = config.hash_concat(&leaves[4], &leaves[5]);
+            assert_eq!(tree.subtree_root(1, 0).unwrap(), ab.as_slice());
+            assert_eq!(tree.subtree_root(1, 1).unwrap(), cd.as_slice());
+            assert_eq!(tree.subtree_root(1, 2).unwrap(), ef.as_slice());
+            assert_eq!(
+                tree.subtree_root(2, 0).unwrap(),
+                config.hash_concat(&ab, &cd).as_slice()
+            );
+
+            // The odd last node follows the tree's odd strategy
+            let g = match config.odd_strategy {
+                OddStrategy::Duplicate => config.hash_concat(&leaves[6], &leaves[6]),
+                OddStrategy::Promote => leaves[6].clone(),
+            };
+            assert_eq!(tree.subtree_root(1, 3).unwrap(), g.as_slice());
+            assert_eq!(
+                tree.subtree_root(2, 1).unwrap(),
+                config.hash_concat(&ef, &g).as_slice()
+            );
+            assert_eq!(
+                tree.subtree_root(3, 0).unwrap(),
+                tree.root_hash_ref().unwrap()
+            );
+            assert_eq!(tree.subtree_root(0, 4).unwrap(), leaves[4].as_slice());
+
+            assert!(matches!(
+                tree.subtree_root(4, 0),
+                Err(MerkleError::IndexOutOfBounds {
+                    index: 4,
+                    leaf_count: 4
+                })
+            ));
+            assert!(matches!(
+                tree.subtree_root(1, 4),
+                Err(MerkleError::IndexOutOfBounds {
+                    index: 4,
+                    leaf_count: 4
+                })
+            ));
+            assert!(matches!(
+                tree.subtree_root(0, 7),
+                Err(MerkleError::IndexOutOfBounds {
+                    index: 7,
+                    leaf_count: 7
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_inclusion_by_index() {
+        let files: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+
+        assert!(tree.verify_inclusion_by_index(1, &sha256(b"b")).unwrap());
+        // Same hash at both indices where it is stored
+        assert!(tree.verify_inclusion_by_index(0, &sha256(b"a")).unwrap());
+        assert!(tree.verify_inclusion_by_index(2, &sha256(b"a")).unwrap());
+
+        // A real leaf, but not the one at this index
+        assert!(!tree.verify_inclusion_by_index(1, &sha256(b"a")).unwrap());
+        assert!(!tree.verify_inclusion_by_index(1, &sha256(b"c")).unwrap());
+
+        assert!(matches!(
+            tree.verify_inclusion_by_index(3, &sha256(b"a")),
+            Err(MerkleError::IndexOutOfBounds {
+                index: 3,
+                leaf_count: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_compute_root_into_reuses_buffer() {
+        let data: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let cases: Vec<(Hash, Vec<ProofNode>)> = (0..data.len())
+            .map(|i| (sha256(&data[i]), tree.generate_proof(i).unwrap()))
+            .collect();
+
+        let mut buf = Vec::with_capacity(32);
+        let ptr = buf.as_ptr();
+        for n in 0..10_000 {
+            let (leaf, proof) = &cases[n % cases.len()];
+            MerkleTree::compute_root_into(leaf, proof, &mut buf);
+            assert!(constant_time_eq(&buf, root));
+        }
+        // No reallocation across all 10k verifications
+        assert_eq!(buf.as_ptr(), ptr);
+
+        MerkleTree::compute_root_into(&sha256(b"wrong"), &cases[0].1, &mut buf);
+        assert!(!constant_time_eq(&buf, root));
+    }
+
+    #[test]
+    fn test_root_from_reader() {
+        let root = sha256(b"root");
+        let hex_root = hex::encode(&root);
+        for saved in [
+            hex_root.clone(),
+            format!("{}\n", hex_root),
+            format!("  {}\r\n", hex_root),
+        ] {
+            assert_eq!(root_from_reader(saved.as_bytes()).unwrap(), root);
+        }
+
+        for bad in [
+            format!("{}zz", hex_root),
+            hex_root[1..].to_string(),
+            "\n".to_string(),
+        ] {
+            let err = root_from_reader(bad.as_bytes()).unwrap_err();
+            assert!(matches!(err, MerkleError::InvalidHex { index: 0, .. }));
+            assert!(err.is_client_error());
+        }
+        assert!(matches!(
+            root_from_reader(&[0xff, 0xfe][..]),
+            Err(MerkleError::InvalidHex { .. })
+        ));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let a = sha256(b"a");
+        assert!(constant_time_eq(&a, &a.clone()));
+        assert!(constant_time_eq(&[], &[]));
+
+        for i in [0, 15, 31] {
+            let mut b = a.clone();
+            b[i] ^= 0x80;
+            assert!(!constant_time_eq(&a, &b), "difference at byte {}", i);
+        }
+        assert!(!constant_time_eq(&a, &a[..31]));
+
+        // Proof verification still accepts and rejects as before
+        let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let proof = tree.generate_proof(0).unwrap();
+        let mut root = tree.root_hash_ref().unwrap().to_vec();
+        assert!(MerkleTree::verify_proof(&a, &proof, &root));
+        root[31] ^= 0x01;
+        assert!(!MerkleTree::verify_proof(&a, &proof, &root));
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let json_err = serde_json::from_str::<MerkleTree>("not json").unwrap_err();
+        let cases = [
+            (MerkleError::EmptyLeaves, true),
+            (
+                MerkleError::IndexOutOfBounds {
+                    index: 3,
+                    leaf_count: 2,
+                },
+                true,
+            ),
+            (MerkleError::LeafNotFound, true),
+            (MerkleError::Io(io::Error::other("disk")), false),
+            (MerkleError::Json(json_err), false),
+            (MerkleError::VerificationFailed, true),
+            (
+                MerkleError::UnsupportedOddStrategy {
+                    required: OddStrategy::Promote,
+                },
+                true,
+            ),
+            (
+                MerkleError::MalformedProof {
+                    expected_len: 2,
+                    actual_len: 3,
+                },
+                true,
+            ),
+            (
+                MerkleError::InvalidProofLength {
+                    len: 34,
+                    node_len: 33,
+                },
+                true,
+            ),
+            (MerkleError::InvalidProofFlag(2), true),
+            (MerkleError::InvalidProofNode { len: 1 }, true),
+            (
+                MerkleError::InconsistentHashLength {
+                    expected: 32,
+                    found: 20,
+                },
+                true,
+            ),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(err.is_client_error(), expected, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_single_leaf() {
+        let data = vec![b"single".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.leaf_count(), 1);
+        assert_eq!(tree.tree_height(), 1);
+
+        let proof = tree.generate_proof(0).unwrap();
+        assert!(proof.is_empty()); // single leaf has no siblings
+
+        let leaf_hash = sha256(b"single");
+        assert!(tree.verify(&leaf_hash, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_two_leaves() {
+        let data = vec![b"left".to_vec(), b"right".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.leaf_count(), 2);
+        assert_eq!(tree.tree_height(), 2);
 
         // Test both proofs
         let proof0 = tree.generate_proof(0).unwrap();
         assert_eq!(proof0.len(), 1);
         assert!(tree.verify(&sha256(b"left"), &proof0).unwrap());
 
-        let proof1 = tree.generate_proof(1).unwrap();
-        assert_eq!(proof1.len(), 1);
-        assert!(tree.verify(&sha256(b"right"), &proof1).unwrap());
+        let proof1 = tree.generate_proof(1).unwrap();
+        assert_eq!(proof1.len(), 1);
+        assert!(tree.verify(&sha256(b"right"), &proof1).unwrap());
+    }
+
+    #[test]
+    fn test_three_leaves_odd_duplication() {
+        // Tests duplication of last node when odd
+        let data = vec![b"alpha".to_vec(), b"bravo".to_vec(), b"charlie".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.leaf_count(), 3);
+
+        for i in 0..3 {
+            let leaf_hash = sha256(&data[i]);
+            let proof = tree.generate_proof(i).unwrap();
+            assert!(
+                tree.verify(&leaf_hash, &proof).unwrap(),
+                "proof for index {} should verify",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn test_power_of_two_leaves() {
+        // 4 leaves = perfect binary tree
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.leaf_count(), 4);
+        assert_eq!(tree.tree_height(), 3); // leaves, intermediate, root
+
+        // All proofs should have same length
+        for i in 0..4 {
+            let proof = tree.generate_proof(i).unwrap();
+            assert_eq!(proof.len(), 2); // log2(4) = 2
+        }
+    }
+
+    #[test]
+    fn test_verify_fails_if_tampered() {
+        let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let leaf_hash = sha256(&files[2]);
+        let mut proof = tree.generate_proof(2).unwrap();
+
+        // Tamper with proof
+        proof[0].hash[0] ^= 0xff;
+        assert!(!tree.verify(&leaf_hash, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_verify_fails_wrong_leaf() {
+        let files = vec![b"a".to_vec(), b"b".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let proof = tree.generate_proof(0).unwrap();
+
+        // Try to verify with wrong leaf
+        let wrong_leaf = sha256(b"wrong");
+        assert!(!tree.verify(&wrong_leaf, &proof).unwrap());
+    }
+
+    #[test]
+    fn test_empty_leaves_error() {
+        let empty: Vec<Vec<u8>> = vec![];
+        let result = MerkleTree::from_bytes_vec(&empty);
+        assert!(matches!(result, Err(MerkleError::EmptyLeaves)));
+    }
+
+    #[test]
+    fn test_index_out_of_bounds() {
+        let data = vec![b"a".to_vec(), b"b".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+
+        let result = tree.generate_proof(2);
+        assert!(matches!(result, Err(MerkleError::IndexOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        assert_eq!(EMPTY_ROOT.to_vec(), sha256(b""));
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            assert_eq!(algorithm.empty_root(), algorithm.digest(b"").as_slice());
+
+            let mut tree = MerkleTree::empty_with_config(TreeConfig::with_algorithm(algorithm));
+            assert_eq!(tree.root_hash_ref().unwrap(), algorithm.empty_root());
+            tree.push_leaf(algorithm.digest(b"a")).unwrap();
+            assert_eq!(
+                tree.root_hash_ref().unwrap(),
+                algorithm.digest(b"a").as_slice()
+            );
+        }
+
+        let tree = MerkleTree::empty();
+        assert_eq!(tree.root_hash_ref().unwrap(), EMPTY_ROOT);
+        assert_eq!(tree.leaf_count(), 0);
+        assert_eq!(tree.tree_height(), 0);
+        assert!(tree.get_leaves().is_empty());
+        assert!(tree.leaf_range(0, 0).unwrap().is_empty());
+        assert_eq!(tree.all_proofs().count(), 0);
+        assert!(!tree.contains_leaf(&EMPTY_ROOT));
+        assert!(matches!(
+            tree.generate_proof(0),
+            Err(MerkleError::IndexOutOfBounds {
+                index: 0,
+                leaf_count: 0
+            })
+        ));
+        assert!(matches!(
+            tree.generate_proof_by_hash(&sha256(b"a")),
+            Err(MerkleError::LeafNotFound)
+        ));
+
+        // Growing it matches a batch build, and concatenation treats it as identity
+        let leaves: Vec<Hash> = (0..3u8).map(|i| sha256(&[i])).collect();
+        let mut grown = MerkleTree::empty();
+        for leaf in &leaves {
+            grown.push_leaf(leaf.clone()).unwrap();
+        }
+        let batch = MerkleTree::from_leaves(leaves).unwrap();
+        assert!(grown.equals_structurally(&batch));
+        let merged = MerkleTree::concat(MerkleTree::empty(), batch.clone()).unwrap();
+        assert!(merged.equals_structurally(&batch));
+        let merged = MerkleTree::concat(batch.clone(), MerkleTree::empty()).unwrap();
+        assert!(merged.equals_structurally(&batch));
+    }
+
+    #[test]
+    fn test_get_leaves() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+
+        let leaves = tree.get_leaves();
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(leaves[0], sha256(b"a"));
+        assert_eq!(leaves[1], sha256(b"b"));
+        assert_eq!(leaves[2], sha256(b"c"));
+    }
+
+    #[test]
+    fn test_proof_node_serde_forms() {
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        let proof = tree.generate_proof(1).unwrap();
+        assert_eq!(proof, vec![ProofNode::left(sha256(b"a"))]);
+        assert_eq!(proof[0].direction(), Direction::Left);
+
+        // Written in the boolean form, so older readers keep working
+        let json = serde_json::to_value(&proof[0]).unwrap();
+        assert_eq!(json["is_left"], true);
+        assert!(json.get("direction").is_none());
+        let parsed: ProofNode = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, proof[0]);
+
+        let hash = serde_json::to_value(sha256(b"x")).unwrap();
+        for (body, expected) in [
+            (
+                serde_json::json!({ "hash": hash, "direction": "right" }),
+                ProofNode::right(sha256(b"x")),
+            ),
+            (
+                serde_json::json!({ "hash": hash, "direction": "left", "is_left": true }),
+                ProofNode::left(sha256(b"x")),
+            ),
+            (
+                serde_json::json!({ "hash": hash, "is_left": false }),
+                ProofNode::new(sha256(b"x"), Direction::Right),
+            ),
+        ] {
+            assert_eq!(serde_json::from_value::<ProofNode>(body).unwrap(), expected);
+        }
+
+        for body in [
+            serde_json::json!({ "hash": hash }),
+            serde_json::json!({ "hash": hash, "direction": "left", "is_left": false }),
+            serde_json::json!({ "hash": hash, "direction": "up" }),
+        ] {
+            assert!(serde_json::from_value::<ProofNode>(body).is_err());
+        }
+    }
+
+    #[test]
+    fn test_leaf_range() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+
+        assert_eq!(tree.leaf_range(0, 5).unwrap(), tree.get_leaves());
+        assert_eq!(tree.leaf_range(1, 3).unwrap(), &tree.get_leaves()[1..3]);
+        assert!(tree.leaf_range(2, 2).unwrap().is_empty());
+        assert!(tree.leaf_range(5, 5).unwrap().is_empty());
+
+        assert!(matches!(
+            tree.leaf_range(3, 6),
+            Err(MerkleError::IndexOutOfBounds {
+                index: 6,
+                leaf_count: 5
+            })
+        ));
+        assert!(matches!(
+            tree.leaf_range(4, 2),
+            Err(MerkleError::IndexOutOfBounds {
+                index: 4,
+                leaf_count: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_non_32_byte_leaves() {
+        // 20-byte leaves, as from a RIPEMD-160 based scheme
+        let leaves: Vec<Hash> = (0..5u8).map(|i| sha256(&[i])[..20].to_vec()).collect();
+        let mut tree = MerkleTree::from_leaves(leaves.clone()).unwrap();
+        assert_eq!(tree.get_leaves()[0].len(), 20);
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.generate_proof(i).unwrap();
+            assert!(tree.verify(leaf, &proof).unwrap());
+        }
+
+        assert!(matches!(
+            tree.update_leaf(1, sha256(b"full length")),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 20,
+                found: 32
+            })
+        ));
+        tree.update_leaf(1, vec![0xab; 20]).unwrap();
+    }
+
+    #[test]
+    fn test_mixed_leaf_lengths_rejected() {
+        let mut leaves: Vec<Hash> = (0..4u8).map(|i| sha256(&[i])).collect();
+        leaves[2].truncate(20);
+        assert!(matches!(
+            MerkleTree::from_leaves(leaves),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 20
+            })
+        ));
+
+        let mixed = vec![vec![1u8; 16], vec![2u8; 16], vec![3u8; 64]];
+        assert!(matches!(
+            MerkleTree::from_leaves_with_config(mixed, TreeConfig::rfc6962()),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 16,
+                found: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_contains_leaf() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+
+        for item in &data {
+            assert!(tree.contains_leaf(&sha256(item)));
+        }
+        assert!(!tree.contains_leaf(&sha256(b"d")));
+        // Internal nodes and the root are not leaves
+        assert!(!tree.contains_leaf(tree.root_hash_ref().unwrap()));
+        assert!(!tree.contains_leaf(&tree.levels()[1][0]));
+        assert!(!tree.contains_leaf(&[]));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let data: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let dot = tree.to_dot();
+
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert_eq!(dot.matches("[label=").count(), 7);
+        assert_eq!(dot.matches("shape=box").count(), 4);
+        assert_eq!(dot.matches(" -> ").count(), 6);
+
+        let root_prefix = &hex::encode(tree.root_hash_ref().unwrap())[..8];
+        assert!(dot.contains(&format!("n2_0 [label=\"{}\", shape=ellipse];", root_prefix)));
+        assert!(dot.contains("n1_1 -> n2_0;"));
+    }
+
+    #[test]
+    fn test_leaf_salt() {
+        let files = vec![b"same".to_vec(), b"other".to_vec()];
+        let a = MerkleTree::from_bytes_vec_with_salt(&files, b"salt-a").unwrap();
+        let b = MerkleTree::from_bytes_vec_with_salt(&files, b"salt-b").unwrap();
+
+        assert_eq!(a.get_leaves()[0], sha256(b"salt-asame"));
+        assert_ne!(a.get_leaves()[0], b.get_leaves()[0]);
+        assert_ne!(a.get_leaves()[0], sha256(b"same"));
+
+        for tree in [&a, &b] {
+            let leaf = tree.config().hash_leaf(b"same");
+            let proof = tree.generate_proof(0).unwrap();
+            assert!(tree.verify(&leaf, &proof).unwrap());
+            // The unsalted hash of the same file is not a leaf
+            assert!(!tree.verify(&sha256(b"same"), &proof).unwrap());
+        }
+
+        // Streaming and named hashing apply the salt the same way
+        let config = a.config();
+        assert_eq!(
+            config.hash_leaf_reader(&b"same"[..]).unwrap(),
+            config.hash_leaf(b"same")
+        );
+        assert_eq!(
+            config.hash_named_leaf_reader("n", &b"same"[..]).unwrap(),
+            config.hash_named_leaf("n", b"same")
+        );
+        assert_ne!(
+            config.hash_named_leaf("n", b"same"),
+            TreeConfig::default().hash_named_leaf("n", b"same")
+        );
+
+        // The salt survives serialization and is omitted when unset
+        let json = a.to_json().unwrap();
+        assert!(json.contains(&hex::encode(b"salt-a")));
+        assert_eq!(MerkleTree::from_json(&json).unwrap().config(), a.config());
+        let unsalted = MerkleTree::from_bytes_vec(&files).unwrap();
+        assert!(!unsalted.to_json().unwrap().contains("leaf_salt"));
+    }
+
+    #[test]
+    fn test_from_hex_leaves() {
+        let leaves: Vec<Hash> = (0..4u8).map(|i| sha256(&[i])).collect();
+        let mut hexes: Vec<String> = leaves.iter().map(hex::encode).collect();
+        let tree = MerkleTree::from_hex_leaves(&hexes).unwrap();
+        assert_eq!(
+            tree.root_hash_ref().unwrap(),
+            MerkleTree::from_leaves(leaves)
+                .unwrap()
+                .root_hash_ref()
+                .unwrap()
+        );
+
+        hexes[2] = "not-hex".to_string();
+        let err = MerkleTree::from_hex_leaves(&hexes).unwrap_err();
+        assert!(matches!(err, MerkleError::InvalidHex { index: 2, .. }));
+        assert!(err.to_string().contains("index 2"));
+        assert!(err.is_client_error());
+
+        assert!(matches!(
+            MerkleTree::from_hex_leaves(&[]),
+            Err(MerkleError::EmptyLeaves)
+        ));
+    }
+
+    #[test]
+    fn test_proof_len() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            for n in [1, 2, 100] {
+                let data: Vec<Vec<u8>> =
+                    (0..n).map(|i| format!("leaf{}", i).into_bytes()).collect();
+                let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+                for i in 0..n {
+                    assert_eq!(
+                        tree.proof_len(i).unwrap(),
+                        tree.generate_proof(i).unwrap().len()
+                    );
+                }
+                assert!(matches!(
+                    tree.proof_len(n),
+                    Err(MerkleError::IndexOutOfBounds { .. })
+                ));
+            }
+        }
+
+        let single = MerkleTree::from_bytes_vec(&[b"a".to_vec()]).unwrap();
+        assert_eq!(single.proof_len(0).unwrap(), 0);
+        let hundred: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&hundred).unwrap();
+        assert_eq!(tree.proof_len(99).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_compressed_proofs() {
+        for config in [TreeConfig::default(), TreeConfig::rfc6962()] {
+            for n in [1u8, 4, 5, 7, 11] {
+                let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i]).collect();
+                let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+                let root = tree.root_hash_ref().unwrap();
+                for i in 0..n as usize {
+                    let full = tree.generate_proof(i).unwrap();
+                    let compressed = tree.generate_proof_compressed(i).unwrap();
+                    assert_eq!(compressed.len(), full.len());
+                    let leaf = &tree.get_leaves()[i];
+                    assert!(MerkleTree::verify_proof_with_config(
+                        leaf, &full, root, &config
+                    ));
+                    assert!(MerkleTree::verify_proof_with_config(
+                        leaf,
+                        &compressed,
+                        root,
+                        &config
+                    ));
+                    assert_eq!(
+                        MerkleTree::compute_root_with_trace_with_config(leaf, &compressed, &config),
+                        MerkleTree::compute_root_with_trace_with_config(leaf, &full, &config)
+                    );
+                }
+            }
+        }
+
+        // The last leaf of five duplicates itself on two levels
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let full = tree.generate_proof(4).unwrap();
+        let compressed = tree.generate_proof_compressed(4).unwrap();
+        assert_eq!(
+            compressed
+                .iter()
+                .map(ProofNode::is_duplicate)
+                .collect::<Vec<_>>(),
+            vec![true, true, false]
+        );
+        let size = |proof: &[ProofNode]| serde_json::to_string(proof).unwrap().len();
+        assert!(size(&compressed) < size(&full));
+        let json = serde_json::to_string(&compressed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Vec<ProofNode>>(&json).unwrap(),
+            compressed
+        );
+        assert!(serde_json::from_str::<ProofNode>(r#"{"hash":[1],"duplicate":true}"#).is_err());
+
+        // Only the explicit marker is a duplicate: an empty sibling hash is
+        // hashed as given
+        let empty = vec![ProofNode::right(Vec::new()); 3];
+        assert!(!empty[0].is_duplicate());
+        assert!(!MerkleTree::verify_proof(
+            &sha256(&[4]),
+            &empty,
+            tree.root_hash_ref().unwrap()
+        ));
+        assert_ne!(
+            MerkleTree::compute_root_with_trace(&sha256(&[4]), &empty),
+            MerkleTree::compute_root_with_trace(&sha256(&[4]), &vec![ProofNode::duplicate(); 3])
+        );
+        assert!(!MerkleTree::verify_proof(
+            &sha256(&[3]),
+            &compressed,
+            tree.root_hash_ref().unwrap()
+        ));
+
+        // Even levels and promoting trees have nothing to omit
+        assert_eq!(
+            tree.generate_proof_compressed(1).unwrap(),
+            tree.generate_proof(1).unwrap()
+        );
+        let promote = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..TreeConfig::default()
+        };
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, promote).unwrap();
+        assert_eq!(
+            tree.generate_proof_compressed(4).unwrap(),
+            tree.generate_proof(4).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_proof_path_indices_match_proof() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            for n in [1, 2, 7, 13] {
+                let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i]).collect();
+                let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+                for i in 0..n as usize {
+                    let coordinates = tree.proof_path_indices(i).unwrap();
+                    let siblings: Vec<&Hash> = coordinates
+                        .iter()
+                        .map(|&(level, index)| &tree.levels()[level][index])
+                        .collect();
+                    let proof = tree.generate_proof(i).unwrap();
+                    assert_eq!(
+                        siblings,
+                        proof.iter().map(|node| &node.hash).collect::<Vec<_>>()
+                    );
+                }
+                assert!(matches!(
+                    tree.proof_path_indices(n as usize),
+                    Err(MerkleError::IndexOutOfBounds { .. })
+                ));
+            }
+        }
+
+        // The odd last node of a duplicating tree is its own sibling
+        let data: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.proof_path_indices(2).unwrap(), vec![(0, 2), (1, 0)]);
+    }
+
+    #[test]
+    fn test_all_proofs() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            for n in [1, 2, 7, 16, 21] {
+                let data: Vec<Vec<u8>> =
+                    (0..n).map(|i| format!("leaf{}", i).into_bytes()).collect();
+                let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+                let root = tree.root_hash_ref().unwrap();
+
+                let proofs: Vec<_> = tree.all_proofs().collect();
+                assert_eq!(proofs.len(), n);
+                for (i, (index, proof)) in proofs.iter().enumerate() {
+                    assert_eq!(*index, i);
+                    assert_eq!(proof, &tree.generate_proof(i).unwrap());
+                    assert!(MerkleTree::verify_proof_with_config(
+                        &config.hash_leaf(&data[i]),
+                        proof,
+                        root,
+                        &config
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialization() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+
+        // Serialize
+        let json = tree.to_json().unwrap();
+        assert!(json.contains("levels"));
+
+        // Deserialize
+        let tree2 = MerkleTree::from_json(&json).unwrap();
+        assert_eq!(
+            tree.root_hash_ref().unwrap(),
+            tree2.root_hash_ref().unwrap()
+        );
+        assert_eq!(tree.leaf_count(), tree2.leaf_count());
+    }
+
+    #[test]
+    fn test_large_tree() {
+        // Test with 100 leaves
+        let data: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("data{}", i).into_bytes())
+            .collect();
+
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_eq!(tree.leaf_count(), 100);
+
+        // Verify all proofs
+        for i in 0..100 {
+            let leaf_hash = sha256(&data[i]);
+            let proof = tree.generate_proof(i).unwrap();
+            assert!(tree.verify(&leaf_hash, &proof).unwrap());
+
+            // Proof length should be log2(100) ≈ 7
+            assert!(proof.len() >= 6 && proof.len() <= 8);
+        }
+    }
+
+    #[test]
+    fn test_algorithms_produce_distinct_roots() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let sha256_tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let sha512_tree =
+            MerkleTree::from_bytes_vec_with_algorithm(&data, HashAlgorithm::Sha512).unwrap();
+        let blake3_tree =
+            MerkleTree::from_bytes_vec_with_algorithm(&data, HashAlgorithm::Blake3).unwrap();
+
+        assert_eq!(sha256_tree.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(sha512_tree.root_hash_ref().unwrap().len(), 64);
+        assert_eq!(blake3_tree.root_hash_ref().unwrap().len(), 32);
+        assert_ne!(
+            sha256_tree.root_hash_ref().unwrap(),
+            blake3_tree.root_hash_ref().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_with_algorithm() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        for algorithm in [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Blake3,
+        ] {
+            let tree = MerkleTree::from_bytes_vec_with_algorithm(&data, algorithm).unwrap();
+            let root = tree.root_hash_ref().unwrap();
+            for (i, item) in data.iter().enumerate() {
+                let leaf_hash = algorithm.digest(item);
+                let proof = tree.generate_proof(i).unwrap();
+                assert!(tree.verify(&leaf_hash, &proof).unwrap());
+                assert!(MerkleTree::verify_proof_with_algorithm(
+                    &leaf_hash, &proof, root, algorithm
+                ));
+            }
+        }
+
+        // A BLAKE3 proof must not verify when checked with SHA-256
+        let tree = MerkleTree::from_bytes_vec_with_algorithm(&data, HashAlgorithm::Blake3).unwrap();
+        let proof = tree.generate_proof(0).unwrap();
+        let leaf_hash = HashAlgorithm::Blake3.digest(b"a");
+        assert!(!MerkleTree::verify_proof(
+            &leaf_hash,
+            &proof,
+            tree.root_hash_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_serialization_preserves_algorithm() {
+        let data = vec![b"a".to_vec(), b"b".to_vec()];
+        let tree = MerkleTree::from_bytes_vec_with_algorithm(&data, HashAlgorithm::Blake3).unwrap();
+
+        let json = tree.to_json().unwrap();
+        assert!(json.contains("\"blake3\""));
+
+        let tree2 = MerkleTree::from_json(&json).unwrap();
+        assert_eq!(tree2.algorithm(), HashAlgorithm::Blake3);
+        let proof = tree2.generate_proof(1).unwrap();
+        assert!(
+            tree2
+                .verify(&HashAlgorithm::Blake3.digest(b"b"), &proof)
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn test_borsh_roundtrip() {
+        let data: Vec<Vec<u8>> = (0..13u8).map(|i| vec![i]).collect();
+        let config = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            leaf_salt: b"salt".to_vec(),
+            ..TreeConfig::rfc6962()
+        };
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, config).unwrap();
+
+        let bytes = tree.to_borsh().unwrap();
+        assert_eq!(bytes, tree.to_borsh().unwrap());
+        let restored = MerkleTree::from_borsh(&bytes).unwrap();
+        assert_eq!(restored.levels(), tree.levels());
+        assert_eq!(restored.config(), tree.config());
+
+        // Hashes are raw bytes rather than JSON number arrays
+        let json_len = tree.to_json().unwrap().len();
+        assert!(
+            bytes.len() * 3 < json_len,
+            "{} vs {}",
+            bytes.len(),
+            json_len
+        );
+
+        for node in tree.generate_proof(5).unwrap() {
+            assert_eq!(
+                ProofNode::from_borsh(&node.to_borsh().unwrap()).unwrap(),
+                node
+            );
+        }
+
+        let mut future = bytes.clone();
+        future[0] = 2;
+        assert!(matches!(
+            MerkleTree::from_borsh(&future),
+            Err(MerkleError::UnsupportedFormatVersion { found: 2, .. })
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            MerkleTree::from_borsh(&trailing),
+            Err(MerkleError::Io(_))
+        ));
+        assert!(MerkleTree::from_borsh(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_serialization_format_version() {
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(value["format_version"], FORMAT_VERSION);
+
+        let v1 = value.to_string();
+        let loaded = MerkleTree::from_json(&v1).unwrap();
+        assert_eq!(
+            loaded.root_hash_ref().unwrap(),
+            tree.root_hash_ref().unwrap()
+        );
+
+        value["format_version"] = 999.into();
+        assert!(matches!(
+            MerkleTree::from_json(&value.to_string()),
+            Err(MerkleError::UnsupportedFormatVersion {
+                found: 999,
+                supported: 1
+            })
+        ));
+
+        value["format_version"] = "one".into();
+        assert!(matches!(
+            MerkleTree::from_json(&value.to_string()),
+            Err(MerkleError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_try_from_levels_rejects_malformed_shapes() {
+        let tree =
+            MerkleTree::from_bytes_vec(&(0..5u8).map(|i| vec![i]).collect::<Vec<_>>()).unwrap();
+        let levels = tree.levels().to_vec();
+        let rebuilt = MerkleTree::try_from_levels(levels.clone()).unwrap();
+        assert_eq!(rebuilt.levels(), tree.levels());
+
+        let check = |levels: Vec<Vec<Hash>>| MerkleTree::try_from_levels(levels).unwrap_err();
+        assert!(matches!(check(Vec::new()), MerkleError::EmptyLeaves));
+        assert!(matches!(check(vec![Vec::new()]), MerkleError::EmptyLeaves));
+
+        // A level with a node too many, a missing root and a level past the root
+        let mut wide = levels.clone();
+        wide[1].push(levels[1][0].clone());
+        assert!(matches!(
+            check(wide),
+            MerkleError::InvalidLevelSize {
+                level: 1,
+                expected: 3,
+                found: 4
+            }
+        ));
+        let mut headless = levels.clone();
+        headless.pop();
+        assert!(matches!(
+            check(headless),
+            MerkleError::InvalidLevelSize {
+                level: 3,
+                expected: 1,
+                found: 0
+            }
+        ));
+        let mut tall = levels.clone();
+        tall.push(levels[3].clone());
+        assert!(matches!(
+            check(tall),
+            MerkleError::InvalidLevelSize {
+                level: 4,
+                expected: 0,
+                found: 1
+            }
+        ));
+
+        // Ragged leaves and a short internal node
+        let mut ragged = levels.clone();
+        ragged[0][4].pop();
+        assert!(matches!(
+            check(ragged),
+            MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 31
+            }
+        ));
+        let mut short = levels.clone();
+        short[2][0].truncate(20);
+        assert!(matches!(
+            check(short),
+            MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 20
+            }
+        ));
+
+        // Promoted leaves keep their own length
+        let config = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..TreeConfig::default()
+        };
+        let leaves = (0..3u8).map(|i| vec![i; 20]).collect();
+        let promoted = MerkleTree::from_leaves_with_config(leaves, config.clone()).unwrap();
+        assert!(
+            MerkleTree::try_from_levels_with_config(promoted.levels().to_vec(), config).is_ok()
+        );
+
+        // Deserialization goes through the same checks
+        let mut value: serde_json::Value = serde_json::from_str(&tree.to_json().unwrap()).unwrap();
+        value["levels"].as_array_mut().unwrap().pop();
+        assert!(matches!(
+            MerkleTree::from_json(&value.to_string()),
+            Err(MerkleError::InvalidLevelSize { level: 3, .. })
+        ));
+        assert!(serde_json::from_value::<MerkleTree>(value).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_without_algorithm_defaults_to_sha256() {
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        let legacy = serde_json::json!({ "levels": tree.levels }).to_string();
+
+        let tree2 = MerkleTree::from_json(&legacy).unwrap();
+        assert_eq!(tree2.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(
+            tree2.root_hash_ref().unwrap(),
+            tree.root_hash_ref().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_nodes() {
+        let leaves = vec![sha256(b"a"), sha256(b"b"), sha256(b"c")];
+        let config = TreeConfig::length_prefixed();
+        let plain = MerkleTree::from_leaves(leaves.clone()).unwrap();
+        let prefixed = MerkleTree::from_leaves_with_config(leaves.clone(), config.clone()).unwrap();
+        assert_ne!(
+            plain.root_hash_ref().unwrap(),
+            prefixed.root_hash_ref().unwrap()
+        );
+
+        // len_le || left || len_le || right, all the way up
+        let prefixed_pair = |left: &[u8], right: &[u8]| {
+            let len = 32u64.to_le_bytes();
+            sha256(&[&len, left, &len, right].concat())
+        };
+        let ab = prefixed_pair(&leaves[0], &leaves[1]);
+        let cc = prefixed_pair(&leaves[2], &leaves[2]);
+        assert_eq!(
+            prefixed.root_hash_ref().unwrap(),
+            prefixed_pair(&ab, &cc).as_slice()
+        );
+        assert_eq!(
+            plain.root_hash_ref().unwrap(),
+            sha256(
+                &[
+                    sha256(&[&leaves[0][..], &leaves[1]].concat()),
+                    sha256(&[&leaves[2][..], &leaves[2]].concat())
+                ]
+                .concat()
+            )
+            .as_slice()
+        );
+
+        // Children that split differently no longer hash alike
+        let plain_config = TreeConfig::default();
+        assert_eq!(
+            plain_config.hash_concat(b"ab", b"c"),
+            plain_config.hash_concat(b"a", b"bc")
+        );
+        assert_ne!(
+            config.hash_concat(b"ab", b"c"),
+            config.hash_concat(b"a", b"bc")
+        );
+
+        // Proofs verify only under the config the tree was built with
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = prefixed.generate_proof(i).unwrap();
+            assert!(prefixed.verify(leaf, &proof).unwrap());
+            let root = prefixed.root_hash_ref().unwrap();
+            assert!(MerkleTree::verify_proof_with_config(
+                leaf, &proof, root, &config
+            ));
+            assert!(!MerkleTree::verify_proof_with_config(
+                leaf,
+                &proof,
+                root,
+                &plain_config
+            ));
+        }
+
+        // The flag survives serialization and is omitted while off
+        let restored = MerkleTree::from_json(&prefixed.to_json().unwrap()).unwrap();
+        assert!(restored.config().length_prefixed_nodes);
+        assert!(!plain.to_json().unwrap().contains("length_prefixed_nodes"));
+    }
+
+    #[test]
+    fn test_rfc6962_domain_separation() {
+        let leaves = vec![sha256(b"a"), sha256(b"b"), sha256(b"c")];
+        let plain = MerkleTree::from_leaves(leaves.clone()).unwrap();
+        let tagged = MerkleTree::from_leaves_rfc6962(leaves.clone()).unwrap();
+
+        assert!(tagged.config().domain_separation);
+        assert_ne!(
+            plain.root_hash_ref().unwrap(),
+            tagged.root_hash_ref().unwrap()
+        );
+
+        let config = TreeConfig::rfc6962();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let leaf_hash = config.hash_leaf(leaf);
+            let proof = tagged.generate_proof(i).unwrap();
+            assert!(tagged.verify(&leaf_hash, &proof).unwrap());
+            assert!(MerkleTree::verify_proof_with_config(
+                &leaf_hash,
+                &proof,
+                tagged.root_hash_ref().unwrap(),
+                &config
+            ));
+            // Untagged verification must not accept the tagged proof
+            assert!(!MerkleTree::verify_proof(
+                &leaf_hash,
+                &proof,
+                tagged.root_hash_ref().unwrap()
+            ));
+        }
+
+        // Existing untagged trees keep verifying
+        let proof = plain.generate_proof(1).unwrap();
+        assert!(plain.verify(&leaves[1], &proof).unwrap());
+    }
+
+    #[test]
+    fn test_rfc6962_rejects_internal_node_as_leaf() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+        let config = TreeConfig::rfc6962();
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+
+        // Present the first internal node as a leaf of a 2-leaf tree
+        let internal = tree.levels[1][0].clone();
+        let forged_proof = vec![ProofNode {
+            hash: tree.levels[1][1].clone(),
+            is_left: false,
+            duplicate: false,
+        }];
+
+        // The untagged construction would accept it; the tagged one must not
+        let forged_leaf = config.hash_leaf(&internal);
+        assert!(!tree.verify(&forged_leaf, &forged_proof).unwrap());
+        assert!(tree.verify(&internal, &forged_proof).unwrap());
     }
 
     #[test]
-    fn test_three_leaves_odd_duplication() {
-        // Tests duplication of last node when odd
-        let data = vec![b"alpha".to_vec(), b"bravo".to_vec(), b"charlie".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
-        assert_eq!(tree.leaf_count(), 3);
+    fn test_rfc6962_serialization_roundtrip() {
+        let tree = MerkleTree::from_leaves_rfc6962(vec![sha256(b"a"), sha256(b"b")]).unwrap();
+        let tree2 = MerkleTree::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(tree2.config(), &TreeConfig::rfc6962());
+        assert_eq!(
+            tree.root_hash_ref().unwrap(),
+            tree2.root_hash_ref().unwrap()
+        );
+    }
 
-        for i in 0..3 {
-            let leaf_hash = sha256(&data[i]);
-            let proof = tree.generate_proof(i).unwrap();
+    #[test]
+    fn test_odd_strategies_compared() {
+        let promote = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..TreeConfig::default()
+        };
+
+        for n in [3usize, 5, 7] {
+            let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8]).collect();
+            let dup_tree = MerkleTree::from_bytes_vec(&data).unwrap();
+            let promo_tree =
+                MerkleTree::from_bytes_vec_with_config(&data, promote.clone()).unwrap();
+
+            assert_eq!(dup_tree.tree_height(), promo_tree.tree_height());
+            assert_ne!(
+                dup_tree.root_hash_ref().unwrap(),
+                promo_tree.root_hash_ref().unwrap(),
+                "roots should differ for {} leaves",
+                n
+            );
+
+            for (i, item) in data.iter().enumerate() {
+                let leaf_hash = sha256(item);
+                let dup_proof = dup_tree.generate_proof(i).unwrap();
+                let promo_proof = promo_tree.generate_proof(i).unwrap();
+
+                assert_eq!(dup_proof.len(), dup_tree.tree_height() - 1);
+                assert!(promo_proof.len() <= dup_proof.len());
+                assert!(dup_tree.verify(&leaf_hash, &dup_proof).unwrap());
+                assert!(promo_tree.verify(&leaf_hash, &promo_proof).unwrap());
+            }
+
+            // The last leaf is promoted at least once, so its proof is shorter
+            let last = n - 1;
             assert!(
-                tree.verify(&leaf_hash, &proof).unwrap(),
-                "proof for index {} should verify",
-                i
+                promo_tree.generate_proof(last).unwrap().len()
+                    < dup_tree.generate_proof(last).unwrap().len()
             );
         }
     }
 
     #[test]
-    fn test_power_of_two_leaves() {
-        // 4 leaves = perfect binary tree
-        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
-        assert_eq!(tree.leaf_count(), 4);
-        assert_eq!(tree.tree_height(), 3); // leaves, intermediate, root
+    fn test_promote_avoids_duplicate_leaf_collision() {
+        let promote = TreeConfig {
+            odd_strategy: OddStrategy::Promote,
+            ..TreeConfig::default()
+        };
+        let three = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let four = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"c".to_vec()];
 
-        // All proofs should have same length
-        for i in 0..4 {
-            let proof = tree.generate_proof(i).unwrap();
-            assert_eq!(proof.len(), 2); // log2(4) = 2
+        // Duplication makes [a, b, c] and [a, b, c, c] indistinguishable
+        assert_eq!(
+            MerkleTree::from_bytes_vec(&three)
+                .unwrap()
+                .root_hash_ref()
+                .unwrap(),
+            MerkleTree::from_bytes_vec(&four)
+                .unwrap()
+                .root_hash_ref()
+                .unwrap()
+        );
+        assert_ne!(
+            MerkleTree::from_bytes_vec_with_config(&three, promote.clone())
+                .unwrap()
+                .root_hash_ref()
+                .unwrap(),
+            MerkleTree::from_bytes_vec_with_config(&four, promote)
+                .unwrap()
+                .root_hash_ref()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_concat_matches_batch_build() {
+        let configs = [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+            TreeConfig {
+                pair_ordering: OrderedPairs::Sorted,
+                ..TreeConfig::default()
+            },
+        ];
+
+        let leaves: Vec<Hash> = (0..24u8).map(|i| sha256(&[i])).collect();
+        for config in configs {
+            for split in 1..leaves.len() {
+                for end in split + 1..=leaves.len() {
+                    let build = |range: &[Hash]| {
+                        MerkleTree::from_leaves_with_config(range.to_vec(), config.clone()).unwrap()
+                    };
+                    let merged =
+                        MerkleTree::concat(build(&leaves[..split]), build(&leaves[split..end]))
+                            .unwrap();
+                    assert_eq!(
+                        merged.levels,
+                        build(&leaves[..end]).levels,
+                        "{} + {} leaves with {:?}",
+                        split,
+                        end - split,
+                        config
+                    );
+                }
+            }
         }
     }
 
     #[test]
-    fn test_verify_fails_if_tampered() {
-        let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
-        let leaf_hash = sha256(&files[2]);
-        let mut proof = tree.generate_proof(2).unwrap();
+    fn test_concat_rejects_mismatched_trees() {
+        let tree = MerkleTree::from_leaves(vec![sha256(b"a")]).unwrap();
+        let rfc =
+            MerkleTree::from_leaves_with_config(vec![sha256(b"b")], TreeConfig::rfc6962()).unwrap();
+        assert!(matches!(
+            MerkleTree::concat(tree.clone(), rfc),
+            Err(MerkleError::ConfigMismatch)
+        ));
 
-        // Tamper with proof
-        proof[0].hash[0] ^= 0xff;
-        assert!(!tree.verify(&leaf_hash, &proof).unwrap());
+        let short = MerkleTree::from_leaves(vec![vec![0u8; 20]]).unwrap();
+        assert!(matches!(
+            MerkleTree::concat(tree, short),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 20
+            })
+        ));
     }
 
     #[test]
-    fn test_verify_fails_wrong_leaf() {
-        let files = vec![b"a".to_vec(), b"b".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
-        let proof = tree.generate_proof(0).unwrap();
+    fn test_push_leaf_matches_batch_build() {
+        let configs = [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ];
 
-        // Try to verify with wrong leaf
-        let wrong_leaf = sha256(b"wrong");
-        assert!(!tree.verify(&wrong_leaf, &proof).unwrap());
+        for config in configs {
+            let leaves: Vec<Hash> = (0..20u8).map(|i| sha256(&[i])).collect();
+            let mut tree =
+                MerkleTree::from_leaves_with_config(vec![leaves[0].clone()], config.clone())
+                    .unwrap();
+
+            for n in 2..=leaves.len() {
+                tree.push_leaf(leaves[n - 1].clone()).unwrap();
+                let batch =
+                    MerkleTree::from_leaves_with_config(leaves[..n].to_vec(), config.clone())
+                        .unwrap();
+
+                assert_eq!(tree.levels, batch.levels, "levels differ at {} leaves", n);
+                assert_eq!(tree.tree_height(), batch.tree_height());
+                for (i, leaf) in leaves[..n].iter().enumerate() {
+                    let proof = tree.generate_proof(i).unwrap();
+                    assert_eq!(proof, batch.generate_proof(i).unwrap());
+                    assert!(tree.verify(leaf, &proof).unwrap());
+                }
+            }
+        }
+
+        // Leaves of the wrong length are refused without touching the tree
+        let mut tree = MerkleTree::from_leaves(vec![sha256(b"a")]).unwrap();
+        assert!(matches!(
+            tree.push_leaf(vec![0; 20]),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 20
+            })
+        ));
+        assert_eq!(tree.leaf_count(), 1);
+        assert!(matches!(
+            MerkleTree::empty().push_leaf(b"short".to_vec()),
+            Err(MerkleError::InconsistentHashLength {
+                expected: 32,
+                found: 5
+            })
+        ));
+
+        // Truncated leaves keep the length the tree was built with
+        let mut tree = MerkleTree::from_leaves(vec![vec![1; 20]]).unwrap();
+        tree.push_leaf(vec![2; 20]).unwrap();
+        assert_eq!(tree.leaf_count(), 2);
     }
 
     #[test]
-    fn test_empty_leaves_error() {
-        let empty: Vec<Vec<u8>> = vec![];
-        let result = MerkleTree::from_bytes_vec(&empty);
-        assert!(matches!(result, Err(MerkleError::EmptyLeaves)));
+    fn test_update_leaf_matches_rebuild() {
+        let configs = [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ];
+
+        for config in configs {
+            for n in [4usize, 5, 9] {
+                let mut leaves: Vec<Hash> = (0..n as u8).map(|i| sha256(&[i])).collect();
+                let mut tree =
+                    MerkleTree::from_leaves_with_config(leaves.clone(), config.clone()).unwrap();
+
+                for index in 0..n {
+                    let new_leaf = sha256(format!("updated{}", index).as_bytes());
+                    tree.update_leaf(index, new_leaf.clone()).unwrap();
+                    leaves[index] = new_leaf;
+
+                    let rebuilt =
+                        MerkleTree::from_leaves_with_config(leaves.clone(), config.clone())
+                            .unwrap();
+                    assert_eq!(
+                        tree.levels, rebuilt.levels,
+                        "levels differ after updating leaf {} of {}",
+                        index, n
+                    );
+                    for (i, leaf) in leaves.iter().enumerate() {
+                        let proof = tree.generate_proof(i).unwrap();
+                        assert!(tree.verify(leaf, &proof).unwrap());
+                    }
+                }
+
+                assert!(matches!(
+                    tree.update_leaf(n, sha256(b"x")),
+                    Err(MerkleError::IndexOutOfBounds { index, leaf_count })
+                        if index == n && leaf_count == n
+                ));
+            }
+        }
     }
 
     #[test]
-    fn test_index_out_of_bounds() {
-        let data = vec![b"a".to_vec(), b"b".to_vec()];
-        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+    fn test_named_leaf_encoding() {
+        let config = TreeConfig::default();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&5u64.to_le_bytes());
+        expected.extend_from_slice(b"a.txt");
+        expected.extend_from_slice(b"alpha");
+        assert_eq!(config.hash_named_leaf("a.txt", b"alpha"), sha256(&expected));
 
-        let result = tree.generate_proof(2);
-        assert!(matches!(result, Err(MerkleError::IndexOutOfBounds { .. })));
+        // The length prefix separates name from contents
+        assert_ne!(
+            config.hash_named_leaf("ab", b"c"),
+            config.hash_named_leaf("a", b"bc")
+        );
+
+        let rfc = TreeConfig::rfc6962();
+        let mut tagged = vec![LEAF_PREFIX];
+        tagged.extend_from_slice(&expected);
+        assert_eq!(rfc.hash_named_leaf("a.txt", b"alpha"), sha256(&tagged));
+        assert_eq!(
+            rfc.hash_named_leaf_reader("a.txt", &b"alpha"[..]).unwrap(),
+            sha256(&tagged)
+        );
     }
 
     #[test]
-    fn test_get_leaves() {
-        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    fn test_named_files_detect_swapped_names() {
+        let entries = vec![
+            ("a.txt".to_string(), b"alpha".to_vec()),
+            ("b.txt".to_string(), b"bravo".to_vec()),
+            ("c.txt".to_string(), b"charlie".to_vec()),
+        ];
+        let tree = MerkleTree::from_named_files(&entries).unwrap();
+        let config = TreeConfig::default();
+
+        // Contents-only leaves carry no name, so nothing ties a proof to one
+        let contents: Vec<Vec<u8>> = entries.iter().map(|(_, c)| c.clone()).collect();
+        let plain = MerkleTree::from_bytes_vec(&contents).unwrap();
+        let plain_proof = plain.generate_proof(1).unwrap();
+        assert!(plain.verify(&sha256(b"bravo"), &plain_proof).unwrap());
+
+        // Serving b.txt's contents and proof under the name a.txt fails
+        let proof_b = tree.generate_proof(1).unwrap();
+        assert!(
+            tree.verify(&config.hash_named_leaf("b.txt", b"bravo"), &proof_b)
+                .unwrap()
+        );
+        assert!(
+            !tree
+                .verify(&config.hash_named_leaf("a.txt", b"bravo"), &proof_b)
+                .unwrap()
+        );
+        let proof_a = tree.generate_proof(0).unwrap();
+        assert!(
+            !tree
+                .verify(&config.hash_named_leaf("a.txt", b"bravo"), &proof_a)
+                .unwrap()
+        );
+
+        // Renaming changes the root
+        let renamed = vec![
+            ("a.txt".to_string(), b"bravo".to_vec()),
+            ("b.txt".to_string(), b"alpha".to_vec()),
+            entries[2].clone(),
+        ];
+        let renamed_tree = MerkleTree::from_named_files(&renamed).unwrap();
+        assert_ne!(
+            renamed_tree.root_hash_ref().unwrap(),
+            tree.root_hash_ref().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_named_streaming_matches_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entries = Vec::new();
+        let mut paths = Vec::new();
+        for (name, contents) in [("a.txt", &b"alpha"[..]), ("b.txt", &b"bravo"[..])] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            entries.push((name.to_string(), contents.to_vec()));
+            paths.push((name.to_string(), path));
+        }
+
+        let in_memory = MerkleTree::from_named_files(&entries).unwrap();
+        let streamed = MerkleTree::from_named_file_paths_streaming(&paths).unwrap();
+        assert_eq!(in_memory.levels, streamed.levels);
+    }
+
+    #[test]
+    fn test_streaming_file_hash_matches_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // 3 MiB + a partial chunk, with non-repeating content
+        let big: Vec<u8> = (0..(3 * 1024 * 1024 + 1234))
+            .map(|i: u32| (i.wrapping_mul(31) >> 3) as u8)
+            .collect();
+        let big_path = dir.path().join("big.bin");
+        std::fs::write(&big_path, &big).unwrap();
+
+        let small = b"small file".to_vec();
+        let small_path = dir.path().join("small.txt");
+        std::fs::write(&small_path, &small).unwrap();
+
+        assert_eq!(sha256_file(&big_path).unwrap(), sha256(&big));
+
+        let streamed = MerkleTree::from_file_paths_streaming(&[&big_path, &small_path]).unwrap();
+        let in_memory = MerkleTree::from_bytes_vec(&[big.clone(), small.clone()]).unwrap();
+        assert_eq!(streamed.get_leaves(), in_memory.get_leaves());
+        assert_eq!(
+            streamed.root_hash_ref().unwrap(),
+            in_memory.root_hash_ref().unwrap()
+        );
+
+        let config = TreeConfig::rfc6962();
+        let streamed =
+            MerkleTree::from_file_paths_streaming_with_config(&[&big_path], config.clone())
+                .unwrap();
+        assert_eq!(streamed.get_leaves()[0], config.hash_leaf(&big));
+    }
+
+    #[test]
+    fn test_streaming_missing_file_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = MerkleTree::from_file_paths_streaming(&[dir.path().join("missing")]);
+        assert!(matches!(result, Err(MerkleError::Io(_))));
+    }
+
+    #[test]
+    fn test_diff_two_changed_leaves() {
+        let old: Vec<Vec<u8>> = (0..16).map(|i| format!("file{}", i).into_bytes()).collect();
+        let mut new = old.clone();
+        new[3] = b"changed3".to_vec();
+        new[12] = b"changed12".to_vec();
+
+        let old_tree = MerkleTree::from_bytes_vec(&old).unwrap();
+        let new_tree = MerkleTree::from_bytes_vec(&new).unwrap();
+
+        assert_eq!(old_tree.diff(&new_tree), vec![3, 12]);
+        assert_eq!(new_tree.diff(&old_tree), vec![3, 12]);
+        assert!(old_tree.diff(&old_tree).is_empty());
+    }
+
+    #[test]
+    fn test_diff_empty_trees() {
+        let empty = MerkleTree::empty();
+        let tree =
+            MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]).unwrap();
+
+        assert!(empty.diff(&MerkleTree::empty()).is_empty());
+        assert_eq!(empty.diff(&tree), vec![0, 1, 2]);
+        assert_eq!(tree.diff(&empty), vec![0, 1, 2]);
+        assert!(
+            empty
+                .explain_mismatch(&MerkleTree::empty())
+                .starts_with("trees are identical")
+        );
+        assert!(empty.explain_mismatch(&tree).contains("level 0, node 0"));
+        assert!(empty.to_dot().starts_with("digraph merkle {"));
+        assert_eq!(empty.all_proofs().count(), 0);
+
+        let restored = MerkleTree::from_json(&empty.to_json().unwrap()).unwrap();
+        assert_eq!(restored.root_hash_ref().unwrap(), EMPTY_ROOT);
+
+        let config = TreeConfig::with_algorithm(HashAlgorithm::Sha512);
+        let empty512 = MerkleTree::empty_with_config(config);
+        let restored = MerkleTree::from_json(&empty512.to_json().unwrap()).unwrap();
+        assert_eq!(restored.algorithm(), HashAlgorithm::Sha512);
+        assert_eq!(
+            restored.root_hash_ref().unwrap(),
+            HashAlgorithm::Sha512.empty_root()
+        );
+        #[cfg(feature = "borsh")]
+        assert_eq!(
+            MerkleTree::from_borsh(&empty.to_borsh().unwrap())
+                .unwrap()
+                .leaf_count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_diff_different_leaf_counts() {
+        let base: Vec<Vec<u8>> = (0..5).map(|i| vec![i]).collect();
+        let mut grown = base.clone();
+        grown.extend((5..9).map(|i| vec![i]));
+        grown[1] = b"x".to_vec();
+
+        let small = MerkleTree::from_bytes_vec(&base).unwrap();
+        let large = MerkleTree::from_bytes_vec(&grown).unwrap();
+
+        assert_eq!(small.diff(&large), vec![1, 5, 6, 7, 8]);
+        assert_eq!(large.diff(&small), vec![1, 5, 6, 7, 8]);
+
+        // [a, b, c] and [a, b, c, c] share a root under duplication
+        let three = MerkleTree::from_bytes_vec(&base[..3]).unwrap();
+        let four = MerkleTree::from_bytes_vec(&[
+            base[0].clone(),
+            base[1].clone(),
+            base[2].clone(),
+            base[2].clone(),
+        ])
+        .unwrap();
+        assert_eq!(three.diff(&four), vec![3]);
+    }
+
+    #[test]
+    fn test_compute_root_with_trace() {
+        for config in [TreeConfig::default(), TreeConfig::rfc6962()] {
+            let data: Vec<Vec<u8>> = (0..11u8).map(|i| vec![i]).collect();
+            let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+            let root = tree.root_hash_ref().unwrap();
+
+            for (i, leaf) in tree.get_leaves().iter().enumerate() {
+                let proof = tree.generate_proof(i).unwrap();
+                let (computed, trace) =
+                    MerkleTree::compute_root_with_trace_with_config(leaf, &proof, &config);
+                assert_eq!(computed, root);
+                assert_eq!(trace.len(), proof.len());
+                assert_eq!(trace.last().unwrap(), root);
+                // each step is the leaf's ancestor one level further up
+                for (level, node) in trace.iter().enumerate() {
+                    assert_eq!(node, &tree.levels()[level + 1][i >> (level + 1)]);
+                }
+            }
+        }
+
+        let (root, trace) = MerkleTree::compute_root_with_trace(b"leaf", &[]);
+        assert_eq!(root, b"leaf");
+        assert!(trace.is_empty());
+    }
+
+    #[test]
+    fn test_equals_structurally() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
         let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert!(tree.equals_structurally(&tree.clone()));
+        let rebuilt = MerkleTree::from_json(&tree.to_json_leaves_only().unwrap()).unwrap();
+        assert!(tree.equals_structurally(&rebuilt));
 
-        let leaves = tree.get_leaves();
-        assert_eq!(leaves.len(), 3);
-        assert_eq!(leaves[0], sha256(b"a"));
-        assert_eq!(leaves[1], sha256(b"b"));
-        assert_eq!(leaves[2], sha256(b"c"));
+        // Duplicating the odd last leaf leaves the root unchanged
+        let mut padded = data.clone();
+        padded.push(data[4].clone());
+        let padded = MerkleTree::from_bytes_vec(&padded).unwrap();
+        assert_eq!(
+            tree.root_hash_ref().unwrap(),
+            padded.root_hash_ref().unwrap()
+        );
+        assert!(!tree.equals_structurally(&padded));
+
+        // Same levels, but proofs would verify under a different config
+        let sorted = TreeConfig {
+            pair_ordering: OrderedPairs::Sorted,
+            ..TreeConfig::default()
+        };
+        let single = MerkleTree::from_leaves(vec![sha256(b"x")]).unwrap();
+        let single_sorted =
+            MerkleTree::from_leaves_with_config(vec![sha256(b"x")], sorted).unwrap();
+        assert_eq!(single.levels(), single_sorted.levels());
+        assert!(!single.equals_structurally(&single_sorted));
     }
 
     #[test]
-    fn test_serialization() {
-        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    fn test_explain_mismatch() {
+        let data: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i]).collect();
+        let mut changed = data.clone();
+        changed[5] = b"x".to_vec();
         let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let other = MerkleTree::from_bytes_vec(&changed).unwrap();
 
-        // Serialize
-        let json = tree.to_json().unwrap();
-        assert!(json.contains("levels"));
+        assert_eq!(
+            tree.explain_mismatch(&other),
+            "first divergence at level 0, node 5 (this tree has 8 leaves, the other 8)"
+        );
+        assert_eq!(
+            tree.explain_mismatch(&tree),
+            "trees are identical (this tree has 8 leaves, the other 8)"
+        );
 
-        // Deserialize
-        let tree2 = MerkleTree::from_json(&json).unwrap();
+        let shorter = MerkleTree::from_bytes_vec(&data[..6]).unwrap();
+        assert!(tree.explain_mismatch(&shorter).starts_with(
+            "first divergence at level 0, node 6 (this tree has 8 leaves, the other 6)"
+        ));
+
+        // Same leaves, different internal hashing
+        let leaves = tree.get_leaves().to_vec();
+        let rfc = MerkleTree::from_leaves_with_config(leaves, TreeConfig::rfc6962()).unwrap();
         assert_eq!(
+            tree.explain_mismatch(&rfc),
+            "first divergence at level 1, node 0 (this tree has 8 leaves, the other 8); tree configs differ"
+        );
+    }
+
+    #[test]
+    fn test_sorted_pairs_ignore_is_left() {
+        let sorted = TreeConfig {
+            pair_ordering: OrderedPairs::Sorted,
+            ..TreeConfig::default()
+        };
+        let data: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, sorted.clone()).unwrap();
+        let positional = MerkleTree::from_bytes_vec(&data).unwrap();
+        assert_ne!(
             tree.root_hash_ref().unwrap(),
-            tree2.root_hash_ref().unwrap()
+            positional.root_hash_ref().unwrap()
         );
-        assert_eq!(tree.leaf_count(), tree2.leaf_count());
+
+        for (i, item) in data.iter().enumerate() {
+            let leaf_hash = sha256(item);
+            let mut proof = tree.generate_proof(i).unwrap();
+            assert!(tree.verify(&leaf_hash, &proof).unwrap());
+
+            // Flipping every direction flag must not matter
+            for node in proof.iter_mut() {
+                node.is_left = !node.is_left;
+            }
+            assert!(tree.verify(&leaf_hash, &proof).unwrap());
+            assert!(MerkleTree::verify_proof_with_config(
+                &leaf_hash,
+                &proof,
+                tree.root_hash_ref().unwrap(),
+                &sorted
+            ));
+        }
+
+        // Positional trees still depend on the flags
+        let mut proof = positional.generate_proof(0).unwrap();
+        proof[0].is_left = !proof[0].is_left;
+        assert!(!positional.verify(&sha256(&data[0]), &proof).unwrap());
     }
 
     #[test]
-    fn test_large_tree() {
-        // Test with 100 leaves
-        let data: Vec<Vec<u8>> = (0..100)
-            .map(|i| format!("data{}", i).into_bytes())
+    fn test_sorted_pairs_serialization() {
+        let config = TreeConfig {
+            pair_ordering: OrderedPairs::Sorted,
+            ..TreeConfig::default()
+        };
+        let tree =
+            MerkleTree::from_bytes_vec_with_config(&[b"a".to_vec(), b"b".to_vec()], config.clone())
+                .unwrap();
+        let json = tree.to_json().unwrap();
+        assert!(json.contains("\"pair_ordering\":\"sorted\""));
+        assert_eq!(MerkleTree::from_json(&json).unwrap().config(), &config);
+    }
+
+    #[test]
+    fn test_from_reader_iter_lazy() {
+        let produced = std::cell::Cell::new(0);
+        let items = (0..1000u32).map(|i| {
+            produced.set(produced.get() + 1);
+            format!("row{}", i).into_bytes()
+        });
+        let tree = MerkleTree::from_reader_iter(items).unwrap();
+        assert_eq!(produced.get(), 1000);
+        assert_eq!(tree.leaf_count(), 1000);
+
+        let collected: Vec<Vec<u8>> = (0..1000u32)
+            .map(|i| format!("row{}", i).into_bytes())
             .collect();
+        let batch = MerkleTree::from_bytes_vec(&collected).unwrap();
+        assert_eq!(
+            tree.root_hash_ref().unwrap(),
+            batch.root_hash_ref().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_reader_iter_empty() {
+        let result = MerkleTree::from_reader_iter(std::iter::empty::<Vec<u8>>());
+        assert!(matches!(result, Err(MerkleError::EmptyLeaves)));
+    }
 
+    #[test]
+    fn test_levels_accessors() {
+        let data = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
         let tree = MerkleTree::from_bytes_vec(&data).unwrap();
-        assert_eq!(tree.leaf_count(), 100);
 
-        // Verify all proofs
-        for i in 0..100 {
-            let leaf_hash = sha256(&data[i]);
-            let proof = tree.generate_proof(i).unwrap();
-            assert!(tree.verify(&leaf_hash, &proof).unwrap());
+        let levels = tree.levels();
+        assert_eq!(levels.len(), tree.tree_height());
+        assert_eq!(levels[0], tree.get_leaves());
+        assert_eq!(
+            levels.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
 
-            // Proof length should be log2(100) ≈ 7
-            assert!(proof.len() >= 6 && proof.len() <= 8);
+        assert_eq!(
+            tree.level_hashes_hex(0).unwrap(),
+            vec![
+                hex::encode(sha256(b"a")),
+                hex::encode(sha256(b"b")),
+                hex::encode(sha256(b"c"))
+            ]
+        );
+        assert_eq!(
+            tree.level_hashes_hex(2).unwrap(),
+            vec![hex::encode(tree.root_hash_ref().unwrap())]
+        );
+        assert!(matches!(
+            tree.level_hashes_hex(3),
+            Err(MerkleError::IndexOutOfBounds { index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_proof_checked_too_short() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let proof = tree.generate_proof(4).unwrap();
+        assert!(MerkleTree::verify_proof_checked(&sha256(&[4]), &proof, root, 5).unwrap());
+
+        let result = MerkleTree::verify_proof_checked(&sha256(&[4]), &proof[..2], root, 5);
+        assert!(matches!(
+            result,
+            Err(MerkleError::MalformedProof {
+                expected_len: 3,
+                actual_len: 2
+            })
+        ));
+        // A well-formed proof for the wrong leaf is still just false
+        assert!(!MerkleTree::verify_proof_checked(&sha256(b"x"), &proof, root, 5).unwrap());
+    }
+
+    #[test]
+    fn test_verify_proof_hex() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let root_hex = hex::encode(tree.root_hash_ref().unwrap());
+        let proof = tree.generate_proof(3).unwrap();
+        let leaf_hex = hex::encode(sha256(&[3]));
+
+        assert!(MerkleTree::verify_proof_hex(&leaf_hex, &proof, &root_hex).unwrap());
+        assert!(
+            MerkleTree::verify_proof_hex(&leaf_hex, &proof, &format!("{}\n", root_hex)).unwrap()
+        );
+        assert!(
+            !MerkleTree::verify_proof_hex(&hex::encode(sha256(&[4])), &proof, &root_hex).unwrap()
+        );
+
+        assert!(matches!(
+            MerkleTree::verify_proof_hex("zz", &proof, &root_hex),
+            Err(MerkleError::InvalidHex { index: 0, .. })
+        ));
+        assert!(matches!(
+            MerkleTree::verify_proof_hex(&leaf_hex, &proof, &root_hex[1..]),
+            Err(MerkleError::InvalidHex { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_proof_any() {
+        let data: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let other = MerkleTree::from_bytes_vec(&data[..4]).unwrap();
+        let other_root = other.root_hash_ref().unwrap();
+        let proof = tree.generate_proof(2).unwrap();
+        let leaf = sha256(&[2]);
+
+        assert_eq!(
+            MerkleTree::verify_proof_any(&leaf, &proof, &[other_root, root]),
+            Some(1)
+        );
+        assert_eq!(
+            MerkleTree::verify_proof_any(&leaf, &proof, &[root, root]),
+            Some(0)
+        );
+        assert_eq!(
+            MerkleTree::verify_proof_any(&leaf, &proof, &[other_root]),
+            None
+        );
+        assert_eq!(MerkleTree::verify_proof_any(&leaf, &proof, &[]), None);
+        assert_eq!(
+            MerkleTree::verify_proof_any(&sha256(&[3]), &proof, &[other_root, root]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_verify_proof_checked_too_long() {
+        let data = vec![b"a".to_vec(), b"b".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&data).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let mut proof = tree.generate_proof(0).unwrap();
+        proof.push(proof[0].clone());
+
+        let result = MerkleTree::verify_proof_checked(&sha256(b"a"), &proof, root, 2);
+        assert!(matches!(
+            result,
+            Err(MerkleError::MalformedProof {
+                expected_len: 1,
+                actual_len: 2
+            })
+        ));
+        assert!(matches!(
+            MerkleTree::verify_proof_checked(&sha256(b"a"), &[], root, 0),
+            Err(MerkleError::EmptyLeaves)
+        ));
+        assert!(MerkleTree::verify_proof_checked(&sha256(b"a"), &[], &sha256(b"a"), 1).unwrap());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_build_matches_serial() {
+        let leaves: Vec<Hash> = (0..100_000u32).map(|i| sha256(&i.to_le_bytes())).collect();
+
+        for config in [
+            TreeConfig::default(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::rfc6962()
+            },
+        ] {
+            let mut serial = vec![leaves.clone()];
+            while serial.last().unwrap().len() > 1 {
+                let next = config.next_level_serial(serial.last().unwrap());
+                serial.push(next);
+            }
+
+            let parallel = MerkleTree::from_leaves_with_config(leaves.clone(), config).unwrap();
+            assert_eq!(parallel.levels(), serial.as_slice());
         }
     }
 }
@@ -0,0 +1,358 @@
+// Merkle mountain ranges for append-only logs
+//
+// An MMR is a list of perfect binary trees ("mountains") of strictly
+// decreasing height, one per set bit of the leaf count. Appending a leaf adds
+// a mountain of height 0 and merges equal-height neighbours, like carrying in
+// a binary counter, so nothing already hashed is ever rehashed. The root bags
+// the mountain peaks from right to left.
+//
+// Nodes are stored in postorder: each mountain's nodes are contiguous and end
+// with its peak, and within a mountain of height `h` the peak's left child
+// sits `2^h` positions before it and the right child directly before it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, MerkleError, Result, TreeConfig, constant_time_eq};
+
+/// An append-only accumulator with O(log n) inclusion proofs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MerkleMountainRange {
+    /// Every node, in postorder
+    nodes: Vec<Hash>,
+    /// Number of leaves appended so far
+    leaf_count: usize,
+    /// Hashing parameters for internal nodes and peak bagging
+    #[serde(flatten)]
+    config: TreeConfig,
+}
+
+/// An inclusion proof for one leaf of a mountain range.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MmrProof {
+    /// Number of leaves in the range the proof was generated from
+    pub leaf_count: usize,
+    /// Index of the proven leaf, in append order
+    pub leaf_index: usize,
+    /// Siblings from the leaf up to its mountain's peak
+    pub path: Vec<Hash>,
+    /// Peaks of the other mountains, left to right
+    pub peaks: Vec<Hash>,
+}
+
+/// A mountain: its height, first leaf index and the position of its peak.
+struct Mountain {
+    height: u32,
+    first_leaf: usize,
+    peak_pos: usize,
+}
+
+/// Largest leaf count whose node positions fit in a `usize`: a range has
+/// just under `2 * leaf_count` nodes, so the top bit must be clear.
+const MAX_LEAF_COUNT: usize = usize::MAX >> 1;
+
+/// Mountains of a range with `leaf_count` leaves, left to right.
+///
+/// `leaf_count` must not exceed `MAX_LEAF_COUNT`.
+fn mountains(leaf_count: usize) -> impl Iterator<Item = Mountain> {
+    let (mut first_leaf, mut next_pos) = (0, 0);
+    (0..usize::BITS).rev().filter_map(move |height| {
+        if leaf_count & (1 << height) == 0 {
+            return None;
+        }
+        let mountain = Mountain {
+            height,
+            first_leaf,
+            peak_pos: next_pos + (1 << (height + 1)) - 2,
+        };
+        first_leaf += 1 << height;
+        next_pos = mountain.peak_pos + 1;
+        Some(mountain)
+    })
+}
+
+impl MerkleMountainRange {
+    /// Empty range hashing with SHA-256.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Empty range hashing internal nodes with `config`.
+    pub fn with_config(config: TreeConfig) -> Self {
+        MerkleMountainRange {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Append a leaf hash and return its index.
+    ///
+    /// Only the nodes completed by this leaf are hashed: one per trailing one
+    /// bit of the previous leaf count, so appends are O(1) amortized.
+    pub fn append(&mut self, leaf: Hash) -> usize {
+        let index = self.leaf_count;
+        self.nodes.push(leaf);
+        for height in 0..index.trailing_ones() {
+            let right = self.nodes.len() - 1;
+            let left = right + 1 - (1 << (height + 1));
+            let parent = self
+                .config
+                .hash_concat(&self.nodes[left], &self.nodes[right]);
+            self.nodes.push(parent);
+        }
+        self.leaf_count += 1;
+        index
+    }
+
+    /// Number of leaves appended so far.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Hashing parameters of the range.
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    /// Peak hashes, left (tallest) to right.
+    pub fn peaks(&self) -> Vec<Hash> {
+        mountains(self.leaf_count)
+            .map(|m| self.nodes[m.peak_pos].clone())
+            .collect()
+    }
+
+    /// Root over all leaves: the peaks bagged from right to left.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if nothing has been appended.
+    pub fn root(&self) -> Result<Hash> {
+        bag_peaks(&self.peaks(), &self.config).ok_or(MerkleError::EmptyLeaves)
+    }
+
+    /// Generate an inclusion proof for the leaf at `leaf_index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if `leaf_index` is not below
+    /// `leaf_count`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleMountainRange, sha256};
+    ///
+    /// let mut mmr = MerkleMountainRange::new();
+    /// for i in 0..11u8 {
+    ///     mmr.append(sha256(&[i]));
+    /// }
+    /// let proof = mmr.proof(6)?;
+    /// assert!(MerkleMountainRange::verify(&sha256(&[6]), &proof, &mmr.root()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn proof(&self, leaf_index: usize) -> Result<MmrProof> {
+        if leaf_index >= self.leaf_count {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: leaf_index,
+                leaf_count: self.leaf_count,
+            });
+        }
+
+        let mut path = Vec::new();
+        let mut peaks = Vec::new();
+        for m in mountains(self.leaf_count) {
+            if !(m.first_leaf..m.first_leaf + (1 << m.height)).contains(&leaf_index) {
+                peaks.push(self.nodes[m.peak_pos].clone());
+                continue;
+            }
+            // Walk down from the peak, collecting the sibling of each step
+            let local = leaf_index - m.first_leaf;
+            let mut pos = m.peak_pos;
+            for height in (1..=m.height).rev() {
+                let (left, right) = (pos - (1 << height), pos - 1);
+                if local & (1 << (height - 1)) == 0 {
+                    path.push(self.nodes[right].clone());
+                    pos = left;
+                } else {
+                    path.push(self.nodes[left].clone());
+                    pos = right;
+                }
+            }
+        }
+        path.reverse();
+
+        Ok(MmrProof {
+            leaf_count: self.leaf_count,
+            leaf_index,
+            path,
+            peaks,
+        })
+    }
+
+    /// Verify a proof for a range hashing with SHA-256.
+    pub fn verify(leaf: &[u8], proof: &MmrProof, root: &[u8]) -> bool {
+        Self::verify_with_config(leaf, proof, root, &TreeConfig::default())
+    }
+
+    /// Verify a proof for a range hashing internal nodes with `config`.
+    ///
+    /// The mountain shapes follow from `leaf_count`, so the path length and
+    /// the sibling sides are checked against `leaf_index` rather than trusted.
+    /// A `leaf_count` too large for any real range is rejected up front.
+    pub fn verify_with_config(
+        leaf: &[u8],
+        proof: &MmrProof,
+        root: &[u8],
+        config: &TreeConfig,
+    ) -> bool {
+        if proof.leaf_index >= proof.leaf_count || proof.leaf_count > MAX_LEAF_COUNT {
+            return false;
+        }
+
+        let mut other_peaks = proof.peaks.iter();
+        let mut peaks = Vec::new();
+        for m in mountains(proof.leaf_count) {
+            if !(m.first_leaf..m.first_leaf + (1 << m.height)).contains(&proof.leaf_index) {
+                let Some(peak) = other_peaks.next() else {
+                    return false;
+                };
+                peaks.push(peak.clone());
+                continue;
+            }
+            if proof.path.len() != m.height as usize {
+                return false;
+            }
+            let local = proof.leaf_index - m.first_leaf;
+            let mut current = leaf.to_vec();
+            for (height, sibling) in proof.path.iter().enumerate() {
+                current = if local & (1 << height) == 0 {
+                    config.hash_concat(&current, sibling)
+                } else {
+                    config.hash_concat(sibling, &current)
+                };
+            }
+            peaks.push(current);
+        }
+
+        other_peaks.next().is_none()
+            && bag_peaks(&peaks, config).is_some_and(|bagged| constant_time_eq(&bagged, root))
+    }
+}
+
+/// Fold peaks from right to left into a single root; `None` if there are none.
+fn bag_peaks(peaks: &[Hash], config: &TreeConfig) -> Option<Hash> {
+    let (last, rest) = peaks.split_last()?;
+    Some(
+        rest.iter()
+            .rev()
+            .fold(last.clone(), |acc, peak| config.hash_concat(peak, &acc)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, sha256};
+
+    fn leaf(i: usize) -> Hash {
+        sha256(format!("entry{}", i).as_bytes())
+    }
+
+    #[test]
+    fn test_append_and_verify_every_leaf() {
+        let mut mmr = MerkleMountainRange::new();
+        assert!(matches!(mmr.root(), Err(MerkleError::EmptyLeaves)));
+
+        for n in 1..=20 {
+            assert_eq!(mmr.append(leaf(n - 1)), n - 1);
+            assert_eq!(mmr.leaf_count(), n);
+            assert_eq!(mmr.peaks().len(), n.count_ones() as usize);
+
+            let root = mmr.root().unwrap();
+            for i in 0..n {
+                let proof = mmr.proof(i).unwrap();
+                assert!(
+                    MerkleMountainRange::verify(&leaf(i), &proof, &root),
+                    "leaf {} of {}",
+                    i,
+                    n
+                );
+                assert!(!MerkleMountainRange::verify(&leaf(n), &proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_mountain_matches_merkle_tree() {
+        for n in [1, 2, 4, 8, 16] {
+            let mut mmr = MerkleMountainRange::new();
+            let leaves: Vec<Hash> = (0..n).map(leaf).collect();
+            for l in &leaves {
+                mmr.append(l.clone());
+            }
+            let tree = MerkleTree::from_leaves(leaves).unwrap();
+            assert_eq!(mmr.root().unwrap(), tree.root_hash_ref().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_appends_keep_earlier_nodes() {
+        let mut mmr = MerkleMountainRange::with_config(TreeConfig::rfc6962());
+        for i in 0..12 {
+            mmr.append(leaf(i));
+        }
+        let before = mmr.nodes.clone();
+        mmr.append(leaf(12));
+        assert_eq!(&mmr.nodes[..before.len()], before.as_slice());
+
+        let proof = mmr.proof(5).unwrap();
+        let root = mmr.root().unwrap();
+        assert!(MerkleMountainRange::verify_with_config(
+            &leaf(5),
+            &proof,
+            &root,
+            mmr.config()
+        ));
+        assert!(!MerkleMountainRange::verify(&leaf(5), &proof, &root));
+    }
+
+    #[test]
+    fn test_rejects_tampered_proofs() {
+        let mut mmr = MerkleMountainRange::new();
+        for i in 0..13 {
+            mmr.append(leaf(i));
+        }
+        let root = mmr.root().unwrap();
+        let proof = mmr.proof(9).unwrap();
+        assert!(MerkleMountainRange::verify(&leaf(9), &proof, &root));
+
+        let mut moved = proof.clone();
+        moved.leaf_index = 8;
+        assert!(!MerkleMountainRange::verify(&leaf(9), &moved, &root));
+
+        let mut short = proof.clone();
+        short.path.pop();
+        assert!(!MerkleMountainRange::verify(&leaf(9), &short, &root));
+
+        let mut extra_peak = proof.clone();
+        extra_peak.peaks.push(leaf(0));
+        assert!(!MerkleMountainRange::verify(&leaf(9), &extra_peak, &root));
+
+        let mut shrunk = proof.clone();
+        shrunk.leaf_count = 12;
+        assert!(!MerkleMountainRange::verify(&leaf(9), &shrunk, &root));
+
+        for huge in [usize::MAX, MAX_LEAF_COUNT + 1, MAX_LEAF_COUNT] {
+            let mut crafted = proof.clone();
+            crafted.leaf_count = huge;
+            assert!(!MerkleMountainRange::verify(&leaf(9), &crafted, &root));
+        }
+
+        assert!(matches!(
+            mmr.proof(13),
+            Err(MerkleError::IndexOutOfBounds {
+                index: 13,
+                leaf_count: 13
+            })
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+// Batch (multi-leaf) Merkle proofs
+//
+// A multiproof authenticates several leaves at once. Sibling hashes shared by
+// more than one path, or derivable from other proven leaves, are sent only once.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, MerkleError, MerkleTree, OddStrategy, Result, TreeConfig, constant_time_eq};
+
+/// A compact proof for several leaves of the same tree.
+///
+/// `hashes` holds only the sibling hashes the verifier cannot derive itself,
+/// ordered level by level from the leaves upward and, within a level, from left
+/// to right. Siblings that are themselves proven leaves (or ancestors of proven
+/// leaves) and duplicated odd nodes are omitted.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultiProof {
+    /// Number of leaves in the tree the proof was generated from
+    pub leaf_count: usize,
+    /// Proven leaf indices, sorted ascending and deduplicated
+    pub indices: Vec<usize>,
+    /// Sibling hashes in consumption order
+    pub hashes: Vec<Hash>,
+}
+
+impl MerkleTree {
+    /// Generate a single proof covering every leaf in `indices`.
+    ///
+    /// Indices may be given in any order and may repeat; the proof records them
+    /// sorted and deduplicated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::EmptyLeaves` if `indices` is empty, or
+    /// `MerkleError::IndexOutOfBounds` if any index >= leaf_count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.generate_multiproof(&[2, 3])?;
+    /// let leaves = vec![(2, sha256(&[2])), (3, sha256(&[3]))];
+    /// assert!(MerkleTree::verify_multiproof(&leaves, &proof, tree.root_hash_ref()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn generate_multiproof(&self, indices: &[usize]) -> Result<MultiProof> {
+        if indices.is_empty() {
+            return Err(MerkleError::EmptyLeaves);
+        }
+
+        let leaf_count = self.leaf_count();
+        let mut known: Vec<usize> = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        if let Some(&index) = known.iter().find(|&&i| i >= leaf_count) {
+            return Err(MerkleError::IndexOutOfBounds { index, leaf_count });
+        }
+
+        let proven = known.clone();
+        let mut hashes = Vec::new();
+
+        for level_nodes in &self.levels[..self.levels.len() - 1] {
+            let mut parents = Vec::with_capacity(known.len());
+            let mut k = 0;
+            while k < known.len() {
+                let i = known[k];
+                let is_right = i % 2 == 1;
+                if !is_right && k + 1 < known.len() && known[k + 1] == i + 1 {
+                    // both children known, nothing to send
+                    k += 2;
+                } else {
+                    let sibling = i ^ 1;
+                    if sibling < level_nodes.len() {
+                        hashes.push(level_nodes[sibling].clone());
+                    }
+                    // otherwise i is the odd last node: duplicated or promoted,
+                    // both of which the verifier reconstructs on its own
+                    k += 1;
+                }
+                parents.push(i / 2);
+            }
+            known = parents;
+        }
+
+        Ok(MultiProof {
+            leaf_count,
+            indices: proven,
+            hashes,
+        })
+    }
+
+    /// Verify a multiproof for a SHA-256 tree.
+    ///
+    /// `leaves` pairs each proven index with its leaf hash, in any order. Every
+    /// index in `proof.indices` must be supplied exactly once.
+    pub fn verify_multiproof(leaves: &[(usize, Hash)], proof: &MultiProof, root: &[u8]) -> bool {
+        Self::verify_multiproof_with_config(leaves, proof, root, &TreeConfig::default())
+    }
+
+    /// Verify a multiproof for a tree built with `config`.
+    pub fn verify_multiproof_with_config(
+        leaves: &[(usize, Hash)],
+        proof: &MultiProof,
+        root: &[u8],
+        config: &TreeConfig,
+    ) -> bool {
+        let mut known: Vec<(usize, Hash)> = leaves.to_vec();
+        known.sort_by_key(|(i, _)| *i);
+        let indices: Vec<usize> = known.iter().map(|(i, _)| *i).collect();
+        if known.is_empty() || indices != proof.indices {
+            return false;
+        }
+        if known.last().is_some_and(|(i, _)| *i >= proof.leaf_count) {
+            return false;
+        }
+
+        let mut hashes = proof.hashes.iter();
+        let mut level_len = proof.leaf_count;
+
+        while level_len > 1 {
+            let mut parents = Vec::with_capacity(known.len());
+            let mut k = 0;
+            while k < known.len() {
+                let (i, ref current) = known[k];
+                let is_right = i % 2 == 1;
+                let parent = if !is_right && k + 1 < known.len() && known[k + 1].0 == i + 1 {
+                    k += 1;
+                    config.hash_concat(current, &known[k].1)
+                } else if (i ^ 1) < level_len {
+                    let Some(sibling) = hashes.next() else {
+                        return false;
+                    };
+                    if is_right {
+                        config.hash_concat(sibling, current)
+                    } else {
+                        config.hash_concat(current, sibling)
+                    }
+                } else {
+                    match config.odd_strategy {
+                        OddStrategy::Duplicate => config.hash_concat(current, current),
+                        OddStrategy::Promote => current.clone(),
+                    }
+                };
+                parents.push((i / 2, parent));
+                k += 1;
+            }
+            known = parents;
+            level_len = level_len.div_ceil(2);
+        }
+
+        hashes.next().is_none() && known.len() == 1 && constant_time_eq(&known[0].1, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256;
+
+    fn tree_of(n: usize, config: TreeConfig) -> (MerkleTree, Vec<Hash>) {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| format!("leaf{}", i).into_bytes()).collect();
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+        let leaves = data.iter().map(|d| config.hash_leaf(d)).collect();
+        (tree, leaves)
+    }
+
+    fn leaf_pairs(leaves: &[Hash], indices: &[usize]) -> Vec<(usize, Hash)> {
+        indices.iter().map(|&i| (i, leaves[i].clone())).collect()
+    }
+
+    #[test]
+    fn test_overlapping_indices() {
+        let (tree, leaves) = tree_of(16, TreeConfig::default());
+        let root = tree.root_hash_ref().unwrap();
+
+        // Adjacent indices share almost their entire path
+        let indices: Vec<usize> = (0..8).collect();
+        let proof = tree.generate_multiproof(&indices).unwrap();
+        assert!(MerkleTree::verify_multiproof(
+            &leaf_pairs(&leaves, &indices),
+            &proof,
+            root
+        ));
+
+        let single_total: usize = indices
+            .iter()
+            .map(|&i| tree.generate_proof(i).unwrap().len())
+            .sum();
+        assert_eq!(single_total, 32);
+        assert_eq!(proof.hashes.len(), 1);
+    }
+
+    #[test]
+    fn test_disjoint_indices() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            let (tree, leaves) = tree_of(13, config.clone());
+            let root = tree.root_hash_ref().unwrap();
+
+            for indices in [vec![0, 12], vec![3, 7, 11], vec![12], vec![5, 6, 9, 12]] {
+                let proof = tree.generate_multiproof(&indices).unwrap();
+                assert!(MerkleTree::verify_multiproof_with_config(
+                    &leaf_pairs(&leaves, &indices),
+                    &proof,
+                    root,
+                    &config
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsorted_and_repeated_indices() {
+        let (tree, leaves) = tree_of(9, TreeConfig::default());
+        let proof = tree.generate_multiproof(&[8, 1, 8, 4]).unwrap();
+        assert_eq!(proof.indices, vec![1, 4, 8]);
+
+        let pairs = leaf_pairs(&leaves, &[4, 8, 1]);
+        assert!(MerkleTree::verify_multiproof(
+            &pairs,
+            &proof,
+            tree.root_hash_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_multiproof_rejects_tampering() {
+        let (tree, leaves) = tree_of(10, TreeConfig::default());
+        let root = tree.root_hash_ref().unwrap();
+        let indices = [1, 6];
+        let proof = tree.generate_multiproof(&indices).unwrap();
+
+        let mut wrong_leaf = leaf_pairs(&leaves, &indices);
+        wrong_leaf[1].1 = sha256(b"wrong");
+        assert!(!MerkleTree::verify_multiproof(&wrong_leaf, &proof, root));
+
+        let mut tampered = proof.clone();
+        tampered.hashes[0][0] ^= 0xff;
+        assert!(!MerkleTree::verify_multiproof(
+            &leaf_pairs(&leaves, &indices),
+            &tampered,
+            root
+        ));
+
+        // Missing a proven leaf
+        assert!(!MerkleTree::verify_multiproof(
+            &leaf_pairs(&leaves, &[1]),
+            &proof,
+            root
+        ));
+
+        // Extra trailing hash
+        let mut padded = proof.clone();
+        padded.hashes.push(sha256(b"extra"));
+        assert!(!MerkleTree::verify_multiproof(
+            &leaf_pairs(&leaves, &indices),
+            &padded,
+            root
+        ));
+    }
+
+    #[test]
+    fn test_multiproof_errors() {
+        let (tree, _) = tree_of(4, TreeConfig::default());
+        assert!(matches!(
+            tree.generate_multiproof(&[]),
+            Err(MerkleError::EmptyLeaves)
+        ));
+        assert!(matches!(
+            tree.generate_multiproof(&[1, 4]),
+            Err(MerkleError::IndexOutOfBounds { index: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_multiproof_serialization() {
+        let (tree, leaves) = tree_of(7, TreeConfig::default());
+        let proof = tree.generate_multiproof(&[0, 2, 6]).unwrap();
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let decoded: MultiProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(MerkleTree::verify_multiproof(
+            &leaf_pairs(&leaves, &[0, 2, 6]),
+            &decoded,
+            tree.root_hash_ref().unwrap()
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+// Non-membership proofs for trees with sorted leaves
+//
+// When the leaves are in ascending order, a value is absent if its would-be
+// neighbours sit at adjacent indices: nothing can lie between them. The proof
+// is the inclusion proof of each neighbour, and the verifier must check that
+// each proof really belongs to the claimed index, since adjacency is the whole
+// argument.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Hash, MerkleError, MerkleTree, OddStrategy, OrderedPairs, ProofNode, Result, TreeConfig,
+    constant_time_eq,
+};
+
+/// One leaf flanking an absent value, with its inclusion proof.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Neighbor {
+    /// Index of the leaf in the tree
+    pub index: usize,
+    /// The leaf hash
+    pub leaf_hash: Hash,
+    /// Inclusion proof for the leaf
+    pub proof: Vec<ProofNode>,
+}
+
+/// A proof that a value is not among the leaves of a sorted tree.
+///
+/// `left` is the largest leaf below the value and `right` the smallest above
+/// it; one of them is missing when the value falls before the first or after
+/// the last leaf.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NonMembershipProof {
+    /// Number of leaves in the tree the proof was generated from
+    pub leaf_count: usize,
+    /// Nearest leaf below the target
+    pub left: Option<Neighbor>,
+    /// Nearest leaf above the target
+    pub right: Option<Neighbor>,
+}
+
+impl MerkleTree {
+    /// Prove that `target` is not a leaf of this tree.
+    ///
+    /// Leaves are compared byte-wise, so `target` must be given in the same
+    /// form as the leaves (normally a leaf hash). The tree's leaves must be
+    /// in ascending order; build it from sorted leaves for this to apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::UnsortedLeaves` if the leaves are not in
+    /// ascending order, or `MerkleError::LeafPresent` if `target` is a leaf.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let mut leaves: Vec<_> = (0..6u8).map(|i| sha256(&[i])).collect();
+    /// leaves.sort();
+    /// let tree = MerkleTree::from_leaves(leaves)?;
+    ///
+    /// let absent = sha256(b"absent");
+    /// let proof = tree.non_membership_proof(&absent)?;
+    /// assert!(MerkleTree::verify_non_membership(&absent, &proof, tree.root_hash_ref()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn non_membership_proof(&self, target: &[u8]) -> Result<NonMembershipProof> {
+        let leaves = self.get_leaves();
+        if leaves.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(MerkleError::UnsortedLeaves);
+        }
+        let position = match leaves.binary_search_by(|leaf| leaf.as_slice().cmp(target)) {
+            Ok(index) => return Err(MerkleError::LeafPresent { index }),
+            Err(position) => position,
+        };
+
+        let neighbor = |index: usize| -> Result<Neighbor> {
+            Ok(Neighbor {
+                index,
+                leaf_hash: leaves[index].clone(),
+                proof: self.generate_proof(index)?,
+            })
+        };
+        Ok(NonMembershipProof {
+            leaf_count: leaves.len(),
+            left: position.checked_sub(1).map(neighbor).transpose()?,
+            right: (position < leaves.len())
+                .then(|| neighbor(position))
+                .transpose()?,
+        })
+    }
+
+    /// Verify a non-membership proof for a SHA-256 tree.
+    pub fn verify_non_membership(target: &[u8], proof: &NonMembershipProof, root: &[u8]) -> bool {
+        Self::verify_non_membership_with_config(target, proof, root, &TreeConfig::default())
+    }
+
+    /// Verify a non-membership proof for a tree built with `config`.
+    ///
+    /// Always fails for `OrderedPairs::Sorted` trees, whose proofs do not bind
+    /// a leaf to its index.
+    pub fn verify_non_membership_with_config(
+        target: &[u8],
+        proof: &NonMembershipProof,
+        root: &[u8],
+        config: &TreeConfig,
+    ) -> bool {
+        if config.pair_ordering == OrderedPairs::Sorted {
+            return false;
+        }
+        let n = proof.leaf_count;
+        let placed = |neighbor: &Neighbor| {
+            verify_at_index(
+                &neighbor.leaf_hash,
+                &neighbor.proof,
+                neighbor.index,
+                n,
+                root,
+                config,
+            )
+        };
+
+        match (&proof.left, &proof.right) {
+            (Some(left), Some(right)) => {
+                left.leaf_hash.as_slice() < target
+                    && target < right.leaf_hash.as_slice()
+                    && right.index == left.index + 1
+                    && placed(left)
+                    && placed(right)
+            }
+            (Some(left), None) => {
+                left.leaf_hash.as_slice() < target && left.index + 1 == n && placed(left)
+            }
+            (None, Some(right)) => {
+                target < right.leaf_hash.as_slice() && right.index == 0 && placed(right)
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+/// Verify `proof` for `leaf_hash` and check that its shape is that of the path
+/// from `index` in a tree of `leaf_count` leaves.
+///
+/// Sibling sides must follow the index bits, levels where a promoting tree
+/// has no sibling must be absent, and duplicated siblings must equal the node
+/// itself. Together these pin the leaf to `index` and reject a `leaf_count`
+/// smaller than the real one.
+fn verify_at_index(
+    leaf_hash: &[u8],
+    proof: &[ProofNode],
+    index: usize,
+    leaf_count: usize,
+    root: &[u8],
+    config: &TreeConfig,
+) -> bool {
+    if index >= leaf_count {
+        return false;
+    }
+
+    let mut nodes = proof.iter();
+    let mut current = leaf_hash.to_vec();
+    let mut i = index;
+    let mut level_len = leaf_count;
+    while level_len > 1 {
+        let has_sibling = (i ^ 1) < level_len;
+        if has_sibling || config.odd_strategy == OddStrategy::Duplicate {
+            let Some(node) = nodes.next() else {
+                return false;
+            };
+            if node.is_left != (i % 2 == 1) || (!has_sibling && node.hash != current) {
+                return false;
+            }
+            current = if node.is_left {
+                config.hash_concat(&node.hash, &current)
+            } else {
+                config.hash_concat(&current, &node.hash)
+            };
+        }
+        i /= 2;
+        level_len = level_len.div_ceil(2);
+    }
+
+    nodes.next().is_none() && constant_time_eq(&current, root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tree over the sorted leaves `[0x10, 0x20, ...]` (one byte each, `n` of them).
+    fn sorted_tree(n: u8, config: TreeConfig) -> MerkleTree {
+        let leaves = (1..=n).map(|i| vec![i * 0x10]).collect();
+        MerkleTree::from_leaves_with_config(leaves, config).unwrap()
+    }
+
+    #[test]
+    fn test_before_between_and_after() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            let tree = sorted_tree(5, config.clone());
+            let root = tree.root_hash_ref().unwrap();
+
+            for (target, left, right) in [
+                (0x01, None, Some(0)),
+                (0x25, Some(1), Some(2)),
+                (0x41, Some(3), Some(4)),
+                (0x99, Some(4), None),
+            ] {
+                let proof = tree.non_membership_proof(&[target]).unwrap();
+                assert_eq!(proof.left.as_ref().map(|n| n.index), left);
+                assert_eq!(proof.right.as_ref().map(|n| n.index), right);
+                assert!(MerkleTree::verify_non_membership_with_config(
+                    &[target],
+                    &proof,
+                    root,
+                    &config
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_membership_errors() {
+        let tree = sorted_tree(4, TreeConfig::default());
+        assert!(matches!(
+            tree.non_membership_proof(&[0x30]),
+            Err(MerkleError::LeafPresent { index: 2 })
+        ));
+
+        let unsorted = MerkleTree::from_leaves(vec![vec![0x20], vec![0x10]]).unwrap();
+        assert!(matches!(
+            unsorted.non_membership_proof(&[0x15]),
+            Err(MerkleError::UnsortedLeaves)
+        ));
+    }
+
+    #[test]
+    fn test_non_membership_rejects_forgeries() {
+        let tree = sorted_tree(8, TreeConfig::default());
+        let root = tree.root_hash_ref().unwrap();
+        let proof = tree.non_membership_proof(&[0x35]).unwrap();
+
+        // The target is not between the neighbours
+        assert!(!MerkleTree::verify_non_membership(&[0x55], &proof, root));
+
+        // Skipping a leaf: neighbours 2 and 4 around a value that is present
+        let wide = NonMembershipProof {
+            right: tree.non_membership_proof(&[0x45]).unwrap().right,
+            ..proof.clone()
+        };
+        assert!(!MerkleTree::verify_non_membership(&[0x40], &wide, root));
+
+        // Relabelling the right neighbour's index to fake adjacency
+        let mut relabelled = wide.clone();
+        relabelled.right.as_mut().unwrap().index = 3;
+        assert!(!MerkleTree::verify_non_membership(
+            &[0x40],
+            &relabelled,
+            root
+        ));
+
+        // Claiming leaf 5 is last by shrinking leaf_count
+        let after = NonMembershipProof {
+            leaf_count: 6,
+            left: tree.non_membership_proof(&[0x65]).unwrap().left,
+            right: None,
+        };
+        assert_eq!(after.left.as_ref().unwrap().index, 5);
+        assert!(!MerkleTree::verify_non_membership(&[0x65], &after, root));
+
+        let empty = NonMembershipProof {
+            leaf_count: 8,
+            left: None,
+            right: None,
+        };
+        assert!(!MerkleTree::verify_non_membership(&[0x65], &empty, root));
+    }
+}
@@ -0,0 +1,197 @@
+// Leaf ordering for sets of files
+//
+// A tree over files depends on the order the files become leaves, so whoever
+// builds a tree and whoever checks its root must order the files the same
+// way. `SortStrategy` names the supported orders so both sides can agree on
+// one explicitly.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+/// Order in which files are assembled into leaves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortStrategy {
+    /// Byte-wise by name. The default.
+    #[default]
+    Lexicographic,
+    /// By name, comparing runs of digits as numbers (see `natural_cmp`).
+    Numeric,
+    /// Oldest modification time first, ties broken by name.
+    ModifiedTime,
+}
+
+impl SortStrategy {
+    /// Name used in configuration: `lexicographic`, `numeric` or `modified-time`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortStrategy::Lexicographic => "lexicographic",
+            SortStrategy::Numeric => "numeric",
+            SortStrategy::ModifiedTime => "modified-time",
+        }
+    }
+
+    /// Parse a name as returned by `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            SortStrategy::Lexicographic,
+            SortStrategy::Numeric,
+            SortStrategy::ModifiedTime,
+        ]
+        .into_iter()
+        .find(|strategy| strategy.name() == name)
+    }
+
+    /// Sort `(name, path)` pairs into leaf order. Names must be unique.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::Io` if a modification time cannot be read
+    /// (`ModifiedTime` only).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::SortStrategy;
+    ///
+    /// let mut files: Vec<(String, &str)> = ["file10", "file2", "file1"]
+    ///     .iter()
+    ///     .map(|name| (name.to_string(), *name))
+    ///     .collect();
+    /// SortStrategy::Numeric.sort_files(&mut files)?;
+    /// let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    /// assert_eq!(names, ["file1", "file2", "file10"]);
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn sort_files<P: AsRef<Path>>(self, files: &mut [(String, P)]) -> Result<()> {
+        match self {
+            SortStrategy::Lexicographic => files.sort_by(|a, b| a.0.cmp(&b.0)),
+            SortStrategy::Numeric => files.sort_by(|a, b| natural_cmp(&a.0, &b.0)),
+            SortStrategy::ModifiedTime => {
+                let mut modified = HashMap::with_capacity(files.len());
+                for (name, path) in files.iter() {
+                    modified.insert(name.clone(), fs::metadata(path)?.modified()?);
+                }
+                files.sort_by(|a, b| modified[&a.0].cmp(&modified[&b.0]).then(a.0.cmp(&b.0)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare names so that runs of ASCII digits compare by numeric value:
+/// `file2` < `file10`.
+///
+/// Other text compares byte-wise. Numbers equal in value but written with
+/// different leading zeros fall back to a byte-wise comparison of the whole
+/// names, so distinct names never compare equal.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a, b);
+    while !x.is_empty() && !y.is_empty() {
+        let (x_run, x_rest) = split_run(x);
+        let (y_run, y_rest) = split_run(y);
+        let is_number = |run: &str| run.as_bytes()[0].is_ascii_digit();
+        let ordering = if is_number(x_run) && is_number(y_run) {
+            let (x_num, y_num) = (x_run.trim_start_matches('0'), y_run.trim_start_matches('0'));
+            x_num.len().cmp(&y_num.len()).then(x_num.cmp(y_num))
+        } else {
+            x_run.cmp(y_run)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (x, y) = (x_rest, y_rest);
+    }
+    x.len().cmp(&y.len()).then(a.cmp(b))
+}
+
+/// Split off the leading run of digits or of non-digits of a non-empty `s`.
+fn split_run(s: &str) -> (&str, &str) {
+    let digits = s.as_bytes()[0].is_ascii_digit();
+    let end = s
+        .bytes()
+        .position(|b| b.is_ascii_digit() != digits)
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "file10", "file2", "file1", "file", "file02", "a100b", "a20b", "b", "file2a", "10", "9",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "9", "10", "a20b", "a100b", "b", "file", "file1", "file02", "file2", "file2a",
+                "file10"
+            ]
+        );
+        assert_eq!(natural_cmp("x7", "x7"), Ordering::Equal);
+        assert_ne!(natural_cmp("x07", "x7"), Ordering::Equal);
+        // Numbers longer than any integer type still compare by value
+        assert_eq!(
+            natural_cmp("n99999999999999999999999", "n100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_sort_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut files = Vec::new();
+        for (name, age) in [("file1", 0), ("file10", 30), ("file2", 20), ("file3", 20)] {
+            let path = dir.path().join(name);
+            fs::File::create(&path)
+                .unwrap()
+                .set_modified(base + Duration::from_secs(age))
+                .unwrap();
+            files.push((name.to_string(), path));
+        }
+        let order = |strategy: SortStrategy, files: &mut Vec<(String, std::path::PathBuf)>| {
+            strategy.sort_files(files).unwrap();
+            files
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            order(SortStrategy::Lexicographic, &mut files),
+            ["file1", "file10", "file2", "file3"]
+        );
+        assert_eq!(
+            order(SortStrategy::Numeric, &mut files),
+            ["file1", "file2", "file3", "file10"]
+        );
+        assert_eq!(
+            order(SortStrategy::ModifiedTime, &mut files),
+            ["file1", "file2", "file3", "file10"]
+        );
+
+        files.push(("missing".to_string(), dir.path().join("missing")));
+        assert!(SortStrategy::ModifiedTime.sort_files(&mut files).is_err());
+
+        for strategy in [
+            SortStrategy::Lexicographic,
+            SortStrategy::Numeric,
+            SortStrategy::ModifiedTime,
+        ] {
+            assert_eq!(SortStrategy::from_name(strategy.name()), Some(strategy));
+        }
+        assert_eq!(SortStrategy::from_name("mtime"), None);
+    }
+}
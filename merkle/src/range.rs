@@ -0,0 +1,243 @@
+// Range proofs for contiguous blocks of leaves
+//
+// Proving leaves `[start, end)` only needs the siblings just outside the range
+// at each level: the interior nodes are recomputed from the leaves themselves.
+// That is at most two hashes per level, however long the range is.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, MerkleError, MerkleTree, Result, TreeConfig, constant_time_eq};
+
+/// A proof that the leaves `[start, end)` are included in a tree.
+///
+/// `hashes` holds the boundary siblings level by level from the leaves upward;
+/// within a level the left boundary comes before the right one.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RangeProof {
+    /// Number of leaves in the tree the proof was generated from
+    pub leaf_count: usize,
+    /// First proven leaf index
+    pub start: usize,
+    /// One past the last proven leaf index
+    pub end: usize,
+    /// Boundary sibling hashes in consumption order
+    pub hashes: Vec<Hash>,
+}
+
+impl MerkleTree {
+    /// Generate a proof covering the contiguous leaves `[start, end)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` unless `start < end <= leaf_count`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let files: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i]).collect();
+    /// let tree = MerkleTree::from_bytes_vec(&files)?;
+    /// let proof = tree.range_proof(3, 6)?;
+    /// let leaves: Vec<_> = (3..6u8).map(|i| sha256(&[i])).collect();
+    /// assert!(MerkleTree::verify_range(&leaves, 3, &proof, tree.root_hash_ref()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn range_proof(&self, start: usize, end: usize) -> Result<RangeProof> {
+        let leaf_count = self.leaf_count();
+        if end > leaf_count {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: end,
+                leaf_count,
+            });
+        }
+        if start >= end {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: start,
+                leaf_count,
+            });
+        }
+
+        let mut hashes = Vec::new();
+        let (mut lo, mut hi) = (start, end);
+        for level_nodes in &self.levels[..self.levels.len() - 1] {
+            if lo % 2 == 1 {
+                hashes.push(level_nodes[lo - 1].clone());
+            }
+            // an even `hi` without a node there is the odd last node, which
+            // the verifier handles through the odd strategy
+            if hi % 2 == 1 && hi < level_nodes.len() {
+                hashes.push(level_nodes[hi].clone());
+            }
+            lo /= 2;
+            hi = hi.div_ceil(2);
+        }
+
+        Ok(RangeProof {
+            leaf_count,
+            start,
+            end,
+            hashes,
+        })
+    }
+
+    /// Verify a range proof for a SHA-256 tree.
+    ///
+    /// `leaves` are the hashes of leaves `start..start + leaves.len()`, which
+    /// must match the range the proof was generated for.
+    pub fn verify_range(leaves: &[Hash], start: usize, proof: &RangeProof, root: &[u8]) -> bool {
+        Self::verify_range_with_config(leaves, start, proof, root, &TreeConfig::default())
+    }
+
+    /// Verify a range proof for a tree built with `config`.
+    pub fn verify_range_with_config(
+        leaves: &[Hash],
+        start: usize,
+        proof: &RangeProof,
+        root: &[u8],
+        config: &TreeConfig,
+    ) -> bool {
+        if leaves.is_empty()
+            || start != proof.start
+            || start + leaves.len() != proof.end
+            || proof.end > proof.leaf_count
+        {
+            return false;
+        }
+
+        let mut hashes = proof.hashes.iter();
+        let mut known: Vec<Hash> = leaves.to_vec();
+        let mut lo = start;
+        let mut level_len = proof.leaf_count;
+
+        while level_len > 1 {
+            if lo % 2 == 1 {
+                let Some(sibling) = hashes.next() else {
+                    return false;
+                };
+                known.insert(0, sibling.clone());
+                lo -= 1;
+            }
+            let hi = lo + known.len();
+            if hi % 2 == 1 && hi < level_len {
+                let Some(sibling) = hashes.next() else {
+                    return false;
+                };
+                known.push(sibling.clone());
+            }
+
+            // `known` now starts on a left child and ends on a right child or
+            // the odd last node of the level
+            known = known
+                .chunks(2)
+                .map(|pair| config.parent_hash(pair, 0))
+                .collect();
+            lo /= 2;
+            level_len = level_len.div_ceil(2);
+        }
+
+        hashes.next().is_none() && known.len() == 1 && constant_time_eq(&known[0], root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OddStrategy, sha256};
+
+    fn tree_of(n: usize, config: TreeConfig) -> (MerkleTree, Vec<Hash>) {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| format!("leaf{}", i).into_bytes()).collect();
+        let tree = MerkleTree::from_bytes_vec_with_config(&data, config.clone()).unwrap();
+        let leaves = data.iter().map(|d| config.hash_leaf(d)).collect();
+        (tree, leaves)
+    }
+
+    #[test]
+    fn test_aligned_ranges() {
+        let (tree, leaves) = tree_of(16, TreeConfig::default());
+        let root = tree.root_hash_ref().unwrap();
+
+        for (start, end, expected_hashes) in [(0, 16, 0), (0, 8, 1), (8, 12, 2), (4, 6, 3)] {
+            let proof = tree.range_proof(start, end).unwrap();
+            assert_eq!(proof.hashes.len(), expected_hashes);
+            assert!(MerkleTree::verify_range(
+                &leaves[start..end],
+                start,
+                &proof,
+                root
+            ));
+        }
+    }
+
+    #[test]
+    fn test_unaligned_ranges() {
+        for config in [
+            TreeConfig::default(),
+            TreeConfig::rfc6962(),
+            TreeConfig {
+                odd_strategy: OddStrategy::Promote,
+                ..TreeConfig::default()
+            },
+        ] {
+            for n in [1, 2, 5, 13, 16] {
+                let (tree, leaves) = tree_of(n, config.clone());
+                let root = tree.root_hash_ref().unwrap();
+                for start in 0..n {
+                    for end in start + 1..=n {
+                        let proof = tree.range_proof(start, end).unwrap();
+                        assert!(MerkleTree::verify_range_with_config(
+                            &leaves[start..end],
+                            start,
+                            &proof,
+                            root,
+                            &config
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_proof_is_smaller_than_single_proofs() {
+        let (tree, _) = tree_of(16, TreeConfig::default());
+        let proof = tree.range_proof(3, 13).unwrap();
+        let single_total: usize = (3..13).map(|i| tree.generate_proof(i).unwrap().len()).sum();
+        assert_eq!(proof.hashes.len(), 4);
+        assert!(proof.hashes.len() < single_total);
+    }
+
+    #[test]
+    fn test_range_rejects_tampering() {
+        let (tree, leaves) = tree_of(13, TreeConfig::default());
+        let root = tree.root_hash_ref().unwrap();
+        let proof = tree.range_proof(3, 9).unwrap();
+
+        let mut wrong_leaf = leaves[3..9].to_vec();
+        wrong_leaf[2] = sha256(b"wrong");
+        assert!(!MerkleTree::verify_range(&wrong_leaf, 3, &proof, root));
+
+        let mut tampered = proof.clone();
+        tampered.hashes[0][0] ^= 0xff;
+        assert!(!MerkleTree::verify_range(&leaves[3..9], 3, &tampered, root));
+
+        // Shifted or shortened ranges do not match the proof
+        assert!(!MerkleTree::verify_range(&leaves[4..10], 4, &proof, root));
+        assert!(!MerkleTree::verify_range(&leaves[3..8], 3, &proof, root));
+
+        let mut padded = proof.clone();
+        padded.hashes.push(sha256(b"extra"));
+        assert!(!MerkleTree::verify_range(&leaves[3..9], 3, &padded, root));
+    }
+
+    #[test]
+    fn test_range_proof_errors() {
+        let (tree, _) = tree_of(4, TreeConfig::default());
+        for (start, end, index) in [(2, 2, 2), (3, 1, 3), (0, 5, 5)] {
+            assert!(matches!(
+                tree.range_proof(start, end),
+                Err(MerkleError::IndexOutOfBounds { index: i, leaf_count: 4 }) if i == index
+            ));
+        }
+    }
+}
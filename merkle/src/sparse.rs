@@ -0,0 +1,298 @@
+// Sparse Merkle trees for key-value stores
+//
+// A sparse Merkle tree has one leaf slot for every 256-bit key, so a key's
+// position is its path: bit 0 (the most significant) picks the side below the
+// root and bit 255 the side just above the leaf. Almost every slot is empty,
+// and an empty subtree of a given height always hashes to the same value, so
+// only nodes on the paths of inserted keys are stored and empty siblings are
+// left out of proofs. Proving an empty slot is a non-membership proof.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Hash, TreeConfig, constant_time_eq, sha256};
+
+/// A 256-bit key; use `SparseMerkleTree::key` to derive one from arbitrary bytes.
+pub type SparseKey = [u8; 32];
+
+/// Number of levels between a leaf and the root.
+const DEPTH: usize = 256;
+
+/// An authenticated key-value store over a fixed-depth (256) Merkle tree.
+///
+/// Present leaves hash as `sha256(value)` and empty leaves are 32 zero bytes;
+/// internal nodes use SHA-256 over the two children.
+#[derive(Clone, Debug, Default)]
+pub struct SparseMerkleTree {
+    /// Stored values by key
+    values: HashMap<SparseKey, Vec<u8>>,
+    /// Non-empty nodes by level (0 = leaves) and key prefix, i.e. the key
+    /// with its lowest `level` bits cleared
+    nodes: HashMap<(usize, SparseKey), Hash>,
+}
+
+/// A proof that a key holds a given value, or that its slot is empty.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SparseProof {
+    /// Bit `level` is set when the sibling at that level is not empty
+    pub non_empty: SparseKey,
+    /// The non-empty siblings, from the leaf up
+    pub siblings: Vec<Hash>,
+}
+
+/// Hash of an empty subtree at each level, from an empty leaf up to the root.
+fn empty_hashes() -> &'static [Hash] {
+    static EMPTY: OnceLock<Vec<Hash>> = OnceLock::new();
+    EMPTY.get_or_init(|| {
+        let config = TreeConfig::default();
+        let mut hashes = vec![vec![0u8; 32]];
+        for level in 0..DEPTH {
+            let empty = &hashes[level];
+            hashes.push(config.hash_concat(empty, empty));
+        }
+        hashes
+    })
+}
+
+/// Whether the node at `level` on the path to `key` is a right child.
+fn is_right(key: &SparseKey, level: usize) -> bool {
+    let bit = DEPTH - 1 - level;
+    key[bit / 8] & (0x80 >> (bit % 8)) != 0
+}
+
+/// `key` with its lowest `level` bits cleared: the id of its ancestor at `level`.
+fn prefix(key: &SparseKey, level: usize) -> SparseKey {
+    let mut prefix = *key;
+    let (whole, partial) = (level / 8, level % 8);
+    for byte in &mut prefix[32 - whole..] {
+        *byte = 0;
+    }
+    if whole < 32 {
+        prefix[31 - whole] &= 0xff << partial;
+    }
+    prefix
+}
+
+/// Id of the sibling of `key`'s ancestor at `level`.
+fn sibling(key: &SparseKey, level: usize) -> SparseKey {
+    let mut id = prefix(key, level);
+    let bit = DEPTH - 1 - level;
+    id[bit / 8] ^= 0x80 >> (bit % 8);
+    id
+}
+
+/// Parent of `node` at `level` given its sibling.
+fn parent(key: &SparseKey, level: usize, node: &[u8], sibling: &[u8]) -> Hash {
+    let config = TreeConfig::default();
+    if is_right(key, level) {
+        config.hash_concat(sibling, node)
+    } else {
+        config.hash_concat(node, sibling)
+    }
+}
+
+impl SparseMerkleTree {
+    /// Empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Derive a key from arbitrary bytes by hashing them with SHA-256.
+    pub fn key(bytes: &[u8]) -> SparseKey {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&sha256(bytes));
+        key
+    }
+
+    /// Set `key` to `value`, replacing any previous value.
+    ///
+    /// Rehashes the 256 nodes on the key's path.
+    pub fn insert(&mut self, key: SparseKey, value: Vec<u8>) {
+        let mut current = sha256(&value);
+        self.nodes.insert((0, key), current.clone());
+        for level in 0..DEPTH {
+            current = parent(
+                &key,
+                level,
+                &current,
+                self.node(level, &sibling(&key, level)),
+            );
+            self.nodes
+                .insert((level + 1, prefix(&key, level + 1)), current.clone());
+        }
+        self.values.insert(key, value);
+    }
+
+    /// Value stored under `key`, if any.
+    pub fn get(&self, key: &SparseKey) -> Option<&[u8]> {
+        self.values.get(key).map(Vec::as_slice)
+    }
+
+    /// Number of keys with a value.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no key has a value.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Root hash; an empty tree has the root of 2^256 empty leaves.
+    pub fn root(&self) -> Hash {
+        self.node(DEPTH, &[0u8; 32]).clone()
+    }
+
+    /// Proof for `key`: of its value if present, of absence otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::SparseMerkleTree;
+    ///
+    /// let mut tree = SparseMerkleTree::new();
+    /// let alice = SparseMerkleTree::key(b"alice");
+    /// tree.insert(alice, b"10".to_vec());
+    ///
+    /// let root = tree.root();
+    /// assert!(SparseMerkleTree::verify(&alice, Some(b"10"), &tree.get_proof(&alice), &root));
+    ///
+    /// let bob = SparseMerkleTree::key(b"bob");
+    /// assert!(SparseMerkleTree::verify(&bob, None, &tree.get_proof(&bob), &root));
+    /// ```
+    pub fn get_proof(&self, key: &SparseKey) -> SparseProof {
+        let mut proof = SparseProof {
+            non_empty: [0u8; 32],
+            siblings: Vec::new(),
+        };
+        for level in 0..DEPTH {
+            let node = self.node(level, &sibling(key, level));
+            if *node != empty_hashes()[level] {
+                proof.non_empty[level / 8] |= 1 << (level % 8);
+                proof.siblings.push(node.clone());
+            }
+        }
+        proof
+    }
+
+    /// Verify that `key` holds `value` under `root`, or with `None` that its
+    /// slot is empty.
+    pub fn verify(key: &SparseKey, value: Option<&[u8]>, proof: &SparseProof, root: &[u8]) -> bool {
+        let empty = empty_hashes();
+        let mut siblings = proof.siblings.iter();
+        let mut current = value.map_or_else(|| empty[0].clone(), sha256);
+        for (level, empty_sibling) in empty[..DEPTH].iter().enumerate() {
+            let sibling = if proof.non_empty[level / 8] & (1 << (level % 8)) != 0 {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                sibling
+            } else {
+                empty_sibling
+            };
+            current = parent(key, level, &current, sibling);
+        }
+        siblings.next().is_none() && constant_time_eq(&current, root)
+    }
+
+    /// Stored node at `level` with id `id`, or the empty hash for that level.
+    fn node(&self, level: usize, id: &SparseKey) -> &Hash {
+        self.nodes
+            .get(&(level, *id))
+            .unwrap_or(&empty_hashes()[level])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_and_non_membership() {
+        let mut tree = SparseMerkleTree::new();
+        let empty_root = tree.root();
+        assert_eq!(empty_root, empty_hashes()[DEPTH]);
+
+        let entries: Vec<(SparseKey, Vec<u8>)> = ["alice", "bob", "carol", "dave"]
+            .iter()
+            .map(|name| {
+                (
+                    SparseMerkleTree::key(name.as_bytes()),
+                    name.as_bytes().to_vec(),
+                )
+            })
+            .collect();
+        for (key, value) in &entries {
+            tree.insert(*key, value.clone());
+        }
+        assert_eq!(tree.len(), 4);
+        let root = tree.root();
+        assert_ne!(root, empty_root);
+
+        for (key, value) in &entries {
+            assert_eq!(tree.get(key), Some(value.as_slice()));
+            let proof = tree.get_proof(key);
+            assert!(SparseMerkleTree::verify(key, Some(value), &proof, &root));
+            assert!(!SparseMerkleTree::verify(
+                key,
+                Some(b"other"),
+                &proof,
+                &root
+            ));
+            assert!(!SparseMerkleTree::verify(key, None, &proof, &root));
+        }
+
+        let missing = SparseMerkleTree::key(b"eve");
+        assert_eq!(tree.get(&missing), None);
+        let proof = tree.get_proof(&missing);
+        assert!(SparseMerkleTree::verify(&missing, None, &proof, &root));
+        assert!(!SparseMerkleTree::verify(
+            &missing,
+            Some(b"eve"),
+            &proof,
+            &root
+        ));
+        // Only siblings on the paths of the four keys are carried
+        assert!(proof.siblings.len() <= 4);
+    }
+
+    #[test]
+    fn test_adjacent_keys_and_updates() {
+        // Keys differing only in their last bit are siblings at the leaves
+        let mut left = [0xaa; 32];
+        left[31] = 0x00;
+        let mut right = left;
+        right[31] = 0x01;
+
+        let mut tree = SparseMerkleTree::new();
+        tree.insert(left, b"l".to_vec());
+        let before = tree.root();
+        let stale = tree.get_proof(&left);
+        tree.insert(right, b"r".to_vec());
+        let root = tree.root();
+
+        let proof = tree.get_proof(&left);
+        assert_eq!(proof.siblings, vec![sha256(b"r")]);
+        assert!(SparseMerkleTree::verify(&left, Some(b"l"), &proof, &root));
+        assert!(SparseMerkleTree::verify(&left, Some(b"l"), &stale, &before));
+        assert!(!SparseMerkleTree::verify(&left, Some(b"l"), &stale, &root));
+
+        // Overwriting a value changes the root; restoring it restores the root
+        tree.insert(right, b"x".to_vec());
+        assert_ne!(tree.root(), root);
+        tree.insert(right, b"r".to_vec());
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.len(), 2);
+
+        let mut truncated = proof.clone();
+        truncated.siblings.clear();
+        assert!(!SparseMerkleTree::verify(
+            &left,
+            Some(b"l"),
+            &truncated,
+            &root
+        ));
+    }
+}
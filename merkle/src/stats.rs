@@ -0,0 +1,124 @@
+// Build and proof timings (`instrument` feature)
+//
+// The instrumented constructors wrap the normal ones and read the clock only
+// around them, so a tree built this way is identical to one built without and
+// the uninstrumented paths pay nothing. Level sizes are read off the finished
+// tree rather than counted while hashing.
+
+use std::time::{Duration, Instant};
+
+use crate::{Hash, MerkleTree, ProofNode, Result, TreeConfig};
+
+/// How a tree was built and what it looks like.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildStats {
+    /// Wall time of the whole build, leaf checks included
+    pub build_time: Duration,
+    /// Number of levels, leaves and root included
+    pub level_count: usize,
+    /// Nodes on each level, leaves first, root (1) last
+    pub level_sizes: Vec<usize>,
+}
+
+/// How long a proof took and how many nodes it has.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofStats {
+    /// Wall time of `generate_proof`
+    pub proof_time: Duration,
+    /// Number of nodes in the proof
+    pub proof_len: usize,
+}
+
+impl MerkleTree {
+    /// `from_leaves`, also returning timings and the tree's shape.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{MerkleTree, sha256};
+    ///
+    /// let leaves: Vec<_> = (0..5u8).map(|i| sha256(&[i])).collect();
+    /// let (tree, stats) = MerkleTree::from_leaves_instrumented(leaves)?;
+    /// assert_eq!(stats.level_sizes, vec![5, 3, 2, 1]);
+    /// assert_eq!(stats.level_count, tree.tree_height());
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn from_leaves_instrumented(leaves: Vec<Hash>) -> Result<(Self, BuildStats)> {
+        MerkleTree::from_leaves_instrumented_with_config(leaves, TreeConfig::default())
+    }
+
+    /// `from_leaves_with_config`, also returning timings and the tree's shape.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `from_leaves_with_config`.
+    pub fn from_leaves_instrumented_with_config(
+        leaves: Vec<Hash>,
+        config: TreeConfig,
+    ) -> Result<(Self, BuildStats)> {
+        let start = Instant::now();
+        let tree = MerkleTree::from_leaves_with_config(leaves, config)?;
+        let build_time = start.elapsed();
+
+        let level_sizes: Vec<usize> = tree.levels.iter().map(Vec::len).collect();
+        let stats = BuildStats {
+            build_time,
+            level_count: level_sizes.len(),
+            level_sizes,
+        };
+        Ok((tree, stats))
+    }
+
+    /// `generate_proof`, also returning how long it took.
+    ///
+    /// # Errors
+    ///
+    /// Returns `MerkleError::IndexOutOfBounds` if index >= leaf_count.
+    pub fn generate_proof_instrumented(
+        &self,
+        index: usize,
+    ) -> Result<(Vec<ProofNode>, ProofStats)> {
+        let start = Instant::now();
+        let proof = self.generate_proof(index)?;
+        let stats = ProofStats {
+            proof_time: start.elapsed(),
+            proof_len: proof.len(),
+        };
+        Ok((proof, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleError, sha256};
+
+    #[test]
+    fn test_instrumented_build_and_proof() {
+        let leaves: Vec<Hash> = (0..1000u32).map(|i| sha256(&i.to_be_bytes())).collect();
+        let (tree, stats) = MerkleTree::from_leaves_instrumented(leaves.clone()).unwrap();
+
+        assert_eq!(
+            tree.levels(),
+            MerkleTree::from_leaves(leaves).unwrap().levels()
+        );
+        assert_eq!(stats.level_count, 11);
+        assert_eq!(
+            stats.level_sizes,
+            vec![1000, 500, 250, 125, 63, 32, 16, 8, 4, 2, 1]
+        );
+
+        let (proof, proof_stats) = tree.generate_proof_instrumented(999).unwrap();
+        assert_eq!(proof, tree.generate_proof(999).unwrap());
+        assert_eq!(proof_stats.proof_len, 10);
+
+        assert!(matches!(
+            tree.generate_proof_instrumented(1000),
+            Err(MerkleError::IndexOutOfBounds { .. })
+        ));
+        assert!(matches!(
+            MerkleTree::from_leaves_instrumented(Vec::new()),
+            Err(MerkleError::EmptyLeaves)
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+// Incremental leaf hashing
+//
+// `LeafHasher` computes the same leaf hash as `TreeConfig::hash_leaf` (or
+// `hash_named_leaf`) from contents fed in chunks, so a large download can be
+// hashed as it arrives and checked against its proof without ever holding the
+// whole file.
+
+use crate::{DigestState, Hash, MerkleTree, ProofNode, TreeConfig};
+
+/// Streaming leaf hash computation, checked against a proof at the end.
+#[derive(Clone)]
+pub struct LeafHasher {
+    state: DigestState,
+    config: TreeConfig,
+}
+
+impl Default for LeafHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LeafHasher {
+    /// Hasher for a leaf of a default (SHA-256) tree.
+    pub fn new() -> Self {
+        Self::with_config(TreeConfig::default())
+    }
+
+    /// Hasher for a leaf of a tree built with `config`; matches `hash_leaf`.
+    pub fn with_config(config: TreeConfig) -> Self {
+        LeafHasher {
+            state: config.leaf_state(),
+            config,
+        }
+    }
+
+    /// Hasher for the contents of the file `name`; matches `hash_named_leaf`.
+    pub fn named(name: &str, config: TreeConfig) -> Self {
+        let mut hasher = Self::with_config(config);
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher
+    }
+
+    /// Feed the next chunk of leaf contents.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.state.update(chunk);
+    }
+
+    /// The leaf hash of everything fed so far.
+    pub fn finalize(self) -> Hash {
+        self.state.finalize()
+    }
+
+    /// Finish hashing and verify the leaf against `proof` and `root`, under
+    /// the hasher's config.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle::{LeafHasher, MerkleTree};
+    ///
+    /// let tree = MerkleTree::from_bytes_vec(&[b"hello world".to_vec(), b"b".to_vec()])?;
+    /// let mut hasher = LeafHasher::new();
+    /// for chunk in [&b"hello"[..], b" ", b"world"] {
+    ///     hasher.update(chunk);
+    /// }
+    /// assert!(hasher.finalize_and_verify(&tree.generate_proof(0)?, tree.root_hash_ref()?));
+    /// # Ok::<(), merkle::MerkleError>(())
+    /// ```
+    pub fn finalize_and_verify(self, proof: &[ProofNode], root: &[u8]) -> bool {
+        let config = self.config.clone();
+        let leaf = self.finalize();
+        MerkleTree::verify_proof_with_config(&leaf, proof, root, &config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashAlgorithm, sha256};
+
+    #[test]
+    fn test_chunked_matches_one_shot() {
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        for chunk_size in [1, 7, 1000, contents.len()] {
+            let mut hasher = LeafHasher::new();
+            for chunk in contents.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), sha256(&contents));
+        }
+
+        let config = TreeConfig {
+            algorithm: HashAlgorithm::Blake3,
+            leaf_salt: b"salt".to_vec(),
+            ..TreeConfig::rfc6962()
+        };
+        let mut hasher = LeafHasher::named("big.bin", config.clone());
+        for chunk in contents.chunks(333) {
+            hasher.update(chunk);
+        }
+        assert_eq!(
+            hasher.finalize(),
+            config.hash_named_leaf("big.bin", &contents)
+        );
+    }
+
+    #[test]
+    fn test_finalize_and_verify() {
+        let files: Vec<(String, Vec<u8>)> = (0..5u8)
+            .map(|i| (format!("f{}", i), vec![i; 4096]))
+            .collect();
+        let config = TreeConfig::rfc6962();
+        let tree = MerkleTree::from_named_files_with_config(&files, config.clone()).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let proof = tree.generate_proof(3).unwrap();
+
+        let mut hasher = LeafHasher::named("f3", config.clone());
+        for chunk in files[3].1.chunks(1000) {
+            hasher.update(chunk);
+        }
+        assert!(hasher.clone().finalize_and_verify(&proof, root));
+
+        // One byte short, or the right bytes under another name
+        hasher = LeafHasher::named("f3", config.clone());
+        hasher.update(&files[3].1[1..]);
+        assert!(!hasher.finalize_and_verify(&proof, root));
+        hasher = LeafHasher::named("f2", config);
+        hasher.update(&files[3].1);
+        assert!(!hasher.finalize_and_verify(&proof, root));
+    }
+}
@@ -1,41 +1,424 @@
 // server/src/main.rs
 use actix_multipart::Multipart;
-use actix_web::{App, HttpResponse, HttpServer, Responder, Result, web};
+use actix_web::http::header::{
+    ByteRangeSpec, CONTENT_RANGE, ETag, EntityTag, IfNoneMatch, RANGE, RETRY_AFTER, Range,
+};
+use actix_web::{App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder, Result, web};
 use base64::{Engine as _, engine::general_purpose};
+use flate2::{Compression, write::GzEncoder};
 use futures_util::stream::StreamExt as _;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
-use tracing::{info, warn};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
 use tracing_actix_web::TracingLogger;
 
-use merkle::{MerkleTree, ProofNode};
+use merkle::{
+    HashAlgorithm, MerkleError, MerkleTree, ProofBundle, ProofNode, RESERVED_NAMES, SortStrategy,
+    TreeConfig, constant_time_eq, natural_cmp, sha256,
+};
+use rustls::ServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+
+mod storage;
+mod ws;
+
+use storage::{LocalFsStorage, Storage};
+use tokio::sync::broadcast;
+use ws::{ROOT_UPDATES_CAPACITY, RootSocket};
 
 #[derive(Clone)]
 struct AppState {
     storage_dir: PathBuf,
+    /// Backend holding the stored files; a `LocalFsStorage` over `storage_dir`
+    storage: Arc<dyn Storage>,
+    /// Tree over the current storage contents; `None` until first built
+    tree_cache: Arc<RwLock<Option<Arc<CachedTree>>>>,
+    /// Counters exposed on `/metrics`
+    metrics: Arc<Metrics>,
+    /// Size and count limits enforced on uploads
+    limits: Limits,
+    /// When each client IP last started an upload
+    last_uploads: Arc<Mutex<HashMap<IpAddr, Instant>>>,
+    /// Order in which stored files become leaves; clients must use the same
+    sort: SortStrategy,
+    /// Persist the tree zstd-compressed as `tree.json.zst`
+    compress_tree: bool,
+    /// Store identical file contents once, hard-linking the duplicate names
+    dedup: bool,
+    /// Cleared when the last integrity check found the stored files no
+    /// longer hash to `root.hex`
+    integrity_ok: Arc<AtomicBool>,
+    /// Hex of each new root, forwarded to `/ws` subscribers
+    root_updates: broadcast::Sender<String>,
+    /// Held by `/upload` and `/append` from staging to swap, so concurrent
+    /// writes neither share the staging directory nor interleave their swaps
+    write_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AppState {
+    fn new(storage_dir: PathBuf, limits: Limits) -> Self {
+        AppState {
+            storage: Arc::new(LocalFsStorage::new(storage_dir.clone())),
+            storage_dir,
+            tree_cache: Arc::new(RwLock::new(None)),
+            metrics: Arc::new(Metrics::default()),
+            limits,
+            last_uploads: Arc::new(Mutex::new(HashMap::new())),
+            sort: SortStrategy::default(),
+            compress_tree: false,
+            dedup: false,
+            integrity_ok: Arc::new(AtomicBool::new(true)),
+            root_updates: broadcast::channel(ROOT_UPDATES_CAPACITY).0,
+            write_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Order leaves by `sort` instead of by name.
+    fn with_sort(self, sort: SortStrategy) -> Self {
+        AppState { sort, ..self }
+    }
+
+    /// Persist the tree as `tree.json.zst` instead of plain `tree.json`.
+    fn with_compressed_tree(self, compress_tree: bool) -> Self {
+        AppState {
+            compress_tree,
+            ..self
+        }
+    }
+
+    /// Share the stored copy of files with identical contents.
+    fn with_dedup(self, dedup: bool) -> Self {
+        AppState { dedup, ..self }
+    }
+
+    /// Push a new root to every connected `/ws` client; without any, the
+    /// root is simply dropped.
+    fn notify_root(&self, root_hex: &str) {
+        let _ = self.root_updates.send(root_hex.to_string());
+    }
+
+    /// Record an upload from `ip` at `now`, or return how long the client
+    /// must still wait if its previous upload was too recent.
+    fn throttle_upload(&self, ip: IpAddr, now: Instant) -> Result<Option<Duration>> {
+        let interval = self.limits.min_upload_interval;
+        let mut last_uploads = self
+            .last_uploads
+            .lock()
+            .map_err(|_| actix_web::error::ErrorInternalServerError("upload throttle poisoned"))?;
+        if let Some(last) = last_uploads.get(&ip) {
+            let elapsed = now.saturating_duration_since(*last);
+            if elapsed < interval {
+                return Ok(Some(interval - elapsed));
+            }
+        }
+        // Forget clients whose interval has passed so the map stays small
+        last_uploads.retain(|_, last| now.saturating_duration_since(*last) < interval);
+        last_uploads.insert(ip, now);
+        Ok(None)
+    }
+}
+
+/// Upload limits, configurable through environment variables of the same
+/// (upper-case) names.
+#[derive(Clone, Copy, Debug)]
+struct Limits {
+    /// Largest single file in bytes
+    max_file_size: usize,
+    /// Largest combined size of one request in bytes
+    max_total_size: usize,
+    /// Most files in one request
+    max_files: usize,
+    /// Shortest time between two uploads from the same client IP
+    min_upload_interval: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            min_upload_interval: Duration::from_secs(DEFAULT_MIN_UPLOAD_INTERVAL_SECS),
+        }
+    }
+}
+
+impl Limits {
+    /// Read `MAX_FILE_SIZE`, `MAX_TOTAL_SIZE`, `MAX_FILES` and
+    /// `MIN_UPLOAD_INTERVAL_SECS`, keeping the default for any that is unset
+    /// or invalid.
+    fn from_env() -> Self {
+        let var = |name: &str, default: usize| parse_limit(name, std::env::var(name).ok(), default);
+        Limits {
+            max_file_size: var("MAX_FILE_SIZE", DEFAULT_MAX_FILE_SIZE),
+            max_total_size: var("MAX_TOTAL_SIZE", DEFAULT_MAX_TOTAL_SIZE),
+            max_files: var("MAX_FILES", DEFAULT_MAX_FILES),
+            min_upload_interval: Duration::from_secs(var(
+                "MIN_UPLOAD_INTERVAL_SECS",
+                DEFAULT_MIN_UPLOAD_INTERVAL_SECS as usize,
+            ) as u64),
+        }
+    }
+}
+
+/// Parse `SORT_STRATEGY`, warning and falling back to lexicographic order if
+/// it is not a `SortStrategy` name.
+fn parse_sort_strategy(value: Option<String>) -> SortStrategy {
+    let Some(value) = value else {
+        return SortStrategy::default();
+    };
+    SortStrategy::from_name(value.trim()).unwrap_or_else(|| {
+        warn!(
+            "Ignoring SORT_STRATEGY={:?}: expected lexicographic, numeric or modified-time",
+            value
+        );
+        SortStrategy::default()
+    })
+}
+
+/// Parse an on/off setting such as `COMPRESS_TREE`: `1` or `true` enable
+/// it, anything else leaves it off.
+fn parse_flag(value: Option<String>) -> bool {
+    value.is_some_and(|v| matches!(v.trim(), "1" | "true"))
+}
+
+/// Parse a positive limit, warning and falling back to `default` otherwise.
+fn parse_limit(name: &str, value: Option<String>, default: usize) -> usize {
+    let Some(value) = value else {
+        return default;
+    };
+    match value.trim().parse::<usize>() {
+        Ok(limit) if limit > 0 => limit,
+        _ => {
+            warn!(
+                "Ignoring {}={:?}: expected a positive integer; using {}",
+                name, value, default
+            );
+            default
+        }
+    }
+}
+
+/// Request counters since startup.
+#[derive(Default)]
+struct Metrics {
+    /// Successful `/upload` and `/append` requests
+    uploads: AtomicU64,
+    /// Files served with a proof, counting each file of a batch
+    file_requests: AtomicU64,
+    /// `/verify` requests whose proof did not verify
+    verification_failures: AtomicU64,
+}
+
+impl Metrics {
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render the counters in the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let counters = [
+            (
+                "merkle_uploads_total",
+                "Successful uploads and appends",
+                &self.uploads,
+            ),
+            (
+                "merkle_file_requests_total",
+                "Files served with a proof",
+                &self.file_requests,
+            ),
+            (
+                "merkle_verification_failures_total",
+                "Proofs rejected by /verify",
+                &self.verification_failures,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, help, counter) in counters {
+            out.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n",
+                counter.load(Ordering::Relaxed)
+            ));
+        }
+        out
+    }
+}
+
+/// Merkle tree over the stored files and the names its leaves map to, in leaf order.
+struct CachedTree {
+    entries: Vec<String>,
+    tree: MerkleTree,
 }
 
 #[derive(Serialize)]
 struct FileResponse {
     file_name: String,
-    file_bytes: String, // base64
+    file_bytes: String, // base64, gzipped first when `compressed`
+    compressed: bool,
+    /// Detected MIME type, `application/octet-stream` when unknown
+    content_type: String,
+    #[serde(flatten)]
+    bundle: ProofBundle, // leaf_hash (hex), proof, root (hex)
+}
+
+#[derive(Deserialize)]
+struct FileQuery {
+    /// Root the client expects the file to be proven against (hex)
+    expected_root: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RootMismatch {
+    error: &'static str,
+    expected_root: String,
+    server_root: String,
+}
+
+#[derive(Serialize)]
+struct ProofResponse {
+    index: usize,
     proof: Vec<ProofNode>,
     root: String, // hex
 }
 
+#[derive(Deserialize)]
+struct RootQuery {
+    /// `text` (the default) for the bare hex root, `json` for `RootResponse`
+    format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RootResponse {
+    /// Hex root, or `None` while nothing is stored
+    root: Option<String>,
+    leaf_count: usize,
+    /// Number of levels, leaves included
+    height: usize,
+    algorithm: HashAlgorithm,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+    files: Vec<String>,
+    /// Hex root, or `None` while nothing is stored
+    root: Option<String>,
+    /// Total number of stored files, regardless of paging
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    name: String,
+    size: u64,
+    leaf_hash_hex: String,
+    index: usize,
+    /// SHA-256 of the contents alone, listed when dedup is on; names
+    /// sharing it share one stored copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash_hex: Option<String>,
+}
+
 #[derive(Serialize)]
 struct UploadResponse {
     root: String,
     files_count: usize,
 }
 
-// Security limits
-const MAX_FILE_SIZE: usize = 1024 * 1024; // 1MB per file
-const MAX_TOTAL_SIZE: usize = 10 * 1024 * 1024; // 10MB total
-const MAX_FILES: usize = 10_000; // Maximum number of files
+#[derive(Serialize)]
+struct AppendResponse {
+    root: String,
+    /// Number of files added by this request
+    added: usize,
+    /// Number of files stored after the append
+    files_count: usize,
+}
+
+#[derive(Deserialize)]
+struct VerifyRequest {
+    leaf_hash: String, // hex
+    proof: Vec<ProofNode>,
+    root: String, // hex
+}
+
+#[derive(Serialize)]
+struct VerifyResponse {
+    valid: bool,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    /// Number of stored files
+    files: usize,
+    /// Whether an upload has produced a root
+    has_root: bool,
+}
+
+// Default security limits, overridable via `Limits::from_env`
+const DEFAULT_MAX_FILE_SIZE: usize = 1024 * 1024; // 1MB per file
+const DEFAULT_MAX_TOTAL_SIZE: usize = 10 * 1024 * 1024; // 10MB total
+const DEFAULT_MAX_FILES: usize = 10_000; // Maximum number of files
+const DEFAULT_MIN_UPLOAD_INTERVAL_SECS: u64 = 1; // Per client IP
+
+/// Interval used when `INTEGRITY_CHECK_INTERVAL_SECS` is set but invalid
+const DEFAULT_INTEGRITY_CHECK_INTERVAL_SECS: usize = 3600;
+
+/// Directory inside storage where an upload is assembled before being swapped in
+const STAGING_DIR: &str = ".upload-staging";
+
+/// Serialized `MerkleTree` written alongside the files on upload
+const TREE_FILE: &str = "tree.json";
+
+/// `TREE_FILE` compressed with zstd, written instead when `COMPRESS_TREE=1`
+const TREE_FILE_ZST: &str = "tree.json.zst";
+
+/// Level passed to zstd for `TREE_FILE_ZST`; 0 selects zstd's default
+const TREE_ZSTD_LEVEL: i32 = 0;
+
+/// Optional upload sidecar listing the leaf hashes in leaf order, one
+/// hex hash per line; lets rebuilds skip rehashing the files
+const LEAVES_FILE: &str = "leaves.hex";
+
+/// Bytes of a hash shown in log lines; enough to correlate, short enough to read
+const LOG_HASH_PREFIX_BYTES: usize = 8;
+
+// Headers carrying the full-file proof on 206 responses, whose body is raw bytes
+const ROOT_HEADER: &str = "x-merkle-root"; // hex
+const LEAF_HASH_HEADER: &str = "x-merkle-leaf-hash"; // hex
+const PROOF_HEADER: &str = "x-merkle-proof"; // hex of MerkleTree::proof_to_bytes
+
+/// Optional upload header: hex SHA-256 of all file contents, concatenated in name order
+const UPLOAD_SHA256_HEADER: &str = "x-upload-sha256";
+
+/// Map a merkle error to a response: 404 for unknown leaves, 400 for other
+/// client errors, 500 otherwise.
+fn merkle_error(e: MerkleError) -> actix_web::Error {
+    match e {
+        MerkleError::IndexOutOfBounds { .. } | MerkleError::LeafNotFound => {
+            actix_web::error::ErrorNotFound(e.to_string())
+        }
+        e if e.is_client_error() => actix_web::error::ErrorBadRequest(e.to_string()),
+        e => actix_web::error::ErrorInternalServerError(e.to_string()),
+    }
+}
 
 /// Sanitize filename to prevent path traversal and other attacks
 fn sanitize_filename(name: &str) -> Result<String> {
@@ -54,7 +437,7 @@ fn sanitize_filename(name: &str) -> Result<String> {
     }
 
     // Reject filenames that are just metadata files
-    if name == "manifest.json" || name == "root.hex" {
+    if RESERVED_NAMES.contains(&name) {
         return Err(actix_web::error::ErrorBadRequest(
             "invalid filename: reserved name",
         ));
@@ -77,242 +460,3130 @@ fn sanitize_filename(name: &str) -> Result<String> {
     Ok(name.to_string())
 }
 
-async fn get_file(state: web::Data<AppState>, path: web::Path<String>) -> Result<impl Responder> {
+/// Whether the client advertised gzip in `Accept-Encoding`.
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(actix_web::http::header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|coding| coding.split(';').next().unwrap_or("").trim() == "gzip")
+        })
+}
+
+/// The single byte range requested in a `Range: bytes=...` header, if any.
+///
+/// Malformed headers, other units and multi-range requests are ignored, so
+/// the whole file is served instead.
+fn requested_range(req: &HttpRequest) -> Option<ByteRangeSpec> {
+    let value = req.headers().get(RANGE)?.to_str().ok()?;
+    match value.parse::<Range>().ok()? {
+        Range::Bytes(mut specs) if specs.len() == 1 => specs.pop(),
+        _ => None,
+    }
+}
+
+/// GET /file/{name}[?expected_root=hex]
+/// Returns the file with its proof, or 409 if the tree root differs from `expected_root`.
+/// With a `Range` header, answers 206 with the raw slice and the proof in headers.
+async fn get_file(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<FileQuery>,
+) -> Result<impl Responder> {
     let file_name = path.into_inner();
     let file_name = sanitize_filename(&file_name)?;
-    let p = state.storage_dir.join(&file_name);
-
-    if !p.exists() {
+    let expected_root = query
+        .into_inner()
+        .expected_root
+        .map(|hex_root| {
+            hex::decode(&hex_root).map_err(|e| {
+                actix_web::error::ErrorBadRequest(format!("invalid expected_root hex: {}", e))
+            })
+        })
+        .transpose()?;
+    let Some(file_bytes) = read_stored(state.storage.as_ref(), &file_name).await? else {
         warn!("File request failed: '{}' not found", file_name);
         return Ok(HttpResponse::NotFound().body("file not found"));
-    }
+    };
 
     info!("Serving file '{}'", file_name);
 
-    // read list of files (sorted), excluding metadata files
-    let mut entries: Vec<_> = fs::read_dir(&state.storage_dir)?
-        .filter_map(|res| res.ok())
-        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.file_name().into_string().ok())
-        .filter_map(|s| s)
-        .filter(|name| name != "manifest.json" && name != "root.hex")
-        .collect();
-    entries.sort();
+    let cached = cached_tree(&state).await?;
+    if let Some(expected_root) = expected_root {
+        let server_root = cached.tree.root_hash_ref().map_err(merkle_error)?;
+        if !constant_time_eq(server_root, &expected_root) {
+            warn!(
+                "File request for '{}' expected a different root than the server's",
+                file_name
+            );
+            return Ok(HttpResponse::Conflict().json(RootMismatch {
+                error: "root mismatch",
+                expected_root: hex::encode(expected_root),
+                server_root: hex::encode(server_root),
+            }));
+        }
+    }
 
-    // read all files in that order
-    let mut files_bytes: Vec<Vec<u8>> = Vec::with_capacity(entries.len());
-    for name in &entries {
-        let pb = state.storage_dir.join(name);
-        let data = fs::read(pb)?;
-        files_bytes.push(data);
+    let etag = leaf_etag(&cached, &file_name);
+    if let Some(etag) = etag.as_ref().filter(|etag| if_none_match(&req, etag)) {
+        info!("File '{}' not modified", file_name);
+        return Ok(HttpResponse::NotModified()
+            .insert_header(ETag(etag.clone()))
+            .finish());
     }
 
-    let tree = MerkleTree::from_bytes_vec(&files_bytes)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-    let root = tree
-        .root_hash_ref()
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+    if let Some(range) = requested_range(&req) {
+        return range_response(&state, &cached, &file_name, file_bytes, &range);
+    }
 
-    // find index
-    let index = entries.iter().position(|n| n == &file_name);
-    let index = match index {
-        Some(i) => i,
-        None => return Ok(HttpResponse::NotFound().body("file not indexed")),
-    };
+    match file_response(&cached, file_name, file_bytes, accepts_gzip(&req))? {
+        Some(resp) => {
+            Metrics::increment(&state.metrics.file_requests);
+            let mut builder = HttpResponse::Ok();
+            if let Some(etag) = etag {
+                builder.insert_header(ETag(etag));
+            }
+            Ok(builder.json(resp))
+        }
+        None => Ok(HttpResponse::NotFound().body("file not indexed")),
+    }
+}
 
-    // generate proof
-    let proof = tree
-        .generate_proof(index)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-    let file_bytes = files_bytes[index].clone();
-    let file_b64 = general_purpose::STANDARD.encode(&file_bytes);
-    let root_hex = hex::encode(root);
+/// ETag for `file_name`: its leaf hash in hex, or `None` if it is not a leaf.
+///
+/// The leaf hash changes exactly when the file's name or contents do, so a
+/// matching tag means the client's copy is current. The proof sent alongside
+/// can still change when other files do.
+fn leaf_etag(cached: &CachedTree, file_name: &str) -> Option<EntityTag> {
+    let index = cached.entries.iter().position(|n| n == file_name)?;
+    let leaf = cached.tree.get_leaves().get(index)?;
+    Some(EntityTag::new_strong(hex::encode(leaf)))
+}
 
-    let resp = FileResponse {
-        file_name,
-        file_bytes: file_b64,
+/// Whether the request's `If-None-Match` lists `etag` or is `*`.
+fn if_none_match(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => false,
+    }
+}
+
+/// GET /proof/{hash_hex}
+/// Returns the index and proof of the leaf with the given hash, or 404 if no
+/// leaf has it.
+async fn get_proof(state: web::Data<AppState>, path: web::Path<String>) -> Result<impl Responder> {
+    let leaf_hash = hex::decode(path.into_inner())
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("invalid leaf hash hex: {}", e)))?;
+    if stored_names(state.storage.as_ref()).await?.is_empty() {
+        return Ok(HttpResponse::NotFound().body("leaf not found"));
+    }
+
+    let cached = cached_tree(&state).await?;
+    let expected_len = cached.tree.algorithm().output_len();
+    if leaf_hash.len() != expected_len {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "leaf hash must be {} bytes, got {}",
+            expected_len,
+            leaf_hash.len()
+        )));
+    }
+
+    let (index, proof) = cached
+        .tree
+        .generate_proof_by_hash(&leaf_hash)
+        .map_err(merkle_error)?;
+    Ok(HttpResponse::Ok().json(ProofResponse {
+        index,
         proof,
-        root: root_hex,
+        root: hex::encode(cached.tree.root_hash_ref().map_err(merkle_error)?),
+    }))
+}
+
+/// Answer a ranged file request: 206 with the raw bytes of `range`, or 416 if
+/// it lies outside the file.
+///
+/// The proof headers cover the whole file, so the client can only verify once
+/// it has fetched every range.
+fn range_response(
+    state: &AppState,
+    cached: &CachedTree,
+    file_name: &str,
+    file_bytes: Vec<u8>,
+    range: &ByteRangeSpec,
+) -> Result<HttpResponse> {
+    let Some(index) = cached.entries.iter().position(|n| n == file_name) else {
+        return Ok(HttpResponse::NotFound().body("file not indexed"));
     };
+    let bundle = ProofBundle::from_tree(&cached.tree, index).map_err(merkle_error)?;
+    let len = file_bytes.len() as u64;
 
-    Ok(HttpResponse::Ok().json(resp))
-}
+    let Some((start, end)) = range.to_satisfiable_range(len) else {
+        return Ok(HttpResponse::RangeNotSatisfiable()
+            .insert_header((CONTENT_RANGE, format!("bytes */{}", len)))
+            .finish());
+    };
 
-async fn root(state: web::Data<AppState>) -> Result<impl Responder> {
-    let root_path = state.storage_dir.join("root.hex");
-    match fs::read_to_string(root_path) {
-        Ok(root) => Ok(HttpResponse::Ok().body(root.trim().to_string())),
-        Err(_) => Ok(HttpResponse::Ok().body("no root yet")),
-    }
+    info!(
+        "Serving bytes {}-{} of '{}' ({} bytes)",
+        start, end, file_name, len
+    );
+    Metrics::increment(&state.metrics.file_requests);
+    Ok(HttpResponse::PartialContent()
+        .insert_header((CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)))
+        .insert_header((ROOT_HEADER, hex::encode(&bundle.root)))
+        .insert_header((LEAF_HASH_HEADER, hex::encode(&bundle.leaf_hash)))
+        .insert_header((
+            PROOF_HEADER,
+            hex::encode(MerkleTree::proof_to_bytes(&bundle.proof)),
+        ))
+        .body(file_bytes[start as usize..=end as usize].to_vec()))
 }
 
-/// POST /upload
-/// Receives all files via multipart/form-data, clears storage, builds new tree.
-async fn upload(state: web::Data<AppState>, mut payload: Multipart) -> Result<impl Responder> {
-    info!("Starting bulk upload");
+/// POST /files
+/// Returns a `FileResponse` for each requested name, building the tree once.
+async fn get_files(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    names: web::Json<Vec<String>>,
+) -> Result<impl Responder> {
+    let names = names
+        .into_inner()
+        .iter()
+        .map(|name| sanitize_filename(name))
+        .collect::<Result<Vec<String>>>()?;
 
-    // 1. Clear storage directory (delete all existing files)
-    if state.storage_dir.exists() {
-        for entry in fs::read_dir(&state.storage_dir)? {
-            let entry = entry?;
-            if entry.file_type()?.is_file() {
-                fs::remove_file(entry.path())?;
+    info!("Serving {} files", names.len());
+
+    let cached = cached_tree(&state).await?;
+    let compress = accepts_gzip(&req);
+    let mut responses = Vec::with_capacity(names.len());
+    for name in names {
+        let response = match read_stored(state.storage.as_ref(), &name).await? {
+            Some(file_bytes) => file_response(&cached, name.clone(), file_bytes, compress)?,
+            None => None,
+        };
+        match response {
+            Some(resp) => responses.push(resp),
+            None => {
+                warn!("Batch file request failed: '{}' not found", name);
+                return Ok(HttpResponse::NotFound().body(format!("file not found: {}", name)));
             }
         }
-    } else {
-        fs::create_dir_all(&state.storage_dir)?;
     }
 
-    // 2. Process multipart data and save files
-    let mut file_count = 0;
-    let mut total_size: usize = 0;
+    state
+        .metrics
+        .file_requests
+        .fetch_add(responses.len() as u64, Ordering::Relaxed);
+    Ok(HttpResponse::Ok().json(responses))
+}
 
-    while let Some(item) = payload.next().await {
-        let mut field = item.map_err(actix_web::error::ErrorBadRequest)?;
+/// GET /list[?offset=&limit=]
+/// Returns a page of the stored file names in leaf order, with the current root.
+async fn list_files(
+    state: web::Data<AppState>,
+    query: web::Query<ListQuery>,
+) -> Result<impl Responder> {
+    let query = query.into_inner();
+    if stored_names(state.storage.as_ref()).await?.is_empty() {
+        return Ok(HttpResponse::Ok().json(ListResponse {
+            files: Vec::new(),
+            root: None,
+            count: 0,
+        }));
+    }
 
-        // Check file count limit
-        if file_count >= MAX_FILES {
-            warn!("Upload rejected: too many files (max {})", MAX_FILES);
-            return Err(actix_web::error::ErrorBadRequest(format!(
-                "too many files (max {})",
-                MAX_FILES
-            )));
-        }
+    let cached = cached_tree(&state).await?;
+    let files: Vec<String> = cached
+        .entries
+        .iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect();
 
-        // Get filename from content disposition
-        let content_disp = field.content_disposition();
-        let filename = content_disp
-            .and_then(|cd| cd.get_filename())
-            .ok_or_else(|| actix_web::error::ErrorBadRequest("missing filename"))?;
+    Ok(HttpResponse::Ok().json(ListResponse {
+        files,
+        root: Some(hex::encode(
+            cached.tree.root_hash_ref().map_err(merkle_error)?,
+        )),
+        count: cached.entries.len(),
+    }))
+}
 
-        // Sanitize filename
-        let filename = sanitize_filename(filename)?;
-        let filepath = state.storage_dir.join(&filename);
+/// GET /manifest
+/// Returns every stored file with its size and leaf hash, in leaf order, plus
+/// its content hash when dedup is on.
+async fn manifest(state: web::Data<AppState>) -> Result<impl Responder> {
+    if stored_names(state.storage.as_ref()).await?.is_empty() {
+        return Ok(HttpResponse::Ok().json(Vec::<ManifestEntry>::new()));
+    }
 
-        // Create file and write chunks
-        let mut f = web::block(move || std::fs::File::create(filepath))
-            .await?
-            .map_err(actix_web::error::ErrorInternalServerError)?;
+    let cached = cached_tree(&state).await?;
+    let mut entries = Vec::with_capacity(cached.entries.len());
+    for (index, (name, leaf)) in cached
+        .entries
+        .iter()
+        .zip(cached.tree.get_leaves())
+        .enumerate()
+    {
+        let content_hash_hex = if state.dedup {
+            Some(hex::encode(sha256(&state.storage.read(name).await?)))
+        } else {
+            None
+        };
+        entries.push(ManifestEntry {
+            name: name.clone(),
+            size: state.storage.size(name).await?,
+            leaf_hash_hex: hex::encode(leaf),
+            index,
+            content_hash_hex,
+        });
+    }
 
-        // Track file size
-        let mut file_size: usize = 0;
+    Ok(HttpResponse::Ok().json(entries))
+}
 
-        // Write field data to file
-        while let Some(chunk) = field.next().await {
-            let data = chunk.map_err(actix_web::error::ErrorBadRequest)?;
+/// Names of the files in `storage` (sorted), excluding metadata files.
+async fn stored_names(storage: &dyn Storage) -> std::io::Result<Vec<String>> {
+    let mut names = storage.list().await?;
+    names.retain(|name| !RESERVED_NAMES.contains(&name.as_str()));
+    Ok(names)
+}
 
-            // Check individual file size limit
-            file_size += data.len();
-            if file_size > MAX_FILE_SIZE {
-                warn!(
-                    "Upload rejected: file '{}' exceeds max size of {} bytes",
-                    filename, MAX_FILE_SIZE
-                );
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "file '{}' exceeds max size of {} bytes",
-                    filename, MAX_FILE_SIZE
-                )));
-            }
+/// Put `names` in leaf order under `sort`, asking `storage` for modification
+/// times if the order needs them.
+async fn sort_stored_names(
+    storage: &dyn Storage,
+    names: &mut [String],
+    sort: SortStrategy,
+) -> std::io::Result<()> {
+    let modified = match sort {
+        SortStrategy::ModifiedTime => stored_modified(storage, names).await?,
+        _ => HashMap::new(),
+    };
+    sort_names(names, sort, &modified);
+    Ok(())
+}
 
-            // Check total size limit
-            total_size += data.len();
-            if total_size > MAX_TOTAL_SIZE {
-                warn!(
-                    "Upload rejected: total size exceeds max of {} bytes",
-                    MAX_TOTAL_SIZE
-                );
-                return Err(actix_web::error::ErrorBadRequest(format!(
-                    "total upload size exceeds max of {} bytes",
-                    MAX_TOTAL_SIZE
-                )));
-            }
+/// When each of `names` was last written, according to `storage`.
+async fn stored_modified(
+    storage: &dyn Storage,
+    names: &[String],
+) -> std::io::Result<HashMap<String, SystemTime>> {
+    let mut modified = HashMap::with_capacity(names.len());
+    for name in names {
+        modified.insert(name.clone(), storage.modified(name).await?);
+    }
+    Ok(modified)
+}
 
-            f = web::block(move || f.write_all(&data).map(|_| f))
-                .await?
-                .map_err(actix_web::error::ErrorInternalServerError)?;
+/// Put `names` in leaf order under `sort`; `modified` must hold every name
+/// when sorting by modification time.
+fn sort_names(names: &mut [String], sort: SortStrategy, modified: &HashMap<String, SystemTime>) {
+    match sort {
+        SortStrategy::Lexicographic => names.sort(),
+        SortStrategy::Numeric => names.sort_by(|a, b| natural_cmp(a, b)),
+        SortStrategy::ModifiedTime => {
+            names.sort_by(|a, b| modified[a].cmp(&modified[b]).then(a.cmp(b)))
         }
-
-        info!("Saved file '{}' ({} bytes)", filename, file_size);
-        file_count += 1;
     }
+}
 
-    // 3. Read all filenames (sorted)
-    let mut entries: Vec<_> = fs::read_dir(&state.storage_dir)?
-        .filter_map(|r| r.ok())
+/// List stored file names (sorted), excluding metadata files.
+fn stored_file_names(storage_dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut entries: Vec<_> = fs::read_dir(storage_dir)?
+        .filter_map(|res| res.ok())
         .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .map(|e| e.file_name().into_string().ok())
-        .filter_map(|s| s)
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| !RESERVED_NAMES.contains(&name.as_str()))
         .collect();
     entries.sort();
+    Ok(entries)
+}
 
-    // 4. Read bytes and compute tree
-    let mut files_bytes: Vec<Vec<u8>> = Vec::with_capacity(entries.len());
-    for name in &entries {
-        let pb = state.storage_dir.join(name);
-        let data = fs::read(pb)?;
-        files_bytes.push(data);
+/// Stored files as `(name, path)` pairs in leaf order under `sort`.
+fn stored_files(storage_dir: &Path, sort: SortStrategy) -> Result<Vec<(String, PathBuf)>> {
+    let mut files: Vec<(String, PathBuf)> = stored_file_names(storage_dir)?
+        .into_iter()
+        .map(|name| {
+            let path = storage_dir.join(&name);
+            (name, path)
+        })
+        .collect();
+    sort.sort_files(&mut files).map_err(merkle_error)?;
+    Ok(files)
+}
+
+/// Build the Merkle tree over all stored files in `sort` order.
+///
+/// Each leaf binds the file name to its contents (see
+/// `MerkleTree::from_named_files`). Returns the file names in leaf order
+/// alongside the tree so leaf indices can be mapped back to names. The files
+/// are not rehashed if a checked `leaves.hex` still matches them (see
+/// `trusted_leaves`).
+fn build_tree(storage_dir: &Path, sort: SortStrategy) -> Result<CachedTree> {
+    let files = stored_files(storage_dir, sort)?;
+    let names: Vec<String> = files.iter().map(|(name, _)| name.clone()).collect();
+    let sidecar = fs::read(storage_dir.join("manifest.json"))
+        .ok()
+        .zip(fs::read(storage_dir.join(LEAVES_FILE)).ok());
+    if let Some(leaves) =
+        sidecar.and_then(|(manifest, leaves)| trusted_leaves(&manifest, &leaves, &names))
+    {
+        let tree = MerkleTree::from_leaves(leaves).map_err(merkle_error)?;
+        return Ok(CachedTree {
+            entries: names,
+            tree,
+        });
     }
+    tree_over(files)
+}
 
-    let tree = MerkleTree::from_bytes_vec(&files_bytes)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-    let root = tree
-        .root_hash_ref()
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
-    let root_hex = hex::encode(root);
+/// Build the Merkle tree over the files in `storage`, as `build_tree` does
+/// for a local directory.
+///
+/// Files are streamed from the backend and each is hashed on the blocking
+/// pool.
+async fn build_stored_tree(storage: &dyn Storage, sort: SortStrategy) -> Result<CachedTree> {
+    let mut names = stored_names(storage).await?;
+    sort_stored_names(storage, &mut names, sort).await?;
+    let sidecar = match (
+        read_stored(storage, "manifest.json").await?,
+        read_stored(storage, LEAVES_FILE).await?,
+    ) {
+        (Some(manifest), Some(leaves)) => trusted_leaves(&manifest, &leaves, &names),
+        _ => None,
+    };
 
-    // 5. Persist manifest + root
-    let manifest_path = state.storage_dir.join("manifest.json");
-    let root_path = state.storage_dir.join("root.hex");
+    let leaves = match sidecar {
+        Some(leaves) => leaves,
+        None => hash_stored(storage, &names).await?,
+    };
+    Ok(CachedTree {
+        entries: names,
+        tree: tree_from_leaves(leaves).await?,
+    })
+}
 
-    let manifest_json = serde_json::to_string(&entries)?;
-    let mut mfile = File::create(manifest_path)?;
-    mfile.write_all(manifest_json.as_bytes())?;
+/// Named leaf hashes of the stored files `names`, each streamed from
+/// `storage` and hashed on the blocking pool.
+async fn hash_stored(storage: &dyn Storage, names: &[String]) -> Result<Vec<Vec<u8>>> {
+    let mut leaves = Vec::with_capacity(names.len());
+    for name in names {
+        let reader = storage.open(name).await?;
+        let name = name.clone();
+        leaves.push(
+            offload(move || {
+                TreeConfig::default()
+                    .hash_named_leaf_reader(&name, reader)
+                    .map_err(merkle_error)
+            })
+            .await?,
+        );
+    }
+    Ok(leaves)
+}
 
-    let mut rfile = File::create(root_path)?;
-    rfile.write_all(root_hex.as_bytes())?;
+/// Build the tree over `leaves` on the blocking pool.
+async fn tree_from_leaves(leaves: Vec<Vec<u8>>) -> Result<MerkleTree> {
+    offload(move || MerkleTree::from_leaves(leaves).map_err(merkle_error)).await
+}
 
-    info!("Upload complete: {} files, root={}", file_count, root_hex);
+/// Build the tree over `(name, path)` pairs, given in leaf order.
+fn tree_over(files: Vec<(String, PathBuf)>) -> Result<CachedTree> {
+    let tree = MerkleTree::from_named_file_paths_streaming(&files).map_err(merkle_error)?;
+    let entries = files.into_iter().map(|(name, _)| name).collect();
+    Ok(CachedTree { entries, tree })
+}
 
-    Ok(HttpResponse::Ok().json(UploadResponse {
-        root: root_hex,
-        files_count: file_count,
-    }))
+/// Parse a `leaves.hex` sidecar: one hex hash per non-empty line.
+fn parse_leaves(text: &str) -> std::result::Result<Vec<Vec<u8>>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(i, line)| hex::decode(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+/// Leaf hashes from a stored `leaves.hex`, if it can stand in for rehashing
+/// the files `names`; `manifest` and `leaves` are the stored
+/// `manifest.json` and `leaves.hex`.
+///
+/// The sidecar was checked against the file contents when it was uploaded,
+/// so it is trusted as long as `manifest.json` still lists exactly `names`
+/// and the sidecar has one hash per name.
+fn trusted_leaves(manifest: &[u8], leaves: &[u8], names: &[String]) -> Option<Vec<Vec<u8>>> {
+    let manifest: Vec<String> = serde_json::from_slice(manifest).ok()?;
+    if manifest != names {
+        return None;
+    }
+    let leaves = parse_leaves(std::str::from_utf8(leaves).ok()?).ok()?;
+    (leaves.len() == names.len()).then_some(leaves)
+}
 
-    let storage_dir = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "./server_files".to_string());
-    let port: u16 = std::env::var("PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(3000);
+/// Check an uploaded `leaves.hex` against the leaves recomputed from the
+/// staged files.
+fn check_leaves_sidecar(staging_dir: &Path, tree: &MerkleTree) -> Result<()> {
+    let text = fs::read_to_string(staging_dir.join(LEAVES_FILE)).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("invalid {}: {}", LEAVES_FILE, e))
+    })?;
+    let leaves = parse_leaves(&text).map_err(|e| {
+        actix_web::error::ErrorBadRequest(format!("invalid {}: {}", LEAVES_FILE, e))
+    })?;
 
-    let state = AppState {
-        storage_dir: PathBuf::from(storage_dir),
-    };
+    let computed = tree.get_leaves();
+    if leaves.len() != computed.len() {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "{} lists {} hashes for {} files",
+            LEAVES_FILE,
+            leaves.len(),
+            computed.len()
+        )));
+    }
+    if let Some(index) = (0..leaves.len()).find(|&i| leaves[i] != computed[i]) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "{} hash {} does not match the uploaded files",
+            LEAVES_FILE, index
+        )));
+    }
+    Ok(())
+}
 
-    info!(
-        "Starting server on 0.0.0.0:{} storing files in {:?}",
-        port, state.storage_dir
-    );
+/// Rebuild the tree from the storage backend and store it in the cache.
+async fn rebuild_cache(state: &AppState) -> Result<Arc<CachedTree>> {
+    let cached = Arc::new(build_stored_tree(state.storage.as_ref(), state.sort).await?);
+    store_cache(state, cached.clone())?;
+    info!("Rebuilt tree cache ({} files)", cached.entries.len());
+    Ok(cached)
+}
 
-    HttpServer::new(move || {
-        App::new()
-            .wrap(TracingLogger::default())
-            .app_data(web::Data::new(state.clone()))
-            .route("/upload", web::post().to(upload))
-            .route("/file/{name}", web::get().to(get_file))
-            .route("/root", web::get().to(root))
+/// Replace the cached tree.
+fn store_cache(state: &AppState, cached: Arc<CachedTree>) -> Result<()> {
+    *state
+        .tree_cache
+        .write()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("tree cache lock poisoned"))? =
+        Some(cached);
+    Ok(())
+}
+
+/// Drop the cached tree so the next reader rebuilds it from storage.
+fn invalidate_cache(state: &AppState) -> Result<()> {
+    *state
+        .tree_cache
+        .write()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("tree cache lock poisoned"))? =
+        None;
+    Ok(())
+}
+
+/// Return the cached tree, building it from storage only if the cache is
+/// empty (e.g. after a restart).
+///
+/// The rebuild hashes every stored file, which runs on the blocking pool.
+async fn cached_tree(state: &AppState) -> Result<Arc<CachedTree>> {
+    let cached = state
+        .tree_cache
+        .read()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("tree cache lock poisoned"))?
+        .clone();
+    match cached {
+        Some(cached) => Ok(cached),
+        None => rebuild_cache(state).await,
+    }
+}
+
+/// Run blocking work, such as hashing files or writing metadata, on the
+/// blocking thread pool so that handlers never stall the async executor.
+///
+/// Errors cannot cross threads as they are, so they come back with the same
+/// status code and message.
+async fn offload<T, F>(work: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(move || work().map_err(|e| (e.as_response_error().status_code(), e.to_string())))
+        .await?
+        .map_err(|(status, message)| actix_web::error::InternalError::new(message, status).into())
+}
+
+/// Name of the persisted tree file in `storage_dir`, preferring the
+/// compressed one, or `None` if neither is present.
+fn persisted_tree_file(storage_dir: &Path) -> Option<&'static str> {
+    [TREE_FILE_ZST, TREE_FILE]
+        .into_iter()
+        .find(|name| storage_dir.join(name).exists())
+}
+
+/// Load the tree persisted by the last upload, checking it against `root.hex`
+/// and `manifest.json`.
+///
+/// Returns a description of the problem if the files are unreadable or
+/// disagree with each other.
+fn load_persisted_tree(storage_dir: &Path) -> std::result::Result<CachedTree, String> {
+    let read = |name: &str| {
+        fs::read_to_string(storage_dir.join(name)).map_err(|e| format!("reading {}: {}", name, e))
+    };
+
+    let tree_file = persisted_tree_file(storage_dir)
+        .ok_or_else(|| format!("neither {} nor {} is present", TREE_FILE, TREE_FILE_ZST))?;
+    let mut tree_json = fs::read(storage_dir.join(tree_file))
+        .map_err(|e| format!("reading {}: {}", tree_file, e))?;
+    if tree_file == TREE_FILE_ZST {
+        tree_json = zstd::decode_all(tree_json.as_slice())
+            .map_err(|e| format!("decompressing {}: {}", tree_file, e))?;
+    }
+    let tree = std::str::from_utf8(&tree_json)
+        .map_err(|e| e.to_string())
+        .and_then(|json| MerkleTree::from_json(json).map_err(|e| e.to_string()))
+        .map_err(|e| format!("parsing {}: {}", tree_file, e))?;
+    let entries: Vec<String> = serde_json::from_str(&read("manifest.json")?)
+        .map_err(|e| format!("parsing manifest.json: {}", e))?;
+    let saved_root = read("root.hex")?;
+
+    let tree_root = hex::encode(tree.root_hash_ref().map_err(|e| e.to_string())?);
+    if tree_root != saved_root.trim() {
+        return Err(format!(
+            "{} root {} does not match root.hex {}",
+            tree_file,
+            tree_root,
+            saved_root.trim()
+        ));
+    }
+    if tree.leaf_count() != entries.len() {
+        return Err(format!(
+            "{} has {} leaves but manifest.json lists {} files",
+            tree_file,
+            tree.leaf_count(),
+            entries.len()
+        ));
+    }
+
+    Ok(CachedTree { entries, tree })
+}
+
+/// Check `manifest.json` against the files actually in storage and, if they
+/// disagree, rewrite manifest, root and tree from the stored files.
+///
+/// Runs at startup so proofs are never served against a stale manifest.
+async fn reconcile_storage(state: &AppState) -> Result<()> {
+    let storage = state.storage.as_ref();
+    let mut stored = stored_names(storage).await?;
+    sort_stored_names(storage, &mut stored, state.sort).await?;
+    let manifest: Option<Vec<String>> = read_stored(storage, "manifest.json")
+        .await?
+        .and_then(|json| serde_json::from_slice(&json).ok());
+
+    match &manifest {
+        Some(listed) if *listed == stored => return Ok(()),
+        Some(listed) => warn!(
+            "manifest.json disagrees with storage ({} missing, {} extra files); rebuilding",
+            listed.iter().filter(|name| !stored.contains(name)).count(),
+            stored.iter().filter(|name| !listed.contains(name)).count()
+        ),
+        None if stored.is_empty() => return Ok(()),
+        None => warn!(
+            "manifest.json missing or unreadable; rebuilding from {} stored files",
+            stored.len()
+        ),
+    }
+
+    if stored.is_empty() {
+        // nothing left to build a tree over: drop the stale metadata
+        for name in [
+            "manifest.json",
+            "root.hex",
+            TREE_FILE,
+            TREE_FILE_ZST,
+            LEAVES_FILE,
+        ] {
+            storage.remove(name).await?;
+        }
+        return invalidate_cache(state);
+    }
+
+    let cached = build_stored_tree(storage, state.sort).await?;
+    for (name, contents) in metadata_files(&cached, state.compress_tree)? {
+        match contents {
+            Some(contents) => storage.write(name, &contents).await?,
+            None => storage.remove(name).await?,
+        }
+    }
+    info!("Reconciled storage ({} files)", cached.entries.len());
+    store_cache(state, Arc::new(cached))
+}
+
+/// Rehash every stored file and compare the resulting root with `root.hex`,
+/// recording the outcome in `state.integrity_ok`.
+///
+/// The sidecar and persisted tree are ignored so that corruption on disk is
+/// caught. A check that overlaps an upload (`root.hex` changes while hashing)
+/// is inconclusive and leaves the flag as it was. Returns whether storage
+/// was found intact.
+async fn check_integrity(state: &AppState) -> std::result::Result<bool, String> {
+    let storage = state.storage.as_ref();
+    let read_root = || async move {
+        read_stored(storage, "root.hex")
+            .await
+            .ok()
+            .flatten()
+            .map(|root| String::from_utf8_lossy(&root).into_owned())
+    };
+    let Some(saved_root) = read_root().await else {
+        // nothing uploaded yet, so nothing to rot
+        state.integrity_ok.store(true, Ordering::Relaxed);
+        return Ok(true);
+    };
+
+    let rebuilt = rehash_stored(storage, state.sort)
+        .await
+        .map_err(|e| e.to_string())?;
+    let rebuilt_root = hex::encode(rebuilt.tree.root_hash_ref().map_err(|e| e.to_string())?);
+
+    if read_root().await.as_ref() != Some(&saved_root) {
+        info!("Storage changed during integrity check; skipping this round");
+        return Ok(state.integrity_ok.load(Ordering::Relaxed));
+    }
+    let intact = rebuilt_root == saved_root.trim();
+    if intact {
+        info!("Integrity check passed ({} files)", rebuilt.entries.len());
+    } else {
+        error!(
+            "Integrity check failed: stored files hash to {} but root.hex is {}",
+            rebuilt_root,
+            saved_root.trim()
+        );
+    }
+    state.integrity_ok.store(intact, Ordering::Relaxed);
+    Ok(intact)
+}
+
+/// Build the tree over every stored file by hashing it, never trusting the
+/// `leaves.hex` sidecar.
+async fn rehash_stored(storage: &dyn Storage, sort: SortStrategy) -> Result<CachedTree> {
+    let mut names = stored_names(storage).await?;
+    sort_stored_names(storage, &mut names, sort).await?;
+    let leaves = hash_stored(storage, &names).await?;
+    Ok(CachedTree {
+        entries: names,
+        tree: tree_from_leaves(leaves).await?,
     })
-    .bind(("0.0.0.0", port))?
-    .run()
-    .await
+}
+
+/// Run `check_integrity` every `interval` until the server stops.
+///
+/// Hashing runs on the blocking pool so requests keep being served.
+async fn run_integrity_checks(state: AppState, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // the first tick fires at once; startup has just reconciled storage
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if let Err(e) = check_integrity(&state).await {
+            warn!("Integrity check could not run: {}", e);
+        }
+    }
+}
+
+/// Populate the cache at startup from `tree.json` or `tree.json.zst`, if
+/// present.
+///
+/// A persisted tree that fails validation is discarded in favour of a
+/// rebuild from the stored files.
+async fn load_cache(state: &AppState) -> Result<()> {
+    if persisted_tree_file(&state.storage_dir).is_none() {
+        return Ok(());
+    }
+    match load_persisted_tree(&state.storage_dir) {
+        Ok(cached) => {
+            info!("Loaded persisted tree ({} files)", cached.entries.len());
+            store_cache(state, Arc::new(cached))
+        }
+        Err(reason) => {
+            warn!("Ignoring persisted tree: {}; rebuilding", reason);
+            rebuild_cache(state).await.map(|_| ())
+        }
+    }
+}
+
+/// Read `name` from storage, or `None` if it is not stored.
+async fn read_stored(storage: &dyn Storage, name: &str) -> Result<Option<Vec<u8>>> {
+    match storage.read(name).await {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Build the response for `file_name` with contents `file_bytes`, or `None`
+/// if it is not a leaf of the cached tree.
+///
+/// With `compress`, the bytes are gzipped before base64 encoding; the proof
+/// still covers the uncompressed contents.
+fn file_response(
+    cached: &CachedTree,
+    file_name: String,
+    mut file_bytes: Vec<u8>,
+    compress: bool,
+) -> Result<Option<FileResponse>> {
+    // find index
+    let index = match cached.entries.iter().position(|n| n == &file_name) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+
+    // generate proof
+    let bundle = ProofBundle::from_tree(&cached.tree, index).map_err(merkle_error)?;
+    let content_type = detect_content_type(&file_name, &file_bytes).to_string();
+    if compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&file_bytes)?;
+        file_bytes = encoder.finish()?;
+    }
+    let file_b64 = general_purpose::STANDARD.encode(&file_bytes);
+
+    Ok(Some(FileResponse {
+        file_name,
+        file_bytes: file_b64,
+        compressed: compress,
+        content_type,
+        bundle,
+    }))
+}
+
+/// Guess a MIME type from the file's leading bytes, falling back to its
+/// extension for formats without a signature (mostly text).
+fn detect_content_type(name: &str, bytes: &[u8]) -> &'static str {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type();
+    }
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("txt") | Some("log") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("csv") => "text/csv",
+        Some("html") | Some("htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("toml") => "application/toml",
+        Some("yaml") | Some("yml") => "application/yaml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// GET /root[?format=json]
+/// Returns the hex root as plain text, or with `format=json` the root along
+/// with the tree's leaf count, height and hash algorithm.
+async fn root(state: web::Data<AppState>, query: web::Query<RootQuery>) -> Result<impl Responder> {
+    match query.into_inner().format.as_deref() {
+        None | Some("text") => {}
+        Some("json") => return root_json(&state).await,
+        Some(other) => {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "unknown format '{}' (expected text or json)",
+                other
+            )));
+        }
+    }
+    match state.storage.read("root.hex").await {
+        Ok(root) => Ok(HttpResponse::Ok().body(String::from_utf8_lossy(&root).trim().to_string())),
+        Err(_) => Ok(HttpResponse::Ok().body("no root yet")),
+    }
+}
+
+/// Root and shape of the cached tree, for `GET /root?format=json`.
+async fn root_json(state: &AppState) -> Result<HttpResponse> {
+    if stored_names(state.storage.as_ref()).await?.is_empty() {
+        return Ok(HttpResponse::Ok().json(RootResponse {
+            root: None,
+            leaf_count: 0,
+            height: 0,
+            algorithm: HashAlgorithm::default(),
+        }));
+    }
+
+    let cached = cached_tree(state).await?;
+    Ok(HttpResponse::Ok().json(RootResponse {
+        root: Some(hex::encode(
+            cached.tree.root_hash_ref().map_err(merkle_error)?,
+        )),
+        leaf_count: cached.tree.leaf_count(),
+        height: cached.tree.tree_height(),
+        algorithm: cached.tree.algorithm(),
+    }))
+}
+
+/// GET /health
+/// Liveness check reporting the stored file count and whether a root exists.
+/// Answers 503 with status `unhealthy` while the last integrity check failed.
+async fn health(state: web::Data<AppState>) -> Result<impl Responder> {
+    let stored = state.storage.list().await?;
+    let (mut builder, status) = if state.integrity_ok.load(Ordering::Relaxed) {
+        (HttpResponse::Ok(), "ok")
+    } else {
+        (HttpResponse::ServiceUnavailable(), "unhealthy")
+    };
+    Ok(builder.json(HealthResponse {
+        status,
+        files: stored
+            .iter()
+            .filter(|name| !RESERVED_NAMES.contains(&name.as_str()))
+            .count(),
+        has_root: stored.iter().any(|name| name == "root.hex"),
+    }))
+}
+
+/// GET /metrics
+/// Request counters in the Prometheus text format.
+async fn metrics(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render())
+}
+
+/// Hex of the first `LOG_HASH_PREFIX_BYTES` of `hash`, for log lines.
+fn hash_prefix(hash: &[u8]) -> String {
+    hex::encode(&hash[..hash.len().min(LOG_HASH_PREFIX_BYTES)])
+}
+
+/// POST /verify
+/// Checks a client-supplied proof against a client-supplied root.
+/// Every outcome is logged (passes at `info`, failures at `warn`) with hash
+/// prefixes, as an audit trail of verification attempts.
+async fn verify(
+    state: web::Data<AppState>,
+    req: web::Json<VerifyRequest>,
+) -> Result<impl Responder> {
+    let req = req.into_inner();
+    let leaf_hash = hex::decode(&req.leaf_hash)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("invalid leaf_hash hex: {}", e)))?;
+    let root = hex::decode(&req.root)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("invalid root hex: {}", e)))?;
+
+    let valid = MerkleTree::verify_proof(&leaf_hash, &req.proof, &root);
+    let (leaf_prefix, root_prefix) = (hash_prefix(&leaf_hash), hash_prefix(&root));
+    if valid {
+        info!(leaf_hash = %leaf_prefix, root = %root_prefix, valid, "Proof verified");
+    } else {
+        warn!(leaf_hash = %leaf_prefix, root = %root_prefix, valid, "Proof rejected");
+        Metrics::increment(&state.metrics.verification_failures);
+    }
+
+    Ok(HttpResponse::Ok().json(VerifyResponse { valid }))
+}
+
+/// The 429 response for a client that uploaded or appended within the last
+/// `min_upload_interval`, or `None` if it may write now.
+fn throttled_response(state: &AppState, req: &HttpRequest) -> Result<Option<HttpResponse>> {
+    // Clients without a known address (only in tests) are not throttled
+    let throttled = match req.peer_addr() {
+        Some(addr) => state
+            .throttle_upload(addr.ip(), Instant::now())?
+            .map(|wait| (addr.ip(), wait)),
+        None => None,
+    };
+    Ok(throttled.map(|(ip, wait)| {
+        warn!("Write from {} rejected: rate limited", ip);
+        HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait.as_secs_f64().ceil().to_string()))
+            .body("Too many uploads; try again later")
+    }))
+}
+
+/// POST /upload
+/// Receives all files via multipart/form-data, clears storage, builds new tree.
+/// Each client IP may upload at most once per `min_upload_interval`. With an
+/// `x-upload-sha256` header the received files must match that checksum.
+async fn upload(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    payload: Multipart,
+) -> Result<impl Responder> {
+    if let Some(response) = throttled_response(&state, &req)? {
+        return Ok(response);
+    }
+    let expected_digest = req
+        .headers()
+        .get(UPLOAD_SHA256_HEADER)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| hex::decode(value).ok())
+                .ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest(format!(
+                        "invalid {} header",
+                        UPLOAD_SHA256_HEADER
+                    ))
+                })
+        })
+        .transpose()?;
+    info!("Starting bulk upload");
+    let _writer = state.write_lock.lock().await;
+
+    // 1. Prepare an empty staging directory; live files stay untouched until the swap
+    let staging_dir = prepare_staging(&state.storage_dir)?;
+
+    // 2. Receive files, build the tree and write metadata, all inside staging
+    let (file_count, cached) =
+        match stage_upload(&state, &staging_dir, payload, expected_digest).await {
+            Ok(staged) => staged,
+            Err(e) => {
+                warn!("Upload failed, discarding staged files: {}", e);
+                discard_staging(&staging_dir);
+                return Err(e);
+            }
+        };
+
+    // 3. Swap staged files into place and refresh the cache
+    invalidate_cache(&state)?;
+    state.storage.replace_with_staged(&staging_dir).await?;
+    let root_hex = hex::encode(cached.tree.root_hash_ref().map_err(merkle_error)?);
+    store_cache(&state, Arc::new(cached))?;
+
+    info!("Upload complete: {} files, root={}", file_count, root_hex);
+    Metrics::increment(&state.metrics.uploads);
+    state.notify_root(&root_hex);
+
+    Ok(HttpResponse::Ok().json(UploadResponse {
+        root: root_hex,
+        files_count: file_count,
+    }))
+}
+
+/// Receive all multipart files into `staging_dir`, then build the tree over
+/// them and persist manifest, root and tree alongside.
+///
+/// The files must hash to `expected_digest`, if given, and a `leaves.hex`
+/// sidecar in the upload must match the recomputed leaves (400 otherwise);
+/// the sidecar is kept for later rebuilds.
+///
+/// Any error leaves the live storage untouched; the caller discards staging.
+async fn stage_upload(
+    state: &AppState,
+    staging_dir: &Path,
+    payload: Multipart,
+    expected_digest: Option<Vec<u8>>,
+) -> Result<(usize, CachedTree)> {
+    let names = receive_files(staging_dir, payload, &[], true, &state.limits).await?;
+    let file_count = names.len();
+
+    let (state, staging_dir) = (state.clone(), staging_dir.to_path_buf());
+    let cached = offload(move || {
+        if let Some(expected) = expected_digest {
+            check_upload_digest(&staging_dir, &names, &expected)?;
+        }
+        if state.dedup {
+            let staged: Vec<PathBuf> = names.iter().map(|name| staging_dir.join(name)).collect();
+            let linked = dedup_files(&[], &staged)?;
+            info!("Deduplicated {} of {} files", linked, names.len());
+        }
+
+        // Read all staged files in leaf order and compute tree; staging has no
+        // manifest yet, so the sidecar is never trusted here
+        let cached = build_tree(&staging_dir, state.sort)?;
+        if staging_dir.join(LEAVES_FILE).exists() {
+            check_leaves_sidecar(&staging_dir, &cached.tree)?;
+        }
+        write_metadata(&staging_dir, &cached, state.compress_tree)?;
+        Ok(cached)
+    })
+    .await?;
+
+    Ok((file_count, cached))
+}
+
+/// Check that the staged `names` (sorted, without the sidecar), read back and
+/// concatenated, hash to the client's `x-upload-sha256` value.
+///
+/// This runs before anything is hashed into the tree, so bytes corrupted in
+/// transit fail the upload with 400 instead of producing a root the client
+/// then rejects.
+fn check_upload_digest(staging_dir: &Path, names: &[String], expected: &[u8]) -> Result<()> {
+    let mut hasher = Sha256::new();
+    for name in names {
+        hasher.update(fs::read(staging_dir.join(name))?);
+    }
+    let digest = hasher.finalize();
+    if !constant_time_eq(&digest, expected) {
+        warn!(
+            "Upload rejected: checksum {} does not match {} header {}",
+            hash_prefix(&digest),
+            UPLOAD_SHA256_HEADER,
+            hash_prefix(expected)
+        );
+        return Err(actix_web::error::ErrorBadRequest(
+            "upload checksum mismatch: files were corrupted in transit",
+        ));
+    }
+    Ok(())
+}
+
+/// Save every multipart file into `staging_dir`, enforcing the size and count
+/// limits, and return the received names sorted.
+///
+/// Names in `existing` are rejected with 409 Conflict. With `accept_leaves`,
+/// a `leaves.hex` sidecar is saved too but left out of the returned names.
+async fn receive_files(
+    staging_dir: &Path,
+    mut payload: Multipart,
+    existing: &[String],
+    accept_leaves: bool,
+    limits: &Limits,
+) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut file_count = 0;
+    let mut total_size: usize = 0;
+
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(actix_web::error::ErrorBadRequest)?;
+
+        // Check file count limit
+        if file_count >= limits.max_files {
+            warn!("Upload rejected: too many files (max {})", limits.max_files);
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "too many files (max {})",
+                limits.max_files
+            )));
+        }
+
+        // Get filename from content disposition
+        let content_disp = field.content_disposition();
+        let filename = content_disp
+            .and_then(|cd| cd.get_filename())
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("missing filename"))?;
+
+        // Sanitize filename
+        let filename = if accept_leaves && filename == LEAVES_FILE {
+            LEAVES_FILE.to_string()
+        } else {
+            sanitize_filename(filename)?
+        };
+        if existing.contains(&filename) {
+            warn!("Upload rejected: '{}' already exists", filename);
+            return Err(actix_web::error::ErrorConflict(format!(
+                "file '{}' already exists",
+                filename
+            )));
+        }
+        let filepath = staging_dir.join(&filename);
+
+        // Create file and write chunks
+        let mut f = web::block(move || std::fs::File::create(filepath))
+            .await?
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        // Track file size
+        let mut file_size: usize = 0;
+
+        // Write field data to file
+        while let Some(chunk) = field.next().await {
+            let data = chunk.map_err(actix_web::error::ErrorBadRequest)?;
+
+            // Check individual file size limit
+            file_size += data.len();
+            if file_size > limits.max_file_size {
+                warn!(
+                    "Upload rejected: file '{}' exceeds max size of {} bytes",
+                    filename, limits.max_file_size
+                );
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "file '{}' exceeds max size of {} bytes",
+                    filename, limits.max_file_size
+                )));
+            }
+
+            // Check total size limit
+            total_size += data.len();
+            if total_size > limits.max_total_size {
+                warn!(
+                    "Upload rejected: total size exceeds max of {} bytes",
+                    limits.max_total_size
+                );
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "total upload size exceeds max of {} bytes",
+                    limits.max_total_size
+                )));
+            }
+
+            f = web::block(move || f.write_all(&data).map(|_| f))
+                .await?
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+
+        info!("Saved file '{}' ({} bytes)", filename, file_size);
+        if filename != LEAVES_FILE {
+            names.push(filename);
+        }
+        file_count += 1;
+    }
+
+    // a name sent twice was overwritten in staging, so it counts once
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Replace each file in `staged` whose contents equal those of a file in
+/// `kept` or an earlier one in `staged` with a hard link to it, returning how
+/// many were replaced.
+///
+/// Names and contents stay as they were, so the tree still has one leaf per
+/// name and the root is the same as without dedup; only the disk space is
+/// shared. Leaves bind the name, so duplicates could not share a leaf anyway.
+fn dedup_files(kept: &[PathBuf], staged: &[PathBuf]) -> std::io::Result<usize> {
+    let mut originals: HashMap<Vec<u8>, &Path> = HashMap::new();
+    for path in kept {
+        originals.entry(sha256(&fs::read(path)?)).or_insert(path);
+    }
+    let mut linked = 0;
+    for path in staged {
+        match originals.entry(sha256(&fs::read(path)?)) {
+            Entry::Occupied(original) => {
+                fs::remove_file(path)?;
+                fs::hard_link(original.get(), path)?;
+                linked += 1;
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(path);
+            }
+        }
+    }
+    Ok(linked)
+}
+
+/// Persist manifest, root and serialized tree for `cached` into `dir`.
+///
+/// With `compress_tree` the tree goes to `tree.json.zst`, otherwise to
+/// `tree.json`; whichever of the two is not written is removed so a restart
+/// never loads a stale one.
+fn write_metadata(dir: &Path, cached: &CachedTree, compress_tree: bool) -> Result<()> {
+    for (name, contents) in metadata_files(cached, compress_tree)? {
+        let path = dir.join(name);
+        match contents {
+            Some(contents) => File::create(path)?.write_all(&contents)?,
+            None if path.exists() => fs::remove_file(path)?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// A metadata file's name and contents, or `None` if it is to be removed.
+type MetadataFile = (&'static str, Option<Vec<u8>>);
+
+/// Manifest, serialized tree and root for `cached` as `(name, contents)`
+/// pairs, root last; the tree file that is not written comes first with
+/// `None`, to be removed.
+fn metadata_files(cached: &CachedTree, compress_tree: bool) -> Result<Vec<MetadataFile>> {
+    let root = cached.tree.root_hash_ref().map_err(merkle_error)?;
+    let manifest_json = serde_json::to_vec(&cached.entries)?;
+
+    // Only the leaves are stored; the levels are cheap to rebuild on load
+    let tree_json = cached.tree.to_json_leaves_only().map_err(merkle_error)?;
+    let (tree_file, tree_bytes, stale) = if compress_tree {
+        let compressed = zstd::encode_all(tree_json.as_bytes(), TREE_ZSTD_LEVEL)?;
+        (TREE_FILE_ZST, compressed, TREE_FILE)
+    } else {
+        (TREE_FILE, tree_json.into_bytes(), TREE_FILE_ZST)
+    };
+
+    Ok(vec![
+        (stale, None),
+        ("manifest.json", Some(manifest_json)),
+        (tree_file, Some(tree_bytes)),
+        ("root.hex", Some(hex::encode(root).into_bytes())),
+    ])
+}
+
+/// POST /append
+/// Adds files via multipart/form-data without removing existing ones; names
+/// that already exist are rejected with 409. Appends count towards the same
+/// per-client `min_upload_interval` as uploads.
+async fn append(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    payload: Multipart,
+) -> Result<impl Responder> {
+    if let Some(response) = throttled_response(&state, &req)? {
+        return Ok(response);
+    }
+    info!("Starting append");
+    let _writer = state.write_lock.lock().await;
+
+    let staging_dir = prepare_staging(&state.storage_dir)?;
+    let existing = stored_names(state.storage.as_ref()).await?;
+
+    let (added, cached) = match stage_append(&state, &staging_dir, payload, &existing).await {
+        Ok(staged) => staged,
+        Err(e) => {
+            warn!("Append failed, discarding staged files: {}", e);
+            discard_staging(&staging_dir);
+            return Err(e);
+        }
+    };
+
+    // Existing files stay; staged files and the new metadata move in beside them
+    invalidate_cache(&state)?;
+    state.storage.add_staged(&staging_dir).await?;
+    let root_hex = hex::encode(cached.tree.root_hash_ref().map_err(merkle_error)?);
+    let files_count = cached.entries.len();
+    store_cache(&state, Arc::new(cached))?;
+
+    info!(
+        "Append complete: {} new files, {} total, root={}",
+        added, files_count, root_hex
+    );
+    Metrics::increment(&state.metrics.uploads);
+    state.notify_root(&root_hex);
+
+    Ok(HttpResponse::Ok().json(AppendResponse {
+        root: root_hex,
+        added,
+        files_count,
+    }))
+}
+
+/// Receive appended files into `staging_dir` and build the tree over the
+/// existing and new files together, writing its metadata into staging.
+///
+/// Existing files are read through the storage backend, new ones from
+/// staging.
+async fn stage_append(
+    state: &AppState,
+    staging_dir: &Path,
+    payload: Multipart,
+    existing: &[String],
+) -> Result<(usize, CachedTree)> {
+    let added = receive_files(staging_dir, payload, existing, false, &state.limits).await?;
+    let added_count = added.len();
+    let storage = state.storage.as_ref();
+
+    if state.dedup {
+        // Hard links only reach files in the local storage directory
+        let kept: Vec<PathBuf> = existing
+            .iter()
+            .map(|name| state.storage_dir.join(name))
+            .collect();
+        let staged: Vec<PathBuf> = added.iter().map(|name| staging_dir.join(name)).collect();
+        let linked = offload(move || Ok(dedup_files(&kept, &staged)?)).await?;
+        info!("Deduplicated {} of {} new files", linked, added_count);
+    }
+
+    let mut leaves: HashMap<String, Vec<u8>> = existing
+        .iter()
+        .cloned()
+        .zip(hash_stored(storage, existing).await?)
+        .collect();
+    let mut modified = match state.sort {
+        SortStrategy::ModifiedTime => stored_modified(storage, existing).await?,
+        _ => HashMap::new(),
+    };
+    let staged_dir = staging_dir.to_path_buf();
+    let staged = offload(move || {
+        added
+            .into_iter()
+            .map(|name| {
+                let path = staged_dir.join(&name);
+                let leaf = TreeConfig::default()
+                    .hash_named_leaf_reader(&name, File::open(&path)?)
+                    .map_err(merkle_error)?;
+                let modified = fs::metadata(&path)?.modified()?;
+                Ok((name, leaf, modified))
+            })
+            .collect::<Result<Vec<_>>>()
+    })
+    .await?;
+    for (name, leaf, time) in staged {
+        leaves.insert(name.clone(), leaf);
+        modified.insert(name, time);
+    }
+
+    let mut names: Vec<String> = leaves.keys().cloned().collect();
+    sort_names(&mut names, state.sort, &modified);
+    let ordered = names.iter().map(|name| leaves[name].clone()).collect();
+    let cached = CachedTree {
+        entries: names,
+        tree: tree_from_leaves(ordered).await?,
+    };
+
+    // keep a stored sidecar in step with the grown tree
+    let keep_sidecar = read_stored(storage, LEAVES_FILE).await?.is_some();
+    let (staging_dir, compress_tree) = (staging_dir.to_path_buf(), state.compress_tree);
+    let cached = offload(move || {
+        write_metadata(&staging_dir, &cached, compress_tree)?;
+        if keep_sidecar {
+            let leaves: Vec<String> = cached.tree.get_leaves().iter().map(hex::encode).collect();
+            fs::write(staging_dir.join(LEAVES_FILE), leaves.join("\n"))?;
+        }
+        Ok(cached)
+    })
+    .await?;
+
+    Ok((added_count, cached))
+}
+
+/// Create an empty staging directory inside `storage_dir`, replacing any
+/// leftover from an interrupted request.
+fn prepare_staging(storage_dir: &Path) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(storage_dir)?;
+    let staging_dir = storage_dir.join(STAGING_DIR);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir(&staging_dir)?;
+    Ok(staging_dir)
+}
+
+/// Remove a staging directory after a failed request, logging any failure.
+fn discard_staging(staging_dir: &Path) {
+    if let Err(cleanup) = fs::remove_dir_all(staging_dir) {
+        warn!(
+            "Failed to remove staging dir {:?}: {}",
+            staging_dir, cleanup
+        );
+    }
+}
+
+/// Move everything in `staging_dir` into `storage_dir`, overwriting files of
+/// the same name with metadata last, then remove the staging directory.
+fn move_staged(storage_dir: &Path, staging_dir: &Path) -> std::io::Result<()> {
+    for name in storage::staged_names(staging_dir)? {
+        fs::rename(staging_dir.join(&name), storage_dir.join(&name))?;
+    }
+    fs::remove_dir(staging_dir)
+}
+
+/// GET /ws
+/// WebSocket that pushes the hex of every new root, after each upload or
+/// append, as a text message. Nothing is sent on connect; fetch `/root` for
+/// the current value.
+async fn ws_root(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse> {
+    actix_web_actors::ws::start(
+        RootSocket::new(state.root_updates.subscribe()),
+        &req,
+        stream,
+    )
+}
+
+/// Register all endpoints; shared by `main` and the tests.
+fn routes(cfg: &mut web::ServiceConfig) {
+    let json_config = web::JsonConfig::default().error_handler(|err, _req| {
+        let message = format!("invalid JSON body: {}", err);
+        actix_web::error::ErrorBadRequest(message)
+    });
+
+    cfg.app_data(json_config)
+        .route("/upload", web::post().to(upload))
+        .route("/append", web::post().to(append))
+        .route("/file/{name}", web::get().to(get_file))
+        .route("/files", web::post().to(get_files))
+        .route("/proof/{hash_hex}", web::get().to(get_proof))
+        .route("/list", web::get().to(list_files))
+        .route("/manifest", web::get().to(manifest))
+        .route("/root", web::get().to(root))
+        .route("/verify", web::post().to(verify))
+        .route("/health", web::get().to(health))
+        .route("/metrics", web::get().to(metrics))
+        .route("/ws", web::get().to(ws_root));
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
+
+    let storage_dir = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "./server_files".to_string());
+    let port: u16 = std::env::var("PORT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(3000);
+
+    let limits = Limits::from_env();
+    info!(
+        "Upload limits: {} bytes per file, {} bytes per request, {} files per request, {:?} between uploads per client",
+        limits.max_file_size, limits.max_total_size, limits.max_files, limits.min_upload_interval
+    );
+
+    // Load TLS material before anything else so a bad cert fails fast
+    let tls = match (std::env::var_os("TLS_CERT"), std::env::var_os("TLS_KEY")) {
+        (Some(cert), Some(key)) => Some(
+            load_tls_config(Path::new(&cert), Path::new(&key)).map_err(std::io::Error::other)?,
+        ),
+        (None, None) => {
+            warn!("TLS_CERT and TLS_KEY are not set; serving plain HTTP");
+            None
+        }
+        _ => {
+            return Err(std::io::Error::other(
+                "TLS_CERT and TLS_KEY must be set together",
+            ));
+        }
+    };
+
+    let sort = parse_sort_strategy(std::env::var("SORT_STRATEGY").ok());
+    info!("Leaf order: {}", sort.name());
+    let compress_tree = parse_flag(std::env::var("COMPRESS_TREE").ok());
+    let dedup = parse_flag(std::env::var("DEDUP_FILES").ok());
+
+    let state = AppState::new(PathBuf::from(storage_dir), limits)
+        .with_sort(sort)
+        .with_compressed_tree(compress_tree)
+        .with_dedup(dedup);
+    if let Err(e) = reconcile_storage(&state).await {
+        warn!("Failed to reconcile storage at startup: {}", e);
+    }
+    if let Err(e) = load_cache(&state).await {
+        warn!("Failed to load tree cache at startup: {}", e);
+    }
+
+    // Off unless configured: each check rehashes every stored file
+    if let Ok(value) = std::env::var("INTEGRITY_CHECK_INTERVAL_SECS") {
+        let secs = parse_limit(
+            "INTEGRITY_CHECK_INTERVAL_SECS",
+            Some(value),
+            DEFAULT_INTEGRITY_CHECK_INTERVAL_SECS,
+        );
+        info!("Checking storage integrity every {}s", secs);
+        actix_web::rt::spawn(run_integrity_checks(
+            state.clone(),
+            Duration::from_secs(secs as u64),
+        ));
+    }
+
+    info!(
+        "Starting {} server on 0.0.0.0:{} storing files in {:?}",
+        if tls.is_some() { "HTTPS" } else { "HTTP" },
+        port,
+        state.storage_dir
+    );
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .app_data(web::Data::new(state.clone()))
+            .configure(routes)
+    });
+    match tls {
+        Some(config) => server.bind_rustls_0_23(("0.0.0.0", port), config)?,
+        None => server.bind(("0.0.0.0", port))?,
+    }
+    .run()
+    .await
+}
+
+/// Build a TLS config from a PEM certificate chain and a PEM private key.
+///
+/// Returns a message naming the offending file if either cannot be read or
+/// parsed, or if the key does not match the certificate.
+fn load_tls_config(cert_path: &Path, key_path: &Path) -> std::result::Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| format!("reading TLS certificate {:?}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {:?}", cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("reading TLS private key {:?}: {}", key_path, e))?;
+
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| {
+            format!(
+                "invalid TLS certificate {:?} or key {:?}: {}",
+                cert_path, key_path, e
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test};
+    use merkle::sha256;
+    use std::io::Read;
+
+    /// Write `files` into a fresh storage dir and return its state.
+    fn state_with_files(files: &[(&str, &[u8])]) -> (tempfile::TempDir, AppState) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, contents) in files {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let state = AppState::new(dir.path().to_path_buf(), Limits::default());
+        (dir, state)
+    }
+
+    /// Build a multipart/form-data body carrying `files`.
+    fn multipart_body(files: &[(&str, &[u8])]) -> (String, Vec<u8>) {
+        let boundary = "merkle-test-boundary";
+        let mut body = Vec::new();
+        for (name, contents) in files {
+            body.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"{name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        (format!("multipart/form-data; boundary={boundary}"), body)
+    }
+
+    /// The tree the server builds over `files`, given in sorted order.
+    fn named_tree(files: &[(&str, &[u8])]) -> MerkleTree {
+        let entries: Vec<(String, Vec<u8>)> = files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.to_vec()))
+            .collect();
+        MerkleTree::from_named_files(&entries).unwrap()
+    }
+
+    fn named_leaf(name: &str, contents: &[u8]) -> Vec<u8> {
+        TreeConfig::default().hash_named_leaf(name, contents)
+    }
+
+    fn upload_request(files: &[(&str, &[u8])]) -> test::TestRequest {
+        let (content_type, body) = multipart_body(files);
+        test::TestRequest::post()
+            .uri("/upload")
+            .insert_header(("content-type", content_type))
+            .set_payload(body)
+    }
+
+    #[actix_web::test]
+    async fn test_upload_replaces_storage() {
+        let (dir, state) = state_with_files(&[("old.txt", b"old")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files_count"], 2);
+
+        let tree = named_tree(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]);
+        assert_eq!(resp["root"], hex::encode(tree.root_hash_ref().unwrap()));
+
+        assert!(!dir.path().join("old.txt").exists());
+        assert!(!dir.path().join(STAGING_DIR).exists());
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["a.txt", "b.txt"]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            resp["root"].as_str().unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_concurrent_writes_do_not_clobber() {
+        let (dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let first = test::call_service(
+            &app,
+            upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request(),
+        );
+        let second = test::call_service(
+            &app,
+            upload_request(&[("c.txt", b"charlie")])
+                .uri("/append")
+                .to_request(),
+        );
+        let (first, second) = futures_util::future::join(first, second).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+
+        // The upload runs first, so the append lands on top of it
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["a.txt", "b.txt", "c.txt"]
+        );
+        let tree = named_tree(&[
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+            ("c.txt", b"charlie"),
+        ]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            hex::encode(tree.root_hash_ref().unwrap())
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_empty_upload_is_bad_request() {
+        let (dir, state) = state_with_files(&[("old.txt", b"old")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let resp = test::call_service(&app, upload_request(&[]).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(stored_file_names(dir.path()).unwrap(), vec!["old.txt"]);
+    }
+
+    #[actix_web::test]
+    async fn test_metadata_files_are_reserved() {
+        for name in [
+            "manifest.json",
+            "root.hex",
+            TREE_FILE,
+            TREE_FILE_ZST,
+            LEAVES_FILE,
+            STAGING_DIR,
+        ] {
+            assert!(RESERVED_NAMES.contains(&name), "{}", name);
+            assert!(sanitize_filename(name).is_err());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_merkle_error_status() {
+        let status = |e| merkle_error(e).as_response_error().status_code();
+        assert_eq!(status(MerkleError::LeafNotFound), StatusCode::NOT_FOUND);
+        assert_eq!(status(MerkleError::EmptyLeaves), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status(MerkleError::Io(std::io::Error::other("disk"))),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    /// Storage kept in memory, to check handlers only go through `Storage`.
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<std::collections::BTreeMap<String, Vec<u8>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Storage for MemoryStorage {
+        async fn list(&self) -> std::io::Result<Vec<String>> {
+            Ok(self.files.lock().unwrap().keys().cloned().collect())
+        }
+
+        async fn read(&self, name: &str) -> std::io::Result<Vec<u8>> {
+            self.files
+                .lock()
+                .unwrap()
+                .get(name)
+                .cloned()
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+
+        async fn write(&self, name: &str, contents: &[u8]) -> std::io::Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(name.to_string(), contents.to_vec());
+            Ok(())
+        }
+
+        async fn remove(&self, name: &str) -> std::io::Result<()> {
+            self.files.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_handlers_use_storage_backend() {
+        let (dir, state) = state_with_files(&[]);
+        let storage = Arc::new(MemoryStorage::default());
+        storage.write("old.txt", b"replaced").await.unwrap();
+        let state = AppState {
+            storage: storage.clone(),
+            ..state
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/root").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "no root yet");
+
+        let files: [(&str, &[u8]); 2] = [("a.txt", b"alpha"), ("b.txt", b"bravo")];
+        let upload: serde_json::Value =
+            test::call_and_read_body_json(&app, upload_request(&files).to_request()).await;
+        let root_hex = hex::encode(named_tree(&files).root_hash_ref().unwrap());
+        assert_eq!(upload["root"], root_hex);
+
+        // Files and metadata landed in the backend, not the storage dir
+        assert_eq!(
+            storage.list().await.unwrap(),
+            vec!["a.txt", "b.txt", "manifest.json", "root.hex", "tree.json"]
+        );
+        assert_eq!(storage.read("b.txt").await.unwrap(), b"bravo");
+        assert!(stored_file_names(dir.path()).unwrap().is_empty());
+        assert!(!dir.path().join(STAGING_DIR).exists());
+
+        let req = test::TestRequest::get().uri("/root").to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, root_hex);
+
+        let req = test::TestRequest::get().uri("/file/b.txt").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(resp["file_bytes"].as_str().unwrap())
+                .unwrap(),
+            b"bravo"
+        );
+        assert_eq!(resp["root"], root_hex);
+
+        let req = test::TestRequest::get().uri("/file/old.txt").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // With the cache gone, the tree is rebuilt from the backend alone
+        invalidate_cache(&state).unwrap();
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["root"], root_hex);
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(resp["file_bytes"].as_str().unwrap())
+                .unwrap(),
+            b"alpha"
+        );
+
+        let req = test::TestRequest::get().uri("/list").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files"], serde_json::json!(["a.txt", "b.txt"]));
+
+        let req = test::TestRequest::get().uri("/manifest").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp[1]["size"], 5);
+
+        let req = test::TestRequest::get()
+            .uri("/root?format=json")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["leaf_count"], 2);
+
+        let leaf_hex = hex::encode(named_leaf("b.txt", b"bravo"));
+        let req = test::TestRequest::get()
+            .uri(&format!("/proof/{}", leaf_hex))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["index"], 1);
+
+        // Appends read the existing files from the backend and add beside them
+        let req = upload_request(&[("c.txt", b"charlie")])
+            .uri("/append")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let grown: [(&str, &[u8]); 3] = [
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+            ("c.txt", b"charlie"),
+        ];
+        let root_hex = hex::encode(named_tree(&grown).root_hash_ref().unwrap());
+        assert_eq!(resp["root"], root_hex);
+        assert_eq!(resp["files_count"], 3);
+        assert_eq!(storage.read("c.txt").await.unwrap(), b"charlie");
+        assert_eq!(storage.read("root.hex").await.unwrap(), root_hex.as_bytes());
+        assert!(stored_file_names(dir.path()).unwrap().is_empty());
+        assert!(!dir.path().join(STAGING_DIR).exists());
+
+        // Startup checks run against the backend too
+        storage.write("manifest.json", b"[]").await.unwrap();
+        reconcile_storage(&state).await.unwrap();
+        assert_eq!(
+            storage.read("manifest.json").await.unwrap(),
+            br#"["a.txt","b.txt","c.txt"]"#
+        );
+        assert!(check_integrity(&state).await.unwrap());
+        storage.write("c.txt", b"charlid").await.unwrap();
+        assert!(!check_integrity(&state).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_root_formats() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/root?format=json")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp,
+            serde_json::json!({ "root": null, "leaf_count": 0, "height": 0, "algorithm": "sha256" })
+        );
+
+        let files: [(&str, &[u8]); 3] = [("a.txt", b"alpha"), ("b.txt", b"bravo"), ("c.txt", b"c")];
+        test::call_service(&app, upload_request(&files).to_request()).await;
+        let root_hex = hex::encode(named_tree(&files).root_hash_ref().unwrap());
+
+        for uri in ["/root", "/root?format=text"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            assert_eq!(test::call_and_read_body(&app, req).await, root_hex);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/root?format=json")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp,
+            serde_json::json!({ "root": root_hex, "leaf_count": 3, "height": 3, "algorithm": "sha256" })
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/root?format=xml")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_health_and_metrics() {
+        let (dir, state) = state_with_files(&[]);
+        fs::remove_dir(dir.path()).unwrap();
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let health: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            health,
+            serde_json::json!({ "status": "ok", "files": 0, "has_root": false })
+        );
+
+        let req = upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request();
+        let upload: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let health: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            health,
+            serde_json::json!({ "status": "ok", "files": 2, "has_root": true })
+        );
+
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        test::call_service(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": hex::encode(sha256(b"forged")),
+                "proof": [],
+                "root": upload["root"],
+            }))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(
+            resp.headers()
+                .get("content-type")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("# TYPE merkle_uploads_total counter\nmerkle_uploads_total 1\n"));
+        assert!(body.contains("\nmerkle_file_requests_total 1\n"));
+        assert!(body.contains("\nmerkle_verification_failures_total 1\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_append_to_existing_store() {
+        let (dir, state) = state_with_files(&[]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+        let req = upload_request(&[("b.txt", b"bravo"), ("d.txt", b"delta")]).to_request();
+        test::call_service(&app, req).await;
+
+        let req = upload_request(&[("c.txt", b"charlie"), ("a.txt", b"alpha")])
+            .uri("/append")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["added"], 2);
+        assert_eq!(resp["files_count"], 4);
+
+        let expected = named_tree(&[
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+            ("c.txt", b"charlie"),
+            ("d.txt", b"delta"),
+        ]);
+        let root_hex = hex::encode(expected.root_hash_ref().unwrap());
+        assert_eq!(resp["root"], root_hex);
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["a.txt", "b.txt", "c.txt", "d.txt"]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            root_hex
+        );
+        assert!(!dir.path().join(STAGING_DIR).exists());
+
+        // The cache holds the new tree and the persisted tree agrees with it
+        let cached = cached_tree(&state).await.unwrap();
+        assert_eq!(
+            cached.tree.root_hash_ref().unwrap(),
+            expected.root_hash_ref().unwrap()
+        );
+        assert!(load_persisted_tree(dir.path()).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_append_rejects_existing_name() {
+        let (dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let req = upload_request(&[("a.txt", b"alpha")]).to_request();
+        let upload: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = upload_request(&[("new.txt", b"new"), ("a.txt", b"replaced")])
+            .uri("/append")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+        assert_eq!(stored_file_names(dir.path()).unwrap(), vec!["a.txt"]);
+        assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            upload["root"].as_str().unwrap()
+        );
+        assert!(!dir.path().join(STAGING_DIR).exists());
+    }
+
+    #[actix_web::test]
+    async fn test_configured_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = Limits {
+            max_file_size: 10,
+            max_total_size: 25,
+            max_files: 3,
+            ..Limits::default()
+        };
+        let state = AppState::new(dir.path().to_path_buf(), limits);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let upload = |files: &[(&str, &[u8])]| upload_request(files).to_request();
+        let at_limit = [0u8; 10];
+        let over_limit = [0u8; 11];
+
+        let resp = test::call_service(&app, upload(&[("a.bin", &at_limit)])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, upload(&[("a.bin", &over_limit)])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains("max size of 10 bytes"));
+
+        // 3 x 8 = 24 bytes fits, 4 files or 26 bytes do not
+        let eight = [0u8; 8];
+        let resp =
+            test::call_service(&app, upload(&[("a", &eight), ("b", &eight), ("c", &eight)])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(
+            &app,
+            upload(&[("a", b"1"), ("b", b"2"), ("c", b"3"), ("d", b"4")]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = test::call_service(
+            &app,
+            upload(&[("a", &at_limit), ("b", &at_limit), ("c", &[0u8; 6])]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_upload_rate_limited_per_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = Limits {
+            min_upload_interval: Duration::from_secs(60),
+            ..Limits::default()
+        };
+        let state = AppState::new(dir.path().to_path_buf(), limits);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let client = "10.0.0.1:5000".parse().unwrap();
+        let upload = |peer| {
+            upload_request(&[("a.txt", b"alpha")])
+                .peer_addr(peer)
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, upload(client)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, upload(client)).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // Other clients are not affected
+        let resp = test::call_service(&app, upload("10.0.0.2:5000".parse().unwrap())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Appends share the same interval
+        let append = upload_request(&[("b.txt", b"bravo")])
+            .uri("/append")
+            .peer_addr(client)
+            .to_request();
+        let resp = test::call_service(&app, append).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[actix_web::test]
+    async fn test_upload_throttle_expires() {
+        let (_dir, state) = state_with_files(&[]);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+        let interval = state.limits.min_upload_interval;
+
+        assert_eq!(state.throttle_upload(ip, start).unwrap(), None);
+        assert!(state.throttle_upload(ip, start).unwrap().is_some());
+        assert_eq!(state.throttle_upload(ip, start + interval).unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn test_load_tls_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        fs::write(&cert_path, generated.cert.pem()).unwrap();
+        fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        assert!(load_tls_config(&cert_path, &key_path).is_ok());
+
+        let missing = dir.path().join("missing.pem");
+        let err = load_tls_config(&missing, &key_path).unwrap_err();
+        assert!(err.contains("reading TLS certificate"), "{}", err);
+
+        let garbage = dir.path().join("garbage.pem");
+        fs::write(&garbage, "not a pem file").unwrap();
+        let err = load_tls_config(&garbage, &key_path).unwrap_err();
+        assert!(err.contains("no certificates found"), "{}", err);
+        let err = load_tls_config(&cert_path, &garbage).unwrap_err();
+        assert!(err.contains("reading TLS private key"), "{}", err);
+
+        // A valid key that belongs to a different certificate
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let other_key = dir.path().join("other_key.pem");
+        fs::write(&other_key, other.key_pair.serialize_pem()).unwrap();
+        let err = load_tls_config(&cert_path, &other_key).unwrap_err();
+        assert!(err.contains("invalid TLS certificate"), "{}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_parse_limit() {
+        assert_eq!(parse_limit("MAX_FILES", None, 7), 7);
+        assert_eq!(parse_limit("MAX_FILES", Some("42".into()), 7), 42);
+        assert_eq!(parse_limit("MAX_FILES", Some("0".into()), 7), 7);
+        assert_eq!(parse_limit("MAX_FILES", Some("-3".into()), 7), 7);
+        assert_eq!(parse_limit("MAX_FILES", Some("lots".into()), 7), 7);
+    }
+
+    #[actix_web::test]
+    async fn test_numeric_sort_strategy() {
+        assert_eq!(parse_sort_strategy(None), SortStrategy::Lexicographic);
+        assert_eq!(
+            parse_sort_strategy(Some(" numeric ".into())),
+            SortStrategy::Numeric
+        );
+        assert_eq!(
+            parse_sort_strategy(Some("random".into())),
+            SortStrategy::Lexicographic
+        );
+
+        let (dir, state) = state_with_files(&[]);
+        let state = state.with_sort(SortStrategy::Numeric);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+
+        let files: [(&str, &[u8]); 3] = [("file10", b"ten"), ("file2", b"two"), ("file1", b"one")];
+        let resp: serde_json::Value =
+            test::call_and_read_body_json(&app, upload_request(&files).to_request()).await;
+        let natural = named_tree(&[files[2], files[1], files[0]]);
+        assert_eq!(resp["root"], hex::encode(natural.root_hash_ref().unwrap()));
+
+        let req = test::TestRequest::get().uri("/list").to_request();
+        let list: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            list["files"],
+            serde_json::json!(["file1", "file2", "file10"])
+        );
+
+        // The manifest is in leaf order, so a restart does not rebuild
+        fs::write(dir.path().join("root.hex"), "untouched").unwrap();
+        reconcile_storage(&state).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            "untouched"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_failed_upload_keeps_previous_files() {
+        let (dir, state) = state_with_files(&[("old1.txt", b"one"), ("old2.txt", b"two")]);
+        fs::write(dir.path().join("root.hex"), "previous-root").unwrap();
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        // The first file is written to staging before the oversized second one fails
+        let too_big = vec![0u8; DEFAULT_MAX_FILE_SIZE + 1];
+        let req = upload_request(&[("new.txt", b"new"), ("big.bin", &too_big)]).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["old1.txt", "old2.txt"]
+        );
+        assert_eq!(fs::read(dir.path().join("old1.txt")).unwrap(), b"one");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            "previous-root"
+        );
+        assert!(!dir.path().join("new.txt").exists());
+        assert!(!dir.path().join(STAGING_DIR).exists());
+    }
+
+    #[actix_web::test]
+    async fn test_upload_checksum_header() {
+        let (dir, state) = state_with_files(&[("old.txt", b"old")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        // Sent out of name order; the checksum covers "a.txt" then "b.txt"
+        let files: &[(&str, &[u8])] = &[("b.txt", b"bee"), ("a.txt", b"ay")];
+
+        // A checksum over other bytes fails before anything is replaced
+        let req = upload_request(files)
+            .insert_header((UPLOAD_SHA256_HEADER, hex::encode(sha256(b"beeay"))))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains("checksum mismatch"));
+        assert_eq!(stored_file_names(dir.path()).unwrap(), vec!["old.txt"]);
+        assert!(!dir.path().join(STAGING_DIR).exists());
+
+        let req = upload_request(files)
+            .insert_header((UPLOAD_SHA256_HEADER, "not hex"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = upload_request(files)
+            .insert_header((UPLOAD_SHA256_HEADER, hex::encode(sha256(b"aybee"))))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["a.txt", "b.txt"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_ws_pushes_new_root() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (_dir, state) = state_with_files(&[]);
+        let server_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(server_state.clone()))
+                .configure(routes)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // Plain WebSocket handshake over TCP
+        let mut socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let handshake = format!(
+            "GET /ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            addr
+        );
+        socket.write_all(handshake.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(socket.read_u8().await.unwrap());
+        }
+        assert!(response.starts_with(b"HTTP/1.1 101"));
+
+        // An upload handled with the same state is pushed to the socket
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let resp: serde_json::Value =
+            test::call_and_read_body_json(&app, upload_request(&[("a.txt", b"a")]).to_request())
+                .await;
+
+        // Server frames are unmasked: FIN + text opcode, then a short length
+        let mut header = [0u8; 2];
+        tokio::time::timeout(Duration::from_secs(5), socket.read_exact(&mut header))
+            .await
+            .expect("no root pushed")
+            .unwrap();
+        assert_eq!(header[0], 0x81);
+        let mut payload = vec![0; header[1] as usize];
+        socket.read_exact(&mut payload).await.unwrap();
+        assert_eq!(String::from_utf8(payload).unwrap(), resp["root"]);
+
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn test_upload_leaves_sidecar() {
+        let (dir, state) = state_with_files(&[("old.txt", b"old")]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+        let files: [(&str, &[u8]); 2] = [("a.txt", b"alpha"), ("b.txt", b"bravo")];
+        let tree = named_tree(&files);
+        let root_hex = hex::encode(tree.root_hash_ref().unwrap());
+
+        // Out of sorted order, so the second hash is wrong
+        let wrong = format!(
+            "{}\n{}\n",
+            hex::encode(named_leaf("b.txt", b"bravo")),
+            hex::encode(named_leaf("a.txt", b"alpha"))
+        );
+        let req =
+            upload_request(&[files[0], files[1], (LEAVES_FILE, wrong.as_bytes())]).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(stored_file_names(dir.path()).unwrap(), vec!["old.txt"]);
+
+        let leaves: Vec<String> = tree.get_leaves().iter().map(hex::encode).collect();
+        let sidecar = leaves.join("\n");
+        let req =
+            upload_request(&[files[0], files[1], (LEAVES_FILE, sidecar.as_bytes())]).to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files_count"], 2);
+        assert_eq!(resp["root"], root_hex);
+        assert_eq!(
+            stored_file_names(dir.path()).unwrap(),
+            vec!["a.txt", "b.txt"]
+        );
+
+        // Rebuilds take the leaves from the sidecar instead of the files
+        fs::write(dir.path().join("a.txt"), b"changed on disk").unwrap();
+        let rebuilt = build_tree(dir.path(), SortStrategy::default()).unwrap();
+        assert_eq!(hex::encode(rebuilt.tree.root_hash_ref().unwrap()), root_hex);
+
+        // ...but not once the manifest no longer matches the stored files
+        fs::write(dir.path().join("c.txt"), b"charlie").unwrap();
+        let rebuilt = build_tree(dir.path(), SortStrategy::default()).unwrap();
+        assert_eq!(rebuilt.entries, vec!["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(
+            rebuilt.tree.get_leaves()[0],
+            named_leaf("a.txt", b"changed on disk")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_persisted_tree_loaded_on_startup() {
+        let (dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let req = upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(dir.path().join(TREE_FILE).exists());
+
+        // Simulated restart: the tree comes from tree.json, not the files on disk
+        fs::write(dir.path().join("b.txt"), b"changed").unwrap();
+        let restarted = AppState::new(dir.path().to_path_buf(), Limits::default());
+        load_cache(&restarted).await.unwrap();
+        let cached = cached_tree(&restarted).await.unwrap();
+        assert_eq!(cached.entries, vec!["a.txt", "b.txt"]);
+        assert_eq!(
+            hex::encode(cached.tree.root_hash_ref().unwrap()),
+            resp["root"].as_str().unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_compressed_tree_reloaded() {
+        let (dir, state) = state_with_files(&[]);
+        let state = state.with_compressed_tree(true);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let req = upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(dir.path().join(TREE_FILE_ZST).exists());
+        assert!(!dir.path().join(TREE_FILE).exists());
+
+        let restarted = AppState::new(dir.path().to_path_buf(), Limits::default());
+        load_cache(&restarted).await.unwrap();
+        let cached = restarted.tree_cache.read().unwrap().clone().unwrap();
+        assert_eq!(
+            hex::encode(cached.tree.root_hash_ref().unwrap()),
+            resp["root"].as_str().unwrap()
+        );
+
+        // Turning compression off replaces the compressed file on next write
+        write_metadata(dir.path(), &cached, false).unwrap();
+        assert!(dir.path().join(TREE_FILE).exists());
+        assert!(!dir.path().join(TREE_FILE_ZST).exists());
+
+        assert!(parse_flag(Some("1".to_string())));
+        assert!(!parse_flag(Some("0".to_string())));
+        assert!(!parse_flag(None));
+    }
+
+    #[actix_web::test]
+    async fn test_offload_leaves_handler_thread() {
+        let handler_thread = std::thread::current().id();
+        let worker_thread = offload(|| Ok(std::thread::current().id())).await.unwrap();
+        assert_ne!(worker_thread, handler_thread);
+
+        // A cold cache is rebuilt through the same path
+        let (_dir, state) = state_with_files(&[("a.txt", b"alpha")]);
+        let cached = cached_tree(&state).await.unwrap();
+        assert_eq!(cached.entries, vec!["a.txt"]);
+        assert!(state.tree_cache.read().unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_dedup_shares_identical_contents() {
+        let (dir, state) = state_with_files(&[]);
+        let state = state.with_dedup(true);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let files: [(&str, &[u8]); 3] =
+            [("a.txt", b"same"), ("b.txt", b"same"), ("c.txt", b"other")];
+        let resp: serde_json::Value =
+            test::call_and_read_body_json(&app, upload_request(&files).to_request()).await;
+        assert_eq!(resp["files_count"], 3);
+        // One leaf per name, so the root is unaffected by dedup
+        assert_eq!(
+            resp["root"],
+            hex::encode(named_tree(&files).root_hash_ref().unwrap())
+        );
+
+        let req = upload_request(&[("d.txt", b"same")])
+            .uri("/append")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files_count"], 4);
+
+        let req = test::TestRequest::get().uri("/manifest").to_request();
+        let manifest: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let content_hashes: Vec<&str> = manifest
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["content_hash_hex"].as_str().unwrap())
+            .collect();
+        let same = hex::encode(sha256(b"same"));
+        assert_eq!(
+            content_hashes,
+            vec![
+                same.as_str(),
+                same.as_str(),
+                &hex::encode(sha256(b"other")),
+                same.as_str()
+            ]
+        );
+
+        for name in ["b.txt", "d.txt"] {
+            assert_eq!(fs::read(dir.path().join(name)).unwrap(), b"same");
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let inode = |name: &str| fs::metadata(dir.path().join(name)).unwrap().ino();
+                assert_eq!(inode(name), inode("a.txt"));
+                assert_ne!(inode("c.txt"), inode("a.txt"));
+            }
+        }
+        let req = test::TestRequest::get().uri("/file/d.txt").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_integrity_check_detects_corruption() {
+        let (dir, state) = state_with_files(&[]);
+        // Nothing stored yet counts as intact
+        assert!(check_integrity(&state).await.unwrap());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+        let req = upload_request(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(check_integrity(&state).await.unwrap());
+
+        // Same length, different contents
+        fs::write(dir.path().join("b.txt"), b"brava").unwrap();
+        assert!(!check_integrity(&state).await.unwrap());
+        assert!(!state.integrity_ok.load(Ordering::Relaxed));
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "unhealthy");
+
+        // Repairing the file clears the flag on the next check
+        fs::write(dir.path().join("b.txt"), b"bravo").unwrap();
+        assert!(check_integrity(&state).await.unwrap());
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_reconcile_storage_rebuilds_stale_manifest() {
+        let (dir, state) = state_with_files(&[("a.txt", b"alpha"), ("c.txt", b"charlie")]);
+        // Lists a file that is gone and misses one that is present
+        fs::write(dir.path().join("manifest.json"), r#"["a.txt","b.txt"]"#).unwrap();
+        fs::write(dir.path().join("root.hex"), hex::encode(sha256(b"stale"))).unwrap();
+
+        reconcile_storage(&state).await.unwrap();
+
+        let expected = named_tree(&[("a.txt", b"alpha"), ("c.txt", b"charlie")]);
+        let root_hex = hex::encode(expected.root_hash_ref().unwrap());
+        let manifest: Vec<String> =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("manifest.json")).unwrap())
+                .unwrap();
+        assert_eq!(manifest, vec!["a.txt", "c.txt"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            root_hex
+        );
+        assert_eq!(
+            hex::encode(
+                cached_tree(&state)
+                    .await
+                    .unwrap()
+                    .tree
+                    .root_hash_ref()
+                    .unwrap()
+            ),
+            root_hex
+        );
+        assert!(load_persisted_tree(dir.path()).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_reconcile_storage_consistent_or_emptied() {
+        let (dir, state) = state_with_files(&[("a.txt", b"alpha")]);
+        fs::write(dir.path().join("manifest.json"), r#"["a.txt"]"#).unwrap();
+        fs::write(dir.path().join("root.hex"), "untouched").unwrap();
+
+        // A matching manifest is trusted as-is
+        reconcile_storage(&state).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("root.hex")).unwrap(),
+            "untouched"
+        );
+        assert!(state.tree_cache.read().unwrap().is_none());
+
+        // With every file gone the metadata is removed
+        fs::remove_file(dir.path().join("a.txt")).unwrap();
+        reconcile_storage(&state).await.unwrap();
+        assert!(!dir.path().join("manifest.json").exists());
+        assert!(!dir.path().join("root.hex").exists());
+    }
+
+    #[actix_web::test]
+    async fn test_persisted_tree_mismatch_rebuilds() {
+        let (dir, state) = state_with_files(&[("a.txt", b"alpha")]);
+        let stale = MerkleTree::from_bytes_vec(&[b"stale".to_vec()]).unwrap();
+        fs::write(dir.path().join(TREE_FILE), stale.to_json().unwrap()).unwrap();
+        fs::write(dir.path().join("manifest.json"), r#"["a.txt"]"#).unwrap();
+        fs::write(dir.path().join("root.hex"), hex::encode(sha256(b"other"))).unwrap();
+
+        load_cache(&state).await.unwrap();
+        let cached = cached_tree(&state).await.unwrap();
+        assert_eq!(
+            cached.tree.root_hash_ref().unwrap(),
+            named_leaf("a.txt", b"alpha")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_uses_cached_tree() {
+        let (dir, state) = state_with_files(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]);
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .configure(routes),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        let first: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(state.tree_cache.read().unwrap().is_some());
+
+        // A rebuild would now produce a single-leaf tree with a different root
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        let second: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(first["root"], second["root"]);
+        assert_eq!(first["proof"], second["proof"]);
+
+        // Once the cache is dropped the tree is rebuilt from disk
+        invalidate_cache(&state).unwrap();
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        let third: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_ne!(first["root"], third["root"]);
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_etag() {
+        let (_dir, state) = state_with_files(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/file/a.txt").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp
+            .headers()
+            .get("etag")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            etag,
+            format!("\"{}\"", hex::encode(named_leaf("a.txt", b"alpha")))
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt")
+            .insert_header(("If-None-Match", etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get("etag").unwrap(), etag.as_str());
+        assert!(test::read_body(resp).await.is_empty());
+
+        // Another file's tag does not match
+        let req = test::TestRequest::get()
+            .uri("/file/b.txt")
+            .insert_header(("If-None-Match", etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_content_type() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let png: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x00\x00\x01";
+        let files: [(&str, &[u8]); 4] = [
+            ("image", png),
+            ("notes.txt", b"plain text"),
+            ("data.JSON", b"{}"),
+            ("blob.bin", b"\x00\x01\x02"),
+        ];
+        let resp = test::call_service(&app, upload_request(&files).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        for (name, expected) in [
+            ("image", "image/png"),
+            ("notes.txt", "text/plain"),
+            ("data.JSON", "application/json"),
+            ("blob.bin", "application/octet-stream"),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/file/{}", name))
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(resp["content_type"], expected, "{}", name);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_expected_root() {
+        let (_dir, state) = state_with_files(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let tree = named_tree(&[("a.txt", b"alpha"), ("b.txt", b"bravo")]);
+        let root_hex = hex::encode(tree.root_hash_ref().unwrap());
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/file/a.txt?expected_root={}", root_hex))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["file_name"], "a.txt");
+        assert_eq!(resp["root"], root_hex);
+
+        let stale_root = hex::encode(sha256(b"stale"));
+        let req = test::TestRequest::get()
+            .uri(&format!("/file/a.txt?expected_root={}", stale_root))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["expected_root"], stale_root);
+        assert_eq!(body["server_root"], root_hex);
+
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt?expected_root=not-hex")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_get_proof_by_leaf_hash() {
+        let files: [(&str, &[u8]); 3] = [("a.txt", b"alpha"), ("b.txt", b"bravo"), ("c.txt", b"c")];
+        let (_dir, state) = state_with_files(&files);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let tree = named_tree(&files);
+        let root = tree.root_hash_ref().unwrap();
+
+        let leaf = named_leaf("b.txt", b"bravo");
+        let req = test::TestRequest::get()
+            .uri(&format!("/proof/{}", hex::encode(&leaf)))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["index"], 1);
+        assert_eq!(resp["root"], hex::encode(root));
+        let proof: Vec<ProofNode> = serde_json::from_value(resp["proof"].clone()).unwrap();
+        assert!(MerkleTree::verify_proof(&leaf, &proof, root));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/proof/{}", hex::encode(sha256(b"unknown"))))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        for bad in ["not-hex", "abcd"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/proof/{}", bad))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", bad);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_gzip_roundtrip() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let text = "merkle ".repeat(10_000).into_bytes();
+        let req = upload_request(&[("big.txt", &text), ("small.txt", b"tiny")]).to_request();
+        let upload: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/file/big.txt")
+            .insert_header(("accept-encoding", "br, gzip;q=0.8"))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["compressed"], true);
+        assert_eq!(resp["root"], upload["root"]);
+
+        let gzipped = general_purpose::STANDARD
+            .decode(resp["file_bytes"].as_str().unwrap())
+            .unwrap();
+        assert!(gzipped.len() < text.len() / 10);
+        let mut bytes = Vec::new();
+        flate2::read::GzDecoder::new(&gzipped[..])
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, text);
+
+        let proof: Vec<ProofNode> = serde_json::from_value(resp["proof"].clone()).unwrap();
+        let root = hex::decode(resp["root"].as_str().unwrap()).unwrap();
+        assert!(MerkleTree::verify_proof(
+            &named_leaf("big.txt", &bytes),
+            &proof,
+            &root
+        ));
+
+        // Without the header the bytes are sent as-is
+        let req = test::TestRequest::get().uri("/file/big.txt").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["compressed"], false);
+    }
+
+    #[actix_web::test]
+    async fn test_get_file_byte_range() {
+        let (_dir, state) = state_with_files(&[("a.txt", b"hello world"), ("b.txt", b"bravo")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt")
+            .insert_header(("range", "bytes=2-5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let header = |name: &str| {
+            resp.headers()
+                .get(name)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(header("content-range"), "bytes 2-5/11");
+        let leaf_hash = hex::decode(header(LEAF_HASH_HEADER)).unwrap();
+        let root = hex::decode(header(ROOT_HEADER)).unwrap();
+        let proof =
+            MerkleTree::proof_from_bytes(&hex::decode(header(PROOF_HEADER)).unwrap()).unwrap();
+        assert_eq!(test::read_body(resp).await.as_ref(), b"llo ");
+
+        // The proof covers the whole file, not the slice
+        let expected = named_tree(&[("a.txt", b"hello world"), ("b.txt", b"bravo")]);
+        assert_eq!(leaf_hash, named_leaf("a.txt", b"hello world"));
+        assert_eq!(root, expected.root_hash_ref().unwrap());
+        assert!(MerkleTree::verify_proof(&leaf_hash, &proof, &root));
+
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt")
+            .insert_header(("range", "bytes=-3"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(test::read_body(resp).await.as_ref(), b"rld");
+
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt")
+            .insert_header(("range", "bytes=50-"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers().get("content-range").unwrap(), "bytes */11");
+
+        // Multi-range requests fall back to the full JSON response
+        let req = test::TestRequest::get()
+            .uri("/file/a.txt")
+            .insert_header(("range", "bytes=0-1,4-5"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_list_files_paginated() {
+        let (_dir, state) = state_with_files(&[
+            ("c.txt", b"charlie"),
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+        ]);
+        fs::write(state.storage_dir.join("root.hex"), "ignored").unwrap();
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/list").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            resp["files"],
+            serde_json::json!(["a.txt", "b.txt", "c.txt"])
+        );
+        assert_eq!(resp["count"], 3);
+        let tree = named_tree(&[
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+            ("c.txt", b"charlie"),
+        ]);
+        assert_eq!(resp["root"], hex::encode(tree.root_hash_ref().unwrap()));
+
+        let req = test::TestRequest::get()
+            .uri("/list?offset=1&limit=1")
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files"], serde_json::json!(["b.txt"]));
+        assert_eq!(resp["count"], 3);
+
+        let req = test::TestRequest::get().uri("/list?offset=10").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files"], serde_json::json!([]));
+
+        let req = test::TestRequest::get().uri("/list?limit=-1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_list_files_empty_store() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/list").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["files"], serde_json::json!([]));
+        assert_eq!(resp["root"], serde_json::Value::Null);
+        assert_eq!(resp["count"], 0);
+    }
+
+    #[actix_web::test]
+    async fn test_manifest_matches_uploaded_files() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/manifest").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp, serde_json::json!([]));
+
+        let files: [(&str, &[u8]); 3] = [
+            ("b.txt", b"bravo"),
+            ("a.txt", b"alpha"),
+            ("c.txt", b"charlie!"),
+        ];
+        let resp = test::call_service(&app, upload_request(&files).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/manifest").to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let manifest = resp.as_array().unwrap();
+        assert_eq!(manifest.len(), 3);
+        for (index, (name, contents)) in [files[1], files[0], files[2]].into_iter().enumerate() {
+            let entry = &manifest[index];
+            assert_eq!(entry["name"], name);
+            assert_eq!(entry["index"], index);
+            assert_eq!(entry["size"], contents.len());
+            // Leaves hash the name together with the contents
+            assert_eq!(
+                entry["leaf_hash_hex"],
+                hex::encode(named_leaf(name, contents))
+            );
+            assert!(entry.get("content_hash_hex").is_none());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_files_batch() {
+        let (_dir, state) = state_with_files(&[
+            ("a.txt", b"alpha"),
+            ("b.txt", b"bravo"),
+            ("c.txt", b"charlie"),
+        ]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/files")
+            .set_json(["c.txt", "a.txt"])
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let items = resp.as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["file_name"], "c.txt");
+        assert_eq!(items[1]["file_name"], "a.txt");
+        assert_eq!(items[0]["root"], items[1]["root"]);
+
+        let root = hex::decode(items[0]["root"].as_str().unwrap()).unwrap();
+        for (item, contents) in items.iter().zip([&b"charlie"[..], &b"alpha"[..]]) {
+            let bytes = general_purpose::STANDARD
+                .decode(item["file_bytes"].as_str().unwrap())
+                .unwrap();
+            assert_eq!(bytes, contents);
+            let leaf = named_leaf(item["file_name"].as_str().unwrap(), &bytes);
+            let proof: Vec<ProofNode> = serde_json::from_value(item["proof"].clone()).unwrap();
+            assert!(MerkleTree::verify_proof(&leaf, &proof, &root));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_get_files_rejects_invalid_or_missing_names() {
+        let (_dir, state) = state_with_files(&[("a.txt", b"alpha")]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/files")
+            .set_json(["a.txt", "../etc/passwd"])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/files")
+            .set_json(["a.txt", "missing.txt"])
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_verify_endpoint() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let files = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+        let tree = MerkleTree::from_bytes_vec(&files).unwrap();
+        let proof = tree.generate_proof(1).unwrap();
+        let root = hex::encode(tree.root_hash_ref().unwrap());
+
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": hex::encode(sha256(b"b")),
+                "proof": proof,
+                "root": root,
+            }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["valid"], true);
+
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": hex::encode(sha256(b"tampered")),
+                "proof": proof,
+                "root": root,
+            }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["valid"], false);
+    }
+
+    /// Log output written through a `tracing` subscriber, shared with the test.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_verify_logs_outcome() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        // The test runtime is single-threaded, so a thread-local default suffices
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+        let tree = MerkleTree::from_bytes_vec(&[b"a".to_vec(), b"b".to_vec()]).unwrap();
+        let root = tree.root_hash_ref().unwrap();
+        let tampered = sha256(b"tampered");
+
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": hex::encode(&tampered),
+                "proof": tree.generate_proof(1).unwrap(),
+                "root": hex::encode(root),
+            }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["valid"], false);
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.contains("Proof rejected"))
+            .expect("no log line for the rejected proof");
+        assert!(line.contains("WARN"));
+        assert!(line.contains("valid=false"));
+        assert!(line.contains(&format!("leaf_hash={}", hex::encode(&tampered[..8]))));
+        assert!(line.contains(&format!("root={}", hex::encode(&root[..8]))));
+    }
+
+    #[actix_web::test]
+    async fn test_verify_endpoint_rejects_malformed_input() {
+        let (_dir, state) = state_with_files(&[]);
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(state)).configure(routes)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": "not-hex",
+                "proof": [],
+                "root": "00",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains("invalid leaf_hash hex"));
+
+        let req = test::TestRequest::post()
+            .uri("/verify")
+            .set_json(serde_json::json!({
+                "leaf_hash": "00",
+                "proof": [{ "hash": "oops" }],
+                "root": "00",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains("invalid JSON body"));
+    }
 }
@@ -0,0 +1,242 @@
+// Storage backends for the server's files
+//
+// Handlers read and replace stored files through `Storage` rather than the
+// filesystem, so the files can live somewhere other than a local directory.
+// Uploads and appends are still received into a local staging directory first
+// and handed to the backend in one `replace_with_staged` or `add_staged` call
+// once they are complete.
+
+use async_trait::async_trait;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where stored files and their metadata (`root.hex`, `manifest.json`, ...) live.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Names of every stored file, metadata included, in sorted order.
+    async fn list(&self) -> io::Result<Vec<String>>;
+
+    /// Contents of `name`, failing with `ErrorKind::NotFound` if it is absent.
+    async fn read(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    /// A blocking reader over `name`, so large files can be hashed without
+    /// holding them in memory; read it on the blocking pool. The default
+    /// reads the whole file up front.
+    async fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(io::Cursor::new(self.read(name).await?)))
+    }
+
+    /// Size of `name` in bytes. The default reads the whole file.
+    async fn size(&self, name: &str) -> io::Result<u64> {
+        Ok(self.read(name).await?.len() as u64)
+    }
+
+    /// When `name` was last written, for the `modified-time` leaf order.
+    /// The default fails with `ErrorKind::Unsupported`.
+    async fn modified(&self, name: &str) -> io::Result<SystemTime> {
+        let _ = name;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "storage does not record modification times",
+        ))
+    }
+
+    /// Create or overwrite `name`.
+    async fn write(&self, name: &str, contents: &[u8]) -> io::Result<()>;
+
+    /// Remove `name`; removing a name that is absent is not an error.
+    async fn remove(&self, name: &str) -> io::Result<()>;
+
+    /// Replace all stored files with the files in the local `staging_dir`,
+    /// then remove it.
+    ///
+    /// The default writes each staged file over the stored one, metadata
+    /// last, and only then removes the stored names the upload no longer
+    /// has, so the old files stay readable until the new ones are in place.
+    /// Backends that can swap files in more cheaply should override it.
+    async fn replace_with_staged(&self, staging_dir: &Path) -> io::Result<()> {
+        let staged = staged_names(staging_dir)?;
+        self.add_staged(staging_dir).await?;
+        remove_unstaged(self, &staged).await
+    }
+
+    /// Add the files in the local `staging_dir` beside the stored ones,
+    /// overwriting files of the same name with metadata last, then remove it.
+    async fn add_staged(&self, staging_dir: &Path) -> io::Result<()> {
+        for name in staged_names(staging_dir)? {
+            self.write(&name, &fs::read(staging_dir.join(&name))?)
+                .await?;
+        }
+        fs::remove_dir_all(staging_dir)
+    }
+}
+
+/// Names of the files in `staging_dir`, data files before metadata so that
+/// `root.hex` and the manifest change only once the files they describe are
+/// in place.
+pub(crate) fn staged_names(staging_dir: &Path) -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(staging_dir)?
+        .filter_map(|res| res.ok())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+    names.sort_by_key(|name| {
+        (
+            merkle::RESERVED_NAMES.contains(&name.as_str()),
+            name == "root.hex",
+        )
+    });
+    Ok(names)
+}
+
+/// Remove every stored name that is not in `staged`.
+async fn remove_unstaged<S: Storage + ?Sized>(storage: &S, staged: &[String]) -> io::Result<()> {
+    for name in storage.list().await? {
+        if !staged.contains(&name) {
+            storage.remove(&name).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Files kept as regular files in a local directory.
+pub struct LocalFsStorage {
+    dir: PathBuf,
+}
+
+impl LocalFsStorage {
+    pub fn new(dir: PathBuf) -> Self {
+        LocalFsStorage { dir }
+    }
+}
+
+/// Run `work` on `path` on the blocking pool, so filesystem calls never
+/// stall the async executor.
+async fn blocking<T, F>(path: PathBuf, work: F) -> io::Result<T>
+where
+    F: FnOnce(PathBuf) -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    actix_web::web::block(move || work(path))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[async_trait]
+impl Storage for LocalFsStorage {
+    async fn list(&self) -> io::Result<Vec<String>> {
+        blocking(self.dir.clone(), |dir| {
+            if !dir.exists() {
+                return Ok(Vec::new());
+            }
+            let mut names: Vec<String> = fs::read_dir(&dir)?
+                .filter_map(|res| res.ok())
+                .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+                .filter_map(|e| e.file_name().into_string().ok())
+                .collect();
+            names.sort();
+            Ok(names)
+        })
+        .await
+    }
+
+    async fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        blocking(self.dir.join(name), fs::read).await
+    }
+
+    async fn open(&self, name: &str) -> io::Result<Box<dyn Read + Send>> {
+        blocking(self.dir.join(name), |path| {
+            Ok(Box::new(File::open(path)?) as Box<dyn Read + Send>)
+        })
+        .await
+    }
+
+    async fn size(&self, name: &str) -> io::Result<u64> {
+        blocking(self.dir.join(name), |path| Ok(fs::metadata(path)?.len())).await
+    }
+
+    async fn modified(&self, name: &str) -> io::Result<SystemTime> {
+        blocking(self.dir.join(name), |path| fs::metadata(path)?.modified()).await
+    }
+
+    /// Replaces the file rather than writing through it, so names that dedup
+    /// hard-linked together never change together.
+    async fn write(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let contents = contents.to_vec();
+        blocking(self.dir.join(name), move |path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            fs::write(path, contents)
+        })
+        .await
+    }
+
+    async fn remove(&self, name: &str) -> io::Result<()> {
+        blocking(self.dir.join(name), |path| match fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        })
+        .await
+    }
+
+    /// Each file is moved over the live one with `rename`, which is atomic on
+    /// the same filesystem. The storage directory itself is never renamed
+    /// since it is commonly a bind mount (see `docker-compose.yml`).
+    async fn add_staged(&self, staging_dir: &Path) -> io::Result<()> {
+        let staging_dir = staging_dir.to_path_buf();
+        blocking(self.dir.clone(), move |dir| {
+            crate::move_staged(&dir, &staging_dir)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_local_fs_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalFsStorage::new(dir.path().join("store"));
+        assert!(storage.list().await.unwrap().is_empty());
+        assert_eq!(
+            storage.read("a.txt").await.unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        storage.write("b.txt", b"bravo").await.unwrap();
+        storage.write("a.txt", b"alpha").await.unwrap();
+        assert_eq!(storage.list().await.unwrap(), vec!["a.txt", "b.txt"]);
+        assert_eq!(storage.read("a.txt").await.unwrap(), b"alpha");
+        let mut opened = Vec::new();
+        storage
+            .open("a.txt")
+            .await
+            .unwrap()
+            .read_to_end(&mut opened)
+            .unwrap();
+        assert_eq!(opened, b"alpha");
+        assert_eq!(storage.size("b.txt").await.unwrap(), 5);
+        assert!(storage.modified("a.txt").await.is_ok());
+
+        let staging = dir.path().join("staging");
+        fs::create_dir(&staging).unwrap();
+        fs::write(staging.join("b.txt"), b"bravo2").unwrap();
+        fs::write(staging.join("c.txt"), b"charlie").unwrap();
+        storage.replace_with_staged(&staging).await.unwrap();
+        assert_eq!(storage.list().await.unwrap(), vec!["b.txt", "c.txt"]);
+        assert_eq!(storage.read("b.txt").await.unwrap(), b"bravo2");
+        assert!(!staging.exists());
+
+        storage.remove("b.txt").await.unwrap();
+        storage.remove("b.txt").await.unwrap();
+        assert_eq!(storage.list().await.unwrap(), vec!["c.txt"]);
+    }
+}
@@ -0,0 +1,76 @@
+// Root change notifications over WebSocket
+//
+// Every successful upload or append publishes the new root on a broadcast
+// channel held in `AppState`. Each `/ws` connection subscribes to it when the
+// handshake is accepted and forwards every root as a text message, so clients
+// can react to changes instead of polling `/root`.
+
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web_actors::ws;
+use futures_util::stream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// Roots kept for a subscriber that has not caught up yet; older ones are
+/// skipped, which is harmless since only the latest root matters.
+pub const ROOT_UPDATES_CAPACITY: usize = 16;
+
+/// One connected client, sent the hex of every new root.
+pub struct RootSocket {
+    updates: Option<broadcast::Receiver<String>>,
+}
+
+impl RootSocket {
+    /// A socket forwarding the roots received on `updates`.
+    pub fn new(updates: broadcast::Receiver<String>) -> Self {
+        RootSocket {
+            updates: Some(updates),
+        }
+    }
+}
+
+impl Actor for RootSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let Some(updates) = self.updates.take() else {
+            return;
+        };
+        ctx.add_stream(stream::unfold(updates, |mut updates| async move {
+            loop {
+                match updates.recv().await {
+                    Ok(root) => return Some((root, updates)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket client fell behind; skipped {} roots", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }));
+    }
+}
+
+/// New roots from the broadcast channel.
+impl StreamHandler<String> for RootSocket {
+    fn handle(&mut self, root_hex: String, ctx: &mut Self::Context) {
+        ctx.text(root_hex);
+    }
+}
+
+/// Frames from the client; only control frames mean anything.
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RootSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("WebSocket protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}